@@ -0,0 +1,10 @@
+-- Access tokens revoked by logout, checked by jti until they would have expired.
+-- REVOKED ACCESS TOKENS (JWT denylist, rows are only needed until the token expires)
+CREATE TABLE revoked_tokens (
+    jti UUID NOT NULL PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    expires_at TIMESTAMPTZ NOT NULL,
+    revoked_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_revoked_tokens_expires_at ON revoked_tokens(expires_at);
@@ -0,0 +1,22 @@
+-- One row per sign-in, so a user can list and revoke their sessions.
+-- USER SESSIONS (one row per login, referenced from the access token `sid` claim)
+CREATE TABLE user_sessions (
+    id UUID NOT NULL PRIMARY KEY DEFAULT (uuid_generate_v4()),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    user_agent TEXT,
+    ip_address TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_seen_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    revoked_at TIMESTAMPTZ
+);
+
+CREATE INDEX idx_user_sessions_user_id ON user_sessions(user_id);
+
+-- Refresh tokens now belong to a session. Tokens issued before sessions existed
+-- cannot be attributed to one, so those users simply log in again.
+DELETE FROM refresh_tokens;
+
+ALTER TABLE refresh_tokens
+    ADD COLUMN session_id UUID NOT NULL REFERENCES user_sessions(id) ON DELETE CASCADE;
+
+CREATE INDEX idx_refresh_tokens_session_id ON refresh_tokens(session_id);
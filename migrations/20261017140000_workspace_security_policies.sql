@@ -0,0 +1,21 @@
+-- Security requirements a workspace sets for its members, such as two-factor sign-in.
+-- PER-WORKSPACE SECURITY POLICY
+CREATE TABLE workspace_security_policies (
+    workspace_id UUID NOT NULL PRIMARY KEY REFERENCES workspaces(id) ON DELETE CASCADE,
+    require_two_factor BOOLEAN NOT NULL DEFAULT FALSE,
+    max_session_age_minutes INTEGER CHECK (max_session_age_minutes > 0),
+    idle_timeout_minutes INTEGER CHECK (idle_timeout_minutes > 0),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+INSERT INTO permissions (id, name, description) VALUES
+    (gen_random_uuid(), 'manage_security_policy', 'Manage the workspace security policy');
+
+-- New workspaces grant every permission to Admin; backfill existing Admin roles.
+INSERT INTO role_permissions (role_id, permission_id)
+SELECT r.id, p.id
+FROM roles r
+    CROSS JOIN permissions p
+WHERE r.name = 'Admin' AND p.name = 'manage_security_policy'
+ON CONFLICT DO NOTHING;
@@ -0,0 +1,11 @@
+-- Emailed sign-in links and codes, stored hashed and used once.
+-- PASSWORDLESS SIGN-IN (one pending link/code per user, stored hashed)
+CREATE TABLE magic_links (
+    user_id UUID REFERENCES users(id) ON DELETE CASCADE,
+    token_hash TEXT UNIQUE NOT NULL,
+    code_hash TEXT NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    expires_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id)
+);
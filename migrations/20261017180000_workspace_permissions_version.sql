@@ -0,0 +1,45 @@
+-- Workspace tokens carry a snapshot of the member's role and permissions.
+-- Any change to role membership or role permissions bumps this version so
+-- tokens issued before the change stop being accepted.
+ALTER TABLE workspaces ADD COLUMN permissions_version BIGINT NOT NULL DEFAULT 1;
+
+CREATE OR REPLACE FUNCTION bump_workspace_permissions_version_for_member()
+RETURNS TRIGGER AS $$
+BEGIN
+    UPDATE workspaces
+    SET permissions_version = permissions_version + 1
+    WHERE id = OLD.workspace_id;
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER bump_permissions_version_on_member_change
+AFTER UPDATE OF role_id OR DELETE ON workspace_users
+FOR EACH ROW
+EXECUTE FUNCTION bump_workspace_permissions_version_for_member();
+
+CREATE OR REPLACE FUNCTION bump_workspace_permissions_version_for_role()
+RETURNS TRIGGER AS $$
+DECLARE
+    changed_role_id UUID;
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        changed_role_id := OLD.role_id;
+    ELSE
+        changed_role_id := NEW.role_id;
+    END IF;
+
+    UPDATE workspaces w
+    SET permissions_version = w.permissions_version + 1
+    FROM roles r
+    WHERE r.id = changed_role_id AND w.id = r.workspace_id;
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER bump_permissions_version_on_role_permission_change
+AFTER INSERT OR DELETE ON role_permissions
+FOR EACH ROW
+EXECUTE FUNCTION bump_workspace_permissions_version_for_role();
@@ -0,0 +1,21 @@
+-- FAILED LOGIN TRACKING
+-- One row per throttled key: a normalised email address or a client IP.
+CREATE TABLE login_attempts (
+    kind TEXT NOT NULL CHECK (kind IN ('email', 'ip')),
+    key TEXT NOT NULL,
+    failed_count INTEGER NOT NULL DEFAULT 0,
+    last_failed_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    locked_until TIMESTAMPTZ,
+    PRIMARY KEY (kind, key)
+);
+
+INSERT INTO permissions (id, name, description) VALUES
+    (gen_random_uuid(), 'unlock_members', 'Unlock members locked out after failed sign-ins');
+
+-- New workspaces grant every permission to Admin; backfill existing Admin roles.
+INSERT INTO role_permissions (role_id, permission_id)
+SELECT r.id, p.id
+FROM roles r
+    CROSS JOIN permissions p
+WHERE r.name = 'Admin' AND p.name = 'unlock_members'
+ON CONFLICT DO NOTHING;
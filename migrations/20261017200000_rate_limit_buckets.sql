@@ -0,0 +1,12 @@
+-- RATE LIMITING
+-- Token buckets shared by every instance when RATE_LIMIT_BACKEND=postgres.
+-- A bucket that has refilled completely is no different from a new one, so
+-- rows are dropped once they pass expires_at.
+CREATE TABLE rate_limit_buckets (
+    key TEXT PRIMARY KEY,
+    tokens DOUBLE PRECISION NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX idx_rate_limit_buckets_expires_at ON rate_limit_buckets(expires_at);
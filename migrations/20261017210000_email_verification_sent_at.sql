@@ -0,0 +1,3 @@
+-- When the current verification link was sent, for the resend cooldown.
+ALTER TABLE email_verifications
+    ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
@@ -0,0 +1,24 @@
+-- Emailed tokens are stored as hex SHA-256 hashes so that reading the database
+-- is not enough to use them. Existing UUID tokens are hashed in their text
+-- form, so links that were already sent keep working.
+ALTER TABLE email_verifications ADD COLUMN token_hash TEXT;
+UPDATE email_verifications SET token_hash = encode(sha256(token::text::bytea), 'hex');
+ALTER TABLE email_verifications
+    ALTER COLUMN token_hash SET NOT NULL,
+    ADD CONSTRAINT email_verifications_token_hash_key UNIQUE (token_hash),
+    DROP COLUMN token;
+
+ALTER TABLE password_resets ADD COLUMN token_hash TEXT;
+UPDATE password_resets SET token_hash = encode(sha256(token::text::bytea), 'hex');
+ALTER TABLE password_resets
+    ALTER COLUMN token_hash SET NOT NULL,
+    ADD CONSTRAINT password_resets_token_hash_key UNIQUE (token_hash),
+    DROP COLUMN token;
+
+ALTER TABLE users ADD COLUMN pending_email_token_hash TEXT;
+UPDATE users
+SET pending_email_token_hash = encode(sha256(pending_email_token::text::bytea), 'hex')
+WHERE pending_email_token IS NOT NULL;
+ALTER TABLE users DROP COLUMN pending_email_token;
+
+CREATE UNIQUE INDEX idx_users_pending_email_token_hash ON users(pending_email_token_hash);
@@ -0,0 +1,12 @@
+-- PASSWORD HISTORY
+-- Hashes a user has replaced, so recent passwords cannot be reused. Only the
+-- newest PASSWORD_HISTORY_SIZE - 1 are kept; the current password is the
+-- other one.
+CREATE TABLE password_history (
+    id UUID NOT NULL PRIMARY KEY DEFAULT (uuid_generate_v4()),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    password_hash TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_password_history_user_id ON password_history(user_id, created_at DESC);
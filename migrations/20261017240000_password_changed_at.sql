@@ -0,0 +1,4 @@
+-- PASSWORD CHANGES
+-- Access tokens issued before this time are rejected, so changing or
+-- resetting the password signs out every other holder of a token.
+ALTER TABLE users ADD COLUMN password_changed_at TIMESTAMPTZ;
@@ -0,0 +1,17 @@
+-- PERSONAL ACCESS TOKENS
+-- Long-lived credentials for scripts and integrations. Each token acts for
+-- its user in one workspace, limited to the listed permission names. Tokens
+-- start with wkpat_ so secret scanners can spot them; only a hash is stored.
+CREATE TABLE personal_access_tokens (
+    id UUID NOT NULL PRIMARY KEY DEFAULT (uuid_generate_v4()),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    workspace_id UUID NOT NULL REFERENCES workspaces(id) ON DELETE CASCADE,
+    name VARCHAR(64) NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    permissions TEXT[] NOT NULL,
+    expires_at TIMESTAMPTZ,
+    last_used_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_personal_access_tokens_user_id ON personal_access_tokens(user_id);
@@ -0,0 +1,32 @@
+-- SERVICE ACCOUNTS
+-- Non-human members of a single workspace. They are rows in users so they can
+-- hold a role through workspace_users, marked by the workspace they belong to.
+-- Their email is a placeholder and their password unusable: they sign in only
+-- with API keys, which start with wksa_ and are stored hashed.
+ALTER TABLE users
+ADD COLUMN service_account_workspace_id UUID REFERENCES workspaces(id) ON DELETE CASCADE;
+
+CREATE INDEX idx_users_service_account_workspace_id ON users(service_account_workspace_id)
+WHERE service_account_workspace_id IS NOT NULL;
+
+CREATE TABLE service_account_keys (
+    id UUID NOT NULL PRIMARY KEY DEFAULT (uuid_generate_v4()),
+    service_account_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    key_hash TEXT NOT NULL UNIQUE,
+    expires_at TIMESTAMPTZ,
+    last_used_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_service_account_keys_service_account_id ON service_account_keys(service_account_id);
+
+INSERT INTO permissions (id, name, description) VALUES
+    (gen_random_uuid(), 'manage_service_accounts', 'Manage workspace service accounts and their API keys');
+
+-- New workspaces grant every permission to Admin; backfill existing Admin roles.
+INSERT INTO role_permissions (role_id, permission_id)
+SELECT r.id, p.id
+FROM roles r
+    CROSS JOIN permissions p
+WHERE r.name = 'Admin' AND p.name = 'manage_service_accounts'
+ON CONFLICT DO NOTHING;
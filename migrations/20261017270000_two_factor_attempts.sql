@@ -0,0 +1,4 @@
+-- Wrong second factors are throttled per user alongside password failures.
+ALTER TABLE login_attempts DROP CONSTRAINT login_attempts_kind_check;
+ALTER TABLE login_attempts ADD CONSTRAINT login_attempts_kind_check
+    CHECK (kind IN ('email', 'ip', 'two_factor'));
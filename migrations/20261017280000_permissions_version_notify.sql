@@ -0,0 +1,42 @@
+-- Deleting a role cascades to its role_permissions rows only after the role
+-- itself is gone, so the role_permissions trigger can no longer find the
+-- workspace. Bump it from the role delete instead.
+CREATE OR REPLACE FUNCTION bump_workspace_permissions_version_for_deleted_role()
+RETURNS TRIGGER AS $$
+BEGIN
+    UPDATE workspaces
+    SET permissions_version = permissions_version + 1
+    WHERE id = OLD.workspace_id;
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER bump_permissions_version_on_role_delete
+AFTER DELETE ON roles
+FOR EACH ROW
+EXECUTE FUNCTION bump_workspace_permissions_version_for_deleted_role();
+
+-- Servers cache permissions versions and drop them when told here. The
+-- payload is "<workspace id>:<version>", with an empty version once the
+-- workspace is deleted.
+CREATE OR REPLACE FUNCTION notify_workspace_permissions_version()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        PERFORM pg_notify('workspace_permissions_version', OLD.id::text || ':');
+    ELSE
+        PERFORM pg_notify(
+            'workspace_permissions_version',
+            NEW.id::text || ':' || NEW.permissions_version::text
+        );
+    END IF;
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER notify_permissions_version_change
+AFTER UPDATE OF permissions_version OR DELETE ON workspaces
+FOR EACH ROW
+EXECUTE FUNCTION notify_workspace_permissions_version();
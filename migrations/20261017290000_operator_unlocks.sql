@@ -0,0 +1,15 @@
+-- Lockouts protect an account everywhere, so lifting them moves from a
+-- workspace permission to the operators listed in OPERATOR_EMAILS.
+DELETE FROM permissions WHERE name = 'unlock_members';
+UPDATE personal_access_tokens SET permissions = array_remove(permissions, 'unlock_members');
+
+-- LOGIN UNLOCKS
+-- Who lifted whose sign-in lockout, and when.
+CREATE TABLE login_unlocks (
+    id UUID NOT NULL PRIMARY KEY DEFAULT (uuid_generate_v4()),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    unlocked_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_login_unlocks_user_id ON login_unlocks(user_id);
@@ -1,23 +1,82 @@
 use std::env;
 
+use crate::config::rate_limit_config::RateLimitConfig;
+
+/// An OpenID Connect provider users can sign in with.
+#[derive(Debug, Clone)]
+pub struct OidcProviderConfig {
+    pub name: String,
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: String,
+    pub scopes: String,
+}
+
+/// What users who have not verified their email may do once the grace
+/// period after signup is over.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EmailVerificationPolicy {
+    Allow,
+    BlockWorkspaces,
+    BlockLogin,
+}
+
+/// Where rate limit buckets are kept. Postgres shares them between instances.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RateLimitBackend {
+    Memory,
+    Postgres,
+}
+
+/// Parses an optional environment variable, panicking on a value that does
+/// not parse so a typo fails at startup.
+pub fn env_parse<T: std::str::FromStr>(key: &str, default: T) -> T {
+    env::var(key)
+        .map(|v| {
+            v.parse()
+                .unwrap_or_else(|_| panic!("{} has an invalid value: {}", key, v))
+        })
+        .unwrap_or(default)
+}
+
 #[derive(Debug, Clone)]
 pub struct Config {
     pub database_url: String,
-    pub jwt_secret: String,
     pub jwt_maxage: i64,
+    pub refresh_token_maxage: i64,
+    pub workspace_token_maxage: i64,
     pub port: u16,
     pub backend_base_url: String,
     pub frontend_base_url: String,
+    pub trust_proxy_headers: bool,
+    pub rate_limit_backend: RateLimitBackend,
+    pub rate_limits: RateLimitConfig,
+    pub email_verification_policy: EmailVerificationPolicy,
+    pub email_verification_grace_hours: i64,
+    pub totp_issuer: String,
+    pub webauthn_rp_id: String,
+    pub webauthn_rp_name: String,
+    pub webauthn_origin: String,
+    pub oauth_providers: Vec<OidcProviderConfig>,
+    pub csrf_exempt_routes: Vec<String>,
+    pub operator_emails: Vec<String>,
+    pub hmac_secret: String,
+    pub metrics_enabled: bool,
 }
 
 impl Config {
     pub fn init() -> Self {
         let database_url = env::var("DATABASE_URL").expect("DATABASE_URL not set in env");
-        let jwt_secret = env::var("JWT_SECRET_KEY").expect("JWT_SECRET_KEY not set in env");
         let jwt_maxage = env::var("JWT_MAXAGE")
             .expect("JWT_MAXAGE not set in env")
             .parse()
             .expect("JWT MAXAGE must be a number");
+        let refresh_token_maxage = env::var("REFRESH_TOKEN_MAXAGE")
+            .map(|v| v.parse().expect("REFRESH_TOKEN_MAXAGE must be a number"))
+            .unwrap_or(30);
+        let workspace_token_maxage = env::var("WORKSPACE_TOKEN_MAXAGE")
+            .map(|v| v.parse().expect("WORKSPACE_TOKEN_MAXAGE must be a number"))
+            .unwrap_or(5);
         let port = env::var("PORT")
             .expect("PORT is not set in env")
             .parse()
@@ -26,14 +85,122 @@ impl Config {
             env::var("BACKEND_BASE_URL").expect("BACKEND_BASE_URL is not set in env");
         let frontend_base_url =
             env::var("FRONTEND_BASE_URL").expect("FRONTEND_BASE_URL is not set in env");
+        let trust_proxy_headers = env::var("TRUST_PROXY_HEADERS")
+            .map(|v| {
+                v.parse()
+                    .expect("TRUST_PROXY_HEADERS must be true or false")
+            })
+            .unwrap_or(false);
+        let rate_limit_backend = match env::var("RATE_LIMIT_BACKEND").as_deref() {
+            Err(_) | Ok("memory") => RateLimitBackend::Memory,
+            Ok("postgres") => RateLimitBackend::Postgres,
+            Ok(other) => panic!(
+                "RATE_LIMIT_BACKEND must be memory or postgres, got {}",
+                other
+            ),
+        };
+        let email_verification_policy = match env::var("EMAIL_VERIFICATION_POLICY").as_deref() {
+            Err(_) | Ok("allow") => EmailVerificationPolicy::Allow,
+            Ok("block_workspaces") => EmailVerificationPolicy::BlockWorkspaces,
+            Ok("block_login") => EmailVerificationPolicy::BlockLogin,
+            Ok(other) => panic!(
+                "EMAIL_VERIFICATION_POLICY must be allow, block_workspaces or block_login, got {}",
+                other
+            ),
+        };
+        let email_verification_grace_hours = env::var("EMAIL_VERIFICATION_GRACE_HOURS")
+            .map(|v| {
+                v.parse()
+                    .expect("EMAIL_VERIFICATION_GRACE_HOURS must be a number")
+            })
+            .unwrap_or(0);
+        let totp_issuer = env::var("TOTP_ISSUER").unwrap_or_else(|_| "Workspace Kit".to_string());
+        // Passkeys are scoped to the frontend's origin unless configured otherwise.
+        let frontend_url =
+            url::Url::parse(&frontend_base_url).expect("FRONTEND_BASE_URL must be a valid URL");
+        let webauthn_origin = env::var("WEBAUTHN_ORIGIN")
+            .unwrap_or_else(|_| frontend_url.origin().ascii_serialization());
+        let webauthn_rp_id = env::var("WEBAUTHN_RP_ID").unwrap_or_else(|_| {
+            frontend_url
+                .host_str()
+                .expect("FRONTEND_BASE_URL must have a host")
+                .to_string()
+        });
+        let webauthn_rp_name =
+            env::var("WEBAUTHN_RP_NAME").unwrap_or_else(|_| "Workspace Kit".to_string());
+        // OAUTH_PROVIDERS=google,okta reads OAUTH_GOOGLE_ISSUER, OAUTH_GOOGLE_CLIENT_ID, ...
+        let oauth_providers = env::var("OAUTH_PROVIDERS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(|name| {
+                let var = |key: &str| {
+                    let key = format!("OAUTH_{}_{}", name.to_uppercase(), key);
+                    env::var(&key).unwrap_or_else(|_| panic!("{} is not set in env", key))
+                };
+                OidcProviderConfig {
+                    name: name.to_lowercase(),
+                    issuer: var("ISSUER"),
+                    client_id: var("CLIENT_ID"),
+                    client_secret: var("CLIENT_SECRET"),
+                    scopes: env::var(format!("OAUTH_{}_SCOPES", name.to_uppercase()))
+                        .unwrap_or_else(|_| "openid email profile".to_string()),
+                }
+            })
+            .collect();
+        // Full paths such as /api/hooks/*; a trailing * matches a prefix.
+        let csrf_exempt_routes = env::var("CSRF_EXEMPT_ROUTES")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|route| !route.is_empty())
+            .map(String::from)
+            .collect();
+        // Accounts allowed to lift sign-in lockouts, matched like sign-in emails.
+        let operator_emails = env::var("OPERATOR_EMAILS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|email| email.trim().to_lowercase())
+            .filter(|email| !email.is_empty())
+            .collect();
+        // Keys the HMACs of short emailed codes, which a plain hash would not
+        // protect against brute force if the database leaked.
+        let hmac_secret = env::var("HMAC_SECRET").expect("HMAC_SECRET is not set in env");
+        if hmac_secret.len() < 32 {
+            panic!("HMAC_SECRET must be at least 32 characters");
+        }
+
+        let metrics_enabled = env_parse("METRICS_ENABLED", false);
 
         Config {
             database_url: database_url,
-            jwt_secret: jwt_secret,
             jwt_maxage: jwt_maxage,
+            refresh_token_maxage,
+            workspace_token_maxage,
             port: port,
             backend_base_url: backend_base_url,
             frontend_base_url: frontend_base_url,
+            trust_proxy_headers,
+            rate_limit_backend,
+            rate_limits: RateLimitConfig::init(),
+            email_verification_policy,
+            email_verification_grace_hours,
+            totp_issuer,
+            webauthn_rp_id,
+            webauthn_rp_name,
+            webauthn_origin,
+            oauth_providers,
+            csrf_exempt_routes,
+            operator_emails,
+            hmac_secret,
+            metrics_enabled,
         }
     }
+
+    pub fn oauth_provider(&self, name: &str) -> Option<&OidcProviderConfig> {
+        self.oauth_providers
+            .iter()
+            .find(|provider| provider.name == name)
+    }
 }
@@ -0,0 +1,207 @@
+use std::env;
+
+use axum::http::{HeaderMap, HeaderValue, header};
+use axum_extra::extract::{
+    CookieJar,
+    cookie::{Cookie, SameSite},
+};
+
+use crate::config::config::Config;
+
+/// The cookies this service sets.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SessionCookie {
+    AccessToken,
+    RefreshToken,
+    Workspace,
+    Csrf,
+    OAuthState,
+}
+
+/// Cookies cleared on logout.
+const SESSION_COOKIES: [SessionCookie; 4] = [
+    SessionCookie::AccessToken,
+    SessionCookie::RefreshToken,
+    SessionCookie::Workspace,
+    SessionCookie::Csrf,
+];
+
+const REFRESH_TOKEN_PATH: &str = "/api/auth";
+const OAUTH_STATE_PATH: &str = "/api/auth/oauth";
+const OAUTH_STATE_MAXAGE_MINUTES: i64 = 10;
+
+/// How every cookie is named, scoped and flagged.
+///
+/// `COOKIE_SECURE` defaults to whether `BACKEND_BASE_URL` uses https, and
+/// `COOKIE_SAME_SITE` to `lax`. With `COOKIE_HOST_PREFIX=true`, cookies scoped
+/// to `/` are named `__Host-<name>` and the others `__Secure-<name>`, which
+/// requires `COOKIE_SECURE` and no `COOKIE_DOMAIN`.
+#[derive(Debug, Clone)]
+pub struct CookieConfig {
+    pub domain: Option<String>,
+    pub secure: bool,
+    pub same_site: SameSite,
+    pub host_prefix: bool,
+    access_token_name: String,
+    refresh_token_name: String,
+    workspace_name: String,
+    csrf_name: String,
+    oauth_state_name: String,
+    access_token_maxage: time::Duration,
+    refresh_token_maxage: time::Duration,
+    workspace_maxage: time::Duration,
+    csrf_maxage: time::Duration,
+}
+
+fn env_minutes(key: &str, default: time::Duration) -> time::Duration {
+    env::var(key)
+        .map(|v| {
+            time::Duration::minutes(
+                v.parse()
+                    .unwrap_or_else(|_| panic!("{} must be a number of minutes", key)),
+            )
+        })
+        .unwrap_or(default)
+}
+
+impl CookieConfig {
+    pub fn init(config: &Config) -> Self {
+        let domain = env::var("COOKIE_DOMAIN")
+            .ok()
+            .filter(|domain| !domain.is_empty());
+        let secure = env::var("COOKIE_SECURE")
+            .map(|v| v.parse().expect("COOKIE_SECURE must be true or false"))
+            .unwrap_or_else(|_| config.backend_base_url.starts_with("https://"));
+        let same_site = match env::var("COOKIE_SAME_SITE").as_deref() {
+            Err(_) | Ok("lax") => SameSite::Lax,
+            Ok("strict") => SameSite::Strict,
+            Ok("none") => SameSite::None,
+            Ok(other) => panic!(
+                "COOKIE_SAME_SITE must be lax, strict or none, got {}",
+                other
+            ),
+        };
+        let host_prefix = env::var("COOKIE_HOST_PREFIX")
+            .map(|v| v.parse().expect("COOKIE_HOST_PREFIX must be true or false"))
+            .unwrap_or(false);
+
+        if same_site == SameSite::None && !secure {
+            panic!("COOKIE_SAME_SITE=none requires COOKIE_SECURE=true");
+        }
+        if host_prefix && (!secure || domain.is_some()) {
+            panic!("COOKIE_HOST_PREFIX requires COOKIE_SECURE=true and no COOKIE_DOMAIN");
+        }
+
+        let name = |key: &str, default: &str, path: &str| {
+            let name = env::var(key).unwrap_or_else(|_| default.to_string());
+            match (host_prefix, path) {
+                (false, _) => name,
+                (true, "/") => format!("__Host-{}", name),
+                (true, _) => format!("__Secure-{}", name),
+            }
+        };
+
+        let access_token_maxage = time::Duration::minutes(config.jwt_maxage);
+        let refresh_token_maxage = time::Duration::days(config.refresh_token_maxage);
+
+        CookieConfig {
+            access_token_name: name("COOKIE_ACCESS_TOKEN_NAME", "token", "/"),
+            refresh_token_name: name(
+                "COOKIE_REFRESH_TOKEN_NAME",
+                "refresh_token",
+                REFRESH_TOKEN_PATH,
+            ),
+            workspace_name: name("COOKIE_WORKSPACE_NAME", "workspace", "/"),
+            csrf_name: name("COOKIE_CSRF_NAME", "csrf_token", "/"),
+            oauth_state_name: name("COOKIE_OAUTH_STATE_NAME", "oauth_state", OAUTH_STATE_PATH),
+            access_token_maxage,
+            refresh_token_maxage,
+            workspace_maxage: env_minutes(
+                "COOKIE_WORKSPACE_MAXAGE",
+                time::Duration::hours(config.jwt_maxage),
+            ),
+            csrf_maxage: env_minutes("COOKIE_CSRF_MAXAGE", refresh_token_maxage),
+            domain,
+            secure,
+            same_site,
+            host_prefix,
+        }
+    }
+
+    pub fn name(&self, cookie: SessionCookie) -> &str {
+        match cookie {
+            SessionCookie::AccessToken => &self.access_token_name,
+            SessionCookie::RefreshToken => &self.refresh_token_name,
+            SessionCookie::Workspace => &self.workspace_name,
+            SessionCookie::Csrf => &self.csrf_name,
+            SessionCookie::OAuthState => &self.oauth_state_name,
+        }
+    }
+
+    pub fn max_age(&self, cookie: SessionCookie) -> time::Duration {
+        match cookie {
+            SessionCookie::AccessToken => self.access_token_maxage,
+            SessionCookie::RefreshToken => self.refresh_token_maxage,
+            SessionCookie::Workspace => self.workspace_maxage,
+            SessionCookie::Csrf => self.csrf_maxage,
+            SessionCookie::OAuthState => time::Duration::minutes(OAUTH_STATE_MAXAGE_MINUTES),
+        }
+    }
+
+    /// The cookie's value in the request, if the browser sent it.
+    pub fn get<'a>(&self, cookie_jar: &'a CookieJar, cookie: SessionCookie) -> Option<&'a str> {
+        cookie_jar.get(self.name(cookie)).map(|c| c.value())
+    }
+
+    pub fn build(&self, cookie: SessionCookie, value: impl Into<String>) -> Cookie<'static> {
+        let path = match cookie {
+            SessionCookie::RefreshToken => REFRESH_TOKEN_PATH,
+            SessionCookie::OAuthState => OAUTH_STATE_PATH,
+            _ => "/",
+        };
+        // The OAuth state has to come back on the provider's cross-site
+        // redirect, which `Strict` would block.
+        let same_site = match cookie {
+            SessionCookie::OAuthState if self.same_site == SameSite::Strict => SameSite::Lax,
+            _ => self.same_site,
+        };
+
+        let mut builder = Cookie::build((self.name(cookie).to_string(), value.into()))
+            .path(path)
+            .max_age(self.max_age(cookie))
+            .secure(self.secure)
+            .same_site(same_site)
+            // Scripts read the CSRF token to echo it in a header.
+            .http_only(cookie != SessionCookie::Csrf);
+        if let Some(domain) = &self.domain {
+            builder = builder.domain(domain.clone());
+        }
+        builder.build()
+    }
+
+    /// Appends a `Set-Cookie` header for the cookie.
+    pub fn set(&self, headers: &mut HeaderMap, cookie: SessionCookie, value: impl Into<String>) {
+        append(headers, self.build(cookie, value));
+    }
+
+    /// Appends a `Set-Cookie` header that deletes the cookie.
+    pub fn clear(&self, headers: &mut HeaderMap, cookie: SessionCookie) {
+        let mut removal = self.build(cookie, "");
+        removal.set_max_age(time::Duration::ZERO);
+        append(headers, removal);
+    }
+
+    /// Deletes every cookie of a signed-in session.
+    pub fn clear_session(&self, headers: &mut HeaderMap) {
+        for cookie in SESSION_COOKIES {
+            self.clear(headers, cookie);
+        }
+    }
+}
+
+fn append(headers: &mut HeaderMap, cookie: Cookie<'static>) {
+    headers.append(
+        header::SET_COOKIE,
+        HeaderValue::from_str(&cookie.to_string()).unwrap(),
+    );
+}
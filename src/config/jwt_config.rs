@@ -0,0 +1,252 @@
+use std::{env, fmt, fs};
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use jsonwebtoken::{
+    Algorithm, DecodingKey, EncodingKey,
+    jwk::{
+        AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm,
+        OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+    },
+};
+use rsa::{
+    RsaPrivateKey, RsaPublicKey,
+    pkcs1::{DecodeRsaPrivateKey, DecodeRsaPublicKey},
+    pkcs8::{DecodePrivateKey, DecodePublicKey},
+    traits::PublicKeyParts,
+};
+use sha2::{Digest, Sha256};
+
+/// A key that access tokens may be signed with. Only asymmetric keys have a
+/// `jwk`, which is what `/.well-known/jwks.json` publishes.
+#[derive(Clone)]
+pub struct VerificationKey {
+    pub kid: Option<String>,
+    pub algorithm: Algorithm,
+    pub key: DecodingKey,
+    pub jwk: Option<Jwk>,
+}
+
+/// Keys and registered claims for the JWTs this service issues.
+///
+/// `JWT_ALGORITHM` selects HS256 (default, signed with `JWT_SECRET_KEY`),
+/// RS256 or EdDSA (signed with the PEM key in `JWT_PRIVATE_KEY_FILE`).
+/// Public keys of retired signing keys listed in `JWT_VERIFICATION_KEY_FILES`
+/// keep verifying tokens until they expire.
+#[derive(Clone)]
+pub struct JwtConfig {
+    pub algorithm: Algorithm,
+    pub kid: Option<String>,
+    pub issuer: String,
+    pub audience: Option<String>,
+    encoding_key: EncodingKey,
+    verification_keys: Vec<VerificationKey>,
+}
+
+impl fmt::Debug for JwtConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JwtConfig")
+            .field("algorithm", &self.algorithm)
+            .field("kid", &self.kid)
+            .field("issuer", &self.issuer)
+            .field("audience", &self.audience)
+            .finish_non_exhaustive()
+    }
+}
+
+impl JwtConfig {
+    pub fn init() -> Self {
+        let algorithm = match env::var("JWT_ALGORITHM").as_deref() {
+            Err(_) | Ok("HS256") => Algorithm::HS256,
+            Ok("RS256") => Algorithm::RS256,
+            Ok("EdDSA") => Algorithm::EdDSA,
+            Ok(other) => panic!("JWT_ALGORITHM must be HS256, RS256 or EdDSA, got {}", other),
+        };
+        let issuer = env::var("JWT_ISSUER").unwrap_or_else(|_| {
+            env::var("BACKEND_BASE_URL").expect("BACKEND_BASE_URL is not set in env")
+        });
+        let audience = env::var("JWT_AUDIENCE").ok().filter(|aud| !aud.is_empty());
+
+        let (kid, encoding_key, signing_verification_key) = if algorithm == Algorithm::HS256 {
+            let jwt_secret = env::var("JWT_SECRET_KEY").expect("JWT_SECRET_KEY not set in env");
+            let key = VerificationKey {
+                kid: None,
+                algorithm,
+                key: DecodingKey::from_secret(jwt_secret.as_bytes()),
+                jwk: None,
+            };
+            (None, EncodingKey::from_secret(jwt_secret.as_bytes()), key)
+        } else {
+            let path = env::var("JWT_PRIVATE_KEY_FILE")
+                .expect("JWT_PRIVATE_KEY_FILE must be set when JWT_ALGORITHM is not HS256");
+            let pem = fs::read(&path).unwrap_or_else(|e| panic!("Failed to read {}: {}", path, e));
+            let (encoding_key, params) = load_private_key(algorithm, &pem)
+                .unwrap_or_else(|| panic!("{} is not a valid {:?} private key", path, algorithm));
+            let kid = env::var("JWT_KEY_ID").unwrap_or_else(|_| jwk_thumbprint(&params));
+            let key = verification_key(Some(kid.clone()), params);
+            (Some(kid), encoding_key, key)
+        };
+
+        // JWT_VERIFICATION_KEY_FILES=old.pem,2026-01=older.pem
+        let mut verification_keys = vec![signing_verification_key];
+        for entry in env::var("JWT_VERIFICATION_KEY_FILES")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+        {
+            let (kid, path) = match entry.split_once('=') {
+                Some((kid, path)) => (Some(kid.to_string()), path),
+                None => (None, entry),
+            };
+            let pem = fs::read(path).unwrap_or_else(|e| panic!("Failed to read {}: {}", path, e));
+            let params = load_public_key(&pem)
+                .unwrap_or_else(|| panic!("{} is not a valid RSA or Ed25519 public key", path));
+            let kid = kid.unwrap_or_else(|| jwk_thumbprint(&params));
+            verification_keys.push(verification_key(Some(kid), params));
+        }
+
+        JwtConfig {
+            algorithm,
+            kid,
+            issuer,
+            audience,
+            encoding_key,
+            verification_keys,
+        }
+    }
+
+    /// HS256 signing with `secret`, without reading the environment.
+    #[cfg(test)]
+    pub fn hs256(secret: &str, issuer: &str) -> Self {
+        JwtConfig {
+            algorithm: Algorithm::HS256,
+            kid: None,
+            issuer: issuer.to_string(),
+            audience: None,
+            encoding_key: EncodingKey::from_secret(secret.as_bytes()),
+            verification_keys: vec![VerificationKey {
+                kid: None,
+                algorithm: Algorithm::HS256,
+                key: DecodingKey::from_secret(secret.as_bytes()),
+                jwk: None,
+            }],
+        }
+    }
+
+    pub fn encoding_key(&self) -> &EncodingKey {
+        &self.encoding_key
+    }
+
+    /// The key a token with header `kid` and `alg` must verify against.
+    pub fn verification_key(
+        &self,
+        kid: Option<&str>,
+        algorithm: Algorithm,
+    ) -> Option<&VerificationKey> {
+        self.verification_keys
+            .iter()
+            .find(|key| key.kid.as_deref() == kid && key.algorithm == algorithm)
+    }
+
+    /// Public keys other services can verify our tokens with.
+    pub fn jwks(&self) -> JwkSet {
+        JwkSet {
+            keys: self
+                .verification_keys
+                .iter()
+                .filter_map(|key| key.jwk.clone())
+                .collect(),
+        }
+    }
+}
+
+fn load_private_key(
+    algorithm: Algorithm,
+    pem: &[u8],
+) -> Option<(EncodingKey, AlgorithmParameters)> {
+    let pem_str = std::str::from_utf8(pem).ok()?;
+    match algorithm {
+        Algorithm::RS256 => {
+            let private_key = RsaPrivateKey::from_pkcs8_pem(pem_str)
+                .or_else(|_| RsaPrivateKey::from_pkcs1_pem(pem_str))
+                .ok()?;
+            let encoding_key = EncodingKey::from_rsa_pem(pem).ok()?;
+            Some((encoding_key, rsa_parameters(&private_key.to_public_key())))
+        }
+        Algorithm::EdDSA => {
+            let signing_key = ed25519_dalek::SigningKey::from_pkcs8_pem(pem_str).ok()?;
+            let encoding_key = EncodingKey::from_ed_pem(pem).ok()?;
+            Some((
+                encoding_key,
+                ed25519_parameters(&signing_key.verifying_key()),
+            ))
+        }
+        _ => None,
+    }
+}
+
+fn load_public_key(pem: &[u8]) -> Option<AlgorithmParameters> {
+    let pem = std::str::from_utf8(pem).ok()?;
+    if let Ok(public_key) =
+        RsaPublicKey::from_public_key_pem(pem).or_else(|_| RsaPublicKey::from_pkcs1_pem(pem))
+    {
+        return Some(rsa_parameters(&public_key));
+    }
+    ed25519_dalek::VerifyingKey::from_public_key_pem(pem)
+        .ok()
+        .map(|public_key| ed25519_parameters(&public_key))
+}
+
+fn rsa_parameters(public_key: &RsaPublicKey) -> AlgorithmParameters {
+    AlgorithmParameters::RSA(RSAKeyParameters {
+        key_type: RSAKeyType::RSA,
+        n: URL_SAFE_NO_PAD.encode(public_key.n().to_bytes_be()),
+        e: URL_SAFE_NO_PAD.encode(public_key.e().to_bytes_be()),
+    })
+}
+
+fn ed25519_parameters(public_key: &ed25519_dalek::VerifyingKey) -> AlgorithmParameters {
+    AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+        key_type: OctetKeyPairType::OctetKeyPair,
+        curve: EllipticCurve::Ed25519,
+        x: URL_SAFE_NO_PAD.encode(public_key.as_bytes()),
+    })
+}
+
+/// RFC 7638 thumbprint, used as the `kid` when none is configured.
+fn jwk_thumbprint(params: &AlgorithmParameters) -> String {
+    let canonical = match params {
+        AlgorithmParameters::RSA(rsa) => {
+            format!(r#"{{"e":"{}","kty":"RSA","n":"{}"}}"#, rsa.e, rsa.n)
+        }
+        AlgorithmParameters::OctetKeyPair(okp) => {
+            format!(r#"{{"crv":"Ed25519","kty":"OKP","x":"{}"}}"#, okp.x)
+        }
+        _ => unreachable!("only RSA and Ed25519 keys are loaded"),
+    };
+    URL_SAFE_NO_PAD.encode(Sha256::digest(canonical.as_bytes()))
+}
+
+fn verification_key(kid: Option<String>, params: AlgorithmParameters) -> VerificationKey {
+    let (algorithm, key_algorithm) = match params {
+        AlgorithmParameters::RSA(_) => (Algorithm::RS256, KeyAlgorithm::RS256),
+        _ => (Algorithm::EdDSA, KeyAlgorithm::EdDSA),
+    };
+    let jwk = Jwk {
+        common: CommonParameters {
+            public_key_use: Some(PublicKeyUse::Signature),
+            key_algorithm: Some(key_algorithm),
+            key_id: kid.clone(),
+            ..Default::default()
+        },
+        algorithm: params,
+    };
+    let key = DecodingKey::from_jwk(&jwk).expect("JWK built from a valid public key");
+
+    VerificationKey {
+        kid,
+        algorithm,
+        key,
+        jwk: Some(jwk),
+    }
+}
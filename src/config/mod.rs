@@ -1,2 +1,6 @@
 pub mod config;
+pub mod cookie_config;
+pub mod jwt_config;
 pub mod mail_config;
+pub mod password_policy;
+pub mod rate_limit_config;
@@ -0,0 +1,206 @@
+use std::{collections::HashSet, env, fmt, fs, sync::Arc};
+
+use crate::{config::config::env_parse, utils::password_strength};
+
+/// Rules every new password must satisfy.
+///
+/// By default a password needs an uppercase letter, a lowercase letter, a
+/// digit and a symbol. Setting `PASSWORD_MIN_STRENGTH` (0-4) replaces these
+/// class rules with an estimated strength score, which favours long
+/// passphrases. Passwords in `PASSWORD_BANNED_LIST_FILE` (one per line) are
+/// always rejected, along with a built-in list of common passwords, as are
+/// the user's last `PASSWORD_HISTORY_SIZE` passwords. Both lists also serve as
+/// the dictionary for the strength score.
+#[derive(Clone)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub max_length: usize,
+    pub require_uppercase: bool,
+    pub require_lowercase: bool,
+    pub require_digit: bool,
+    pub require_symbol: bool,
+    pub min_strength: Option<u8>,
+    pub history_size: usize,
+    banned_passwords: Arc<HashSet<String>>,
+}
+
+impl fmt::Debug for PasswordPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PasswordPolicy")
+            .field("min_length", &self.min_length)
+            .field("max_length", &self.max_length)
+            .field("require_uppercase", &self.require_uppercase)
+            .field("require_lowercase", &self.require_lowercase)
+            .field("require_digit", &self.require_digit)
+            .field("require_symbol", &self.require_symbol)
+            .field("min_strength", &self.min_strength)
+            .field("history_size", &self.history_size)
+            .field("banned_passwords", &self.banned_passwords.len())
+            .finish()
+    }
+}
+
+/// Frequently used passwords and the words they are built from.
+const COMMON_PASSWORDS: &str = include_str!("common_passwords.txt");
+
+impl PasswordPolicy {
+    pub fn init() -> Self {
+        let min_length = env_parse("PASSWORD_MIN_LENGTH", 8);
+        let max_length = env_parse("PASSWORD_MAX_LENGTH", 128);
+        if min_length == 0 || min_length > max_length {
+            panic!("PASSWORD_MIN_LENGTH must be between 1 and PASSWORD_MAX_LENGTH");
+        }
+
+        let min_strength = env::var("PASSWORD_MIN_STRENGTH").ok().map(|v| {
+            v.parse()
+                .ok()
+                .filter(|score| *score <= password_strength::MAX_SCORE)
+                .expect("PASSWORD_MIN_STRENGTH must be a score from 0 to 4")
+        });
+
+        let banned_list = match env::var("PASSWORD_BANNED_LIST_FILE") {
+            Ok(path) => fs::read_to_string(&path)
+                .unwrap_or_else(|e| panic!("Failed to read {}: {}", path, e)),
+            Err(_) => String::new(),
+        };
+        let banned_passwords = COMMON_PASSWORDS
+            .lines()
+            .chain(banned_list.lines())
+            .map(|line| line.trim().to_lowercase())
+            .filter(|line| !line.is_empty())
+            .collect();
+
+        PasswordPolicy {
+            min_length,
+            max_length,
+            require_uppercase: env_parse("PASSWORD_REQUIRE_UPPERCASE", true),
+            require_lowercase: env_parse("PASSWORD_REQUIRE_LOWERCASE", true),
+            require_digit: env_parse("PASSWORD_REQUIRE_DIGIT", true),
+            require_symbol: env_parse("PASSWORD_REQUIRE_SYMBOL", true),
+            min_strength,
+            history_size: env_parse("PASSWORD_HISTORY_SIZE", 5),
+            banned_passwords: Arc::new(banned_passwords),
+        }
+    }
+
+    /// Checks a new password, returning a message for the user if it is not
+    /// allowed. `user_inputs` such as the account's name and email count
+    /// against the strength score.
+    pub fn check(&self, password: &str, user_inputs: &[&str]) -> Result<(), String> {
+        let length = password.chars().count();
+        if length < self.min_length || length > self.max_length {
+            return Err(format!(
+                "Password must be between {} and {} characters",
+                self.min_length, self.max_length
+            ));
+        }
+
+        if self
+            .banned_passwords
+            .contains(&password.trim().to_lowercase())
+        {
+            return Err("This password is too common. Please choose another".to_string());
+        }
+
+        if let Some(min_strength) = self.min_strength {
+            let score = password_strength::score(password, user_inputs, &self.banned_passwords);
+            if score < min_strength {
+                return Err(
+                    "Password is too easy to guess. Try a longer passphrase of unrelated words"
+                        .to_string(),
+                );
+            }
+            return Ok(());
+        }
+
+        let rules = [
+            (
+                self.require_uppercase,
+                password.chars().any(|c| c.is_uppercase()),
+                "Password must contain at least one uppercase letter",
+            ),
+            (
+                self.require_lowercase,
+                password.chars().any(|c| c.is_lowercase()),
+                "Password must contain at least one lowercase letter",
+            ),
+            (
+                self.require_digit,
+                password.chars().any(|c| c.is_ascii_digit()),
+                "Password must contain at least one number",
+            ),
+            (
+                self.require_symbol,
+                password.chars().any(|c| !c.is_alphanumeric()),
+                "Password must contain at least one special character",
+            ),
+        ];
+        for (required, satisfied, message) in rules {
+            if required && !satisfied {
+                return Err(message.to_string());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::password_strength::MAX_SCORE;
+
+    fn policy(min_strength: Option<u8>) -> PasswordPolicy {
+        PasswordPolicy {
+            min_length: 8,
+            max_length: 64,
+            require_uppercase: true,
+            require_lowercase: true,
+            require_digit: true,
+            require_symbol: true,
+            min_strength,
+            history_size: 5,
+            banned_passwords: Arc::new(
+                COMMON_PASSWORDS
+                    .lines()
+                    .map(|line| line.trim().to_lowercase())
+                    .filter(|line| !line.is_empty())
+                    .collect(),
+            ),
+        }
+    }
+
+    #[test]
+    fn class_rules_apply_without_a_strength_score() {
+        let policy = policy(None);
+
+        assert!(policy.check("Sh0rt!", &[]).is_err());
+        assert!(policy.check(&"Aa1!".repeat(20), &[]).is_err());
+        assert!(policy.check("alllowercase1!", &[]).is_err());
+        assert!(policy.check("ALLUPPERCASE1!", &[]).is_err());
+        assert!(policy.check("NoDigitsHere!", &[]).is_err());
+        assert!(policy.check("NoSymbols123", &[]).is_err());
+        assert!(policy.check("Quiet-Otter-42", &[]).is_ok());
+    }
+
+    #[test]
+    fn common_passwords_are_banned_in_any_case() {
+        let too_common = Err("This password is too common. Please choose another".to_string());
+
+        assert_eq!(policy(None).check("PassWord123", &[]), too_common);
+        assert_eq!(policy(Some(0)).check("password", &[]), too_common);
+    }
+
+    #[test]
+    fn strength_score_replaces_the_class_rules() {
+        let policy = policy(Some(MAX_SCORE));
+
+        assert!(policy.check("plum gadget oxide tundra", &[]).is_ok());
+        assert!(policy.check("Password2024!", &[]).is_err());
+        assert!(
+            policy
+                .check("MorrisonJanet!", &["Janet Morrison", "janet@example.com"])
+                .is_err()
+        );
+    }
+}
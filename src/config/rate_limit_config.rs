@@ -0,0 +1,49 @@
+use std::time::Duration;
+
+use crate::{config::config::env_parse, utils::rate_limit::BucketPolicy};
+
+/// Token buckets for the public auth endpoints, from
+/// `RATE_LIMIT_<ROUTE>_PER_IP` and `RATE_LIMIT_<ROUTE>_PER_EMAIL`. Each is
+/// written `<capacity>/<seconds>`: a burst of `capacity` requests, then one
+/// more every `seconds`.
+#[derive(Debug, Clone)]
+pub struct RateLimitConfig {
+    pub register_per_ip: BucketPolicy,
+    pub register_per_email: BucketPolicy,
+    pub forgot_password_per_ip: BucketPolicy,
+    pub forgot_password_per_email: BucketPolicy,
+    pub resend_verification_per_ip: BucketPolicy,
+    pub resend_verification_per_email: BucketPolicy,
+    pub magic_link_per_ip: BucketPolicy,
+    pub magic_link_per_email: BucketPolicy,
+    pub reset_password_per_ip: BucketPolicy,
+    pub verify_per_ip: BucketPolicy,
+}
+
+fn bucket(key: &str, capacity: u32, refill_seconds: u64) -> BucketPolicy {
+    env_parse(
+        key,
+        BucketPolicy::new(capacity, Duration::from_secs(refill_seconds)),
+    )
+}
+
+impl RateLimitConfig {
+    pub fn init() -> Self {
+        RateLimitConfig {
+            register_per_ip: bucket("RATE_LIMIT_REGISTER_PER_IP", 10, 360),
+            register_per_email: bucket("RATE_LIMIT_REGISTER_PER_EMAIL", 3, 1200),
+            forgot_password_per_ip: bucket("RATE_LIMIT_FORGOT_PASSWORD_PER_IP", 10, 360),
+            forgot_password_per_email: bucket("RATE_LIMIT_FORGOT_PASSWORD_PER_EMAIL", 3, 1200),
+            resend_verification_per_ip: bucket("RATE_LIMIT_RESEND_VERIFICATION_PER_IP", 10, 360),
+            resend_verification_per_email: bucket(
+                "RATE_LIMIT_RESEND_VERIFICATION_PER_EMAIL",
+                3,
+                1200,
+            ),
+            magic_link_per_ip: bucket("RATE_LIMIT_MAGIC_LINK_PER_IP", 10, 360),
+            magic_link_per_email: bucket("RATE_LIMIT_MAGIC_LINK_PER_EMAIL", 5, 300),
+            reset_password_per_ip: bucket("RATE_LIMIT_RESET_PASSWORD_PER_IP", 10, 60),
+            verify_per_ip: bucket("RATE_LIMIT_VERIFY_PER_IP", 20, 30),
+        }
+    }
+}
@@ -9,8 +9,10 @@ pub mod permissions {
     pub const VIEW_PERMISSIONS: &str = "view_permissions";
     pub const REMOVE_MEMBERS: &str = "remove_members";
     pub const ASSIGN_ROLES_TO_MEMBERS: &str = "assign_roles_to_members";
+    pub const MANAGE_SECURITY_POLICY: &str = "manage_security_policy";
+    pub const MANAGE_SERVICE_ACCOUNTS: &str = "manage_service_accounts";
 
-    pub const ALL: [&str; 10] = [
+    pub const ALL: [&str; 12] = [
         UPDATE_WORKSPACE,
         DELETE_WORKSPACE,
         MANAGE_ROLES,
@@ -21,5 +23,7 @@ pub mod permissions {
         VIEW_PERMISSIONS,
         REMOVE_MEMBERS,
         ASSIGN_ROLES_TO_MEMBERS,
+        MANAGE_SECURITY_POLICY,
+        MANAGE_SERVICE_ACCOUNTS,
     ];
 }
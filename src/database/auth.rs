@@ -1,10 +1,10 @@
 use crate::{
     database::DBClient,
-    models::{EmailVerification, PasswordReset, User},
+    models::{EmailVerification, MagicLink, PasswordReset, User},
 };
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
-use sqlx::{self, Error};
+use sqlx::{self, Error, PgConnection, QueryBuilder};
 use uuid::Uuid;
 
 #[async_trait]
@@ -21,26 +21,78 @@ pub trait AuthExt {
         name: T,
         email: T,
         password: T,
-        verfification_token: Uuid,
+        verification_token_hash: &str,
         token_expires_at: DateTime<Utc>,
     ) -> Result<User, Error>;
 
-    async fn get_user_id_by_token(&self, token: Uuid) -> Result<Option<EmailVerification>, Error>;
+    async fn take_email_verification(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<EmailVerification>, Error>;
 
     async fn verify_user(&self, user_id: Uuid) -> Result<(), Error>;
 
+    async fn rotate_email_verification_token(
+        &self,
+        user_id: Uuid,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+        cooldown_seconds: i64,
+    ) -> Result<Option<DateTime<Utc>>, Error>;
+
     async fn save_password_reset_token(
         &self,
         user_id: Uuid,
-        token: Uuid,
+        token_hash: &str,
         expires_at: DateTime<Utc>,
     ) -> Result<(), Error>;
 
-    async fn get_password_reset_token(&self, token: Uuid) -> Result<Option<PasswordReset>, Error>;
+    async fn get_password_reset_token(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<PasswordReset>, Error>;
+
+    async fn reset_password(
+        &self,
+        token_hash: &str,
+        user_id: Uuid,
+        new_password: &str,
+        history_size: usize,
+    ) -> Result<bool, Error>;
+
+    async fn update_user_password(
+        &self,
+        user_id: Uuid,
+        new_password: &str,
+        history_size: usize,
+    ) -> Result<(), Error>;
+
+    async fn get_password_history(&self, user_id: Uuid, limit: usize)
+    -> Result<Vec<String>, Error>;
+
+    async fn rehash_user_password(
+        &self,
+        user_id: Uuid,
+        old_hash: &str,
+        new_hash: &str,
+    ) -> Result<(), Error>;
+
+    async fn save_magic_link(
+        &self,
+        user_id: Uuid,
+        token_hash: &str,
+        code_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), Error>;
 
-    async fn reset_password(&self, user_id: Uuid, new_password: &str) -> Result<(), Error>;
+    async fn take_magic_link_by_token(&self, token_hash: &str) -> Result<Option<MagicLink>, Error>;
 
-    async fn update_user_password(&self, user_id: Uuid, new_password: &str) -> Result<(), Error>;
+    async fn take_magic_link_by_code(
+        &self,
+        user_id: Uuid,
+        code_hash: &str,
+        max_attempts: i32,
+    ) -> Result<Option<MagicLink>, Error>;
 }
 
 #[async_trait]
@@ -51,21 +103,23 @@ impl AuthExt for DBClient {
         name: Option<&str>,
         email: Option<&str>,
     ) -> Result<Option<User>, Error> {
-        let mut query = String::from("SELECT * FROM users WHERE 1 = 1");
-        if let Some(_id) = user_id {
-            query.push_str(" AND id = $1");
+        let mut builder = QueryBuilder::new("SELECT * FROM users WHERE 1 = 1");
+        if let Some(id) = user_id {
+            builder.push(" AND id = ").push_bind(id);
         }
-        if let Some(_n) = name {
-            query.push_str("AND name = $2");
+        if let Some(n) = name {
+            builder.push(" AND name = ").push_bind(n);
         }
-        if let Some(_e) = email {
-            query.push_str("AND email = $3");
+        if let Some(e) = email {
+            // Service account emails are placeholders nobody can sign in with.
+            builder
+                .push(" AND email = ")
+                .push_bind(e)
+                .push(" AND service_account_workspace_id IS NULL");
         }
 
-        let rows = sqlx::query_as::<_, User>(&query)
-            .bind(user_id)
-            .bind(name)
-            .bind(email)
+        let rows = builder
+            .build_query_as::<User>()
             .fetch_optional(&self.pool)
             .await?;
         Ok(rows)
@@ -76,7 +130,7 @@ impl AuthExt for DBClient {
         name: T,
         email: T,
         password: T,
-        verification_token: Uuid,
+        verification_token_hash: &str,
         token_expires_at: DateTime<Utc>,
     ) -> Result<User, Error> {
         let name = name.into();
@@ -90,7 +144,7 @@ impl AuthExt for DBClient {
             r#"
             INSERT INTO users (name, email, password) 
             VALUES ($1, $2, $3)
-            RETURNING id, name, email, password, pending_email, email_verified, pending_email_expires_at, pending_email_token, created_at, updated_at
+            RETURNING id, name, email, password, pending_email, email_verified, pending_email_expires_at, pending_email_token_hash, password_changed_at, service_account_workspace_id, created_at, updated_at
             "#,
             name,
             email,
@@ -101,11 +155,11 @@ impl AuthExt for DBClient {
 
         sqlx::query!(
             r#"
-            INSERT INTO email_verifications (user_id, token, expires_at)
-            VALUES ($1, $2::UUID, $3)
+            INSERT INTO email_verifications (user_id, token_hash, expires_at)
+            VALUES ($1, $2, $3)
             "#,
             user.id,
-            verification_token,
+            verification_token_hash,
             token_expires_at,
         )
         .execute(&mut *tx)
@@ -116,19 +170,23 @@ impl AuthExt for DBClient {
         Ok(user)
     }
 
-    async fn get_user_id_by_token(&self, token: Uuid) -> Result<Option<EmailVerification>, Error> {
-        let record = sqlx::query_as!(
+    /// Deletes and returns the verification the token belongs to, so
+    /// concurrent requests cannot both use it.
+    async fn take_email_verification(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<EmailVerification>, Error> {
+        sqlx::query_as!(
             EmailVerification,
             r#"
-            SELECT user_id, token, expires_at 
-            FROM email_verifications 
-            WHERE token = $1
+            DELETE FROM email_verifications
+            WHERE token_hash = $1
+            RETURNING user_id, token_hash, expires_at, created_at
             "#,
-            token
+            token_hash
         )
         .fetch_optional(&self.pool)
-        .await?;
-        Ok(record)
+        .await
     }
 
     async fn verify_user(&self, user_id: Uuid) -> Result<(), Error> {
@@ -156,19 +214,68 @@ impl AuthExt for DBClient {
         Ok(())
     }
 
+    /// Replaces the user's verification token unless the current one was sent
+    /// within the cooldown. Returns when the kept token was sent, or `None` if
+    /// `token` replaced it.
+    async fn rotate_email_verification_token(
+        &self,
+        user_id: Uuid,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+        cooldown_seconds: i64,
+    ) -> Result<Option<DateTime<Utc>>, Error> {
+        let rotated = sqlx::query!(
+            r#"
+            INSERT INTO email_verifications (user_id, token_hash, expires_at)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (user_id) DO UPDATE
+            SET token_hash = EXCLUDED.token_hash,
+                expires_at = EXCLUDED.expires_at,
+                created_at = NOW()
+            WHERE email_verifications.created_at < NOW() - make_interval(secs => $4)
+            "#,
+            user_id,
+            token_hash,
+            expires_at,
+            cooldown_seconds as f64
+        )
+        .execute(&self.pool)
+        .await?;
+
+        if rotated.rows_affected() > 0 {
+            return Ok(None);
+        }
+
+        sqlx::query_scalar!(
+            r#"
+            SELECT created_at
+            FROM email_verifications
+            WHERE user_id = $1
+            "#,
+            user_id
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map(Some)
+    }
+
+    /// Replaces any earlier reset token for the user.
     async fn save_password_reset_token(
         &self,
         user_id: Uuid,
-        token: Uuid,
+        token_hash: &str,
         expires_at: DateTime<Utc>,
     ) -> Result<(), Error> {
         sqlx::query!(
             r#"
-            INSERT INTO password_resets (user_id, token, expires_at)
-            VALUES ($1, $2::UUID, $3)
+            INSERT INTO password_resets (user_id, token_hash, expires_at)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (user_id) DO UPDATE
+            SET token_hash = EXCLUDED.token_hash,
+                expires_at = EXCLUDED.expires_at
             "#,
             user_id,
-            token,
+            token_hash,
             expires_at
         )
         .execute(&self.pool)
@@ -176,32 +283,68 @@ impl AuthExt for DBClient {
         Ok(())
     }
 
-    async fn get_password_reset_token(&self, token: Uuid) -> Result<Option<PasswordReset>, Error> {
-        let record = sqlx::query_as!(
+    /// Deletes and returns the reset the token belongs to, so it can only be
+    /// used once.
+    /// Looks a reset up without using it, so the new password can be
+    /// checked against the user's history first.
+    async fn get_password_reset_token(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<PasswordReset>, Error> {
+        sqlx::query_as!(
             PasswordReset,
             r#"
-            SELECT user_id, token, expires_at 
-            FROM password_resets 
-            WHERE token = $1
+            SELECT user_id, token_hash, expires_at
+            FROM password_resets
+            WHERE token_hash = $1
             "#,
-            token
+            token_hash
         )
         .fetch_optional(&self.pool)
-        .await?;
-        Ok(record)
+        .await
     }
 
-    async fn reset_password(&self, user_id: Uuid, new_password: &str) -> Result<(), Error> {
+    /// Uses up the reset token and sets the new password together, so a
+    /// failure leaves both as they were. Returns false, changing nothing, if
+    /// the token was already used.
+    async fn reset_password(
+        &self,
+        token_hash: &str,
+        user_id: Uuid,
+        new_password: &str,
+        history_size: usize,
+    ) -> Result<bool, Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let taken = sqlx::query!(
+            r#"
+            DELETE FROM password_resets
+            WHERE token_hash = $1 AND user_id = $2
+            "#,
+            token_hash,
+            user_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        if taken.rows_affected() == 0 {
+            return Ok(false);
+        }
+
+        archive_current_password(&mut tx, user_id, history_size).await?;
+
+        // Stamped by the same clock as token `iat`s, so the two compare.
         sqlx::query!(
             r#"
             UPDATE users
-            SET password = $2
+            SET password = $2, password_changed_at = $3
             WHERE id = $1
             "#,
             user_id,
-            new_password
+            new_password,
+            Utc::now()
         )
-        .execute(&self.pool)
+        .execute(&mut *tx)
         .await?;
 
         sqlx::query!(
@@ -211,24 +354,208 @@ impl AuthExt for DBClient {
             "#,
             user_id
         )
-        .execute(&self.pool)
+        .execute(&mut *tx)
         .await?;
 
-        Ok(())
+        tx.commit().await?;
+        Ok(true)
     }
 
-    async fn update_user_password(&self, user_id: Uuid, new_password: &str) -> Result<(), Error> {
+    async fn update_user_password(
+        &self,
+        user_id: Uuid,
+        new_password: &str,
+        history_size: usize,
+    ) -> Result<(), Error> {
+        let mut tx = self.pool.begin().await?;
+
+        archive_current_password(&mut tx, user_id, history_size).await?;
+
+        // Stamped by the same clock as token `iat`s, so the two compare.
         sqlx::query!(
             r#"
             UPDATE users
-            SET password = $2, updated_at = NOW()
+            SET password = $2, password_changed_at = $3, updated_at = NOW()
             WHERE id = $1
             "#,
             user_id,
-            new_password
+            new_password,
+            Utc::now()
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await
+    }
+
+    /// The user's previous password hashes, newest first.
+    async fn get_password_history(
+        &self,
+        user_id: Uuid,
+        limit: usize,
+    ) -> Result<Vec<String>, Error> {
+        sqlx::query_scalar!(
+            r#"
+            SELECT password_hash
+            FROM password_history
+            WHERE user_id = $1
+            ORDER BY created_at DESC
+            LIMIT $2
+            "#,
+            user_id,
+            limit as i64
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Swaps in a hash with current parameters for the same password. Does
+    /// nothing if the password changed since `old_hash` was read.
+    async fn rehash_user_password(
+        &self,
+        user_id: Uuid,
+        old_hash: &str,
+        new_hash: &str,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+            UPDATE users
+            SET password = $3
+            WHERE id = $1 AND password = $2
+            "#,
+            user_id,
+            old_hash,
+            new_hash
         )
         .execute(&self.pool)
         .await?;
         Ok(())
     }
+
+    /// Replaces any pending sign-in link for the user. Wrong code attempts
+    /// carry over until the replaced link would have expired, so asking for
+    /// a new code does not grant more guesses.
+    async fn save_magic_link(
+        &self,
+        user_id: Uuid,
+        token_hash: &str,
+        code_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO magic_links (user_id, token_hash, code_hash, expires_at)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (user_id) DO UPDATE
+            SET token_hash = EXCLUDED.token_hash,
+                code_hash = EXCLUDED.code_hash,
+                attempts = CASE
+                    WHEN magic_links.expires_at > NOW() THEN magic_links.attempts
+                    ELSE 0
+                END,
+                expires_at = EXCLUDED.expires_at,
+                created_at = NOW()
+            "#,
+            user_id,
+            token_hash,
+            code_hash,
+            expires_at
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn take_magic_link_by_token(&self, token_hash: &str) -> Result<Option<MagicLink>, Error> {
+        sqlx::query_as!(
+            MagicLink,
+            r#"
+            DELETE FROM magic_links
+            WHERE token_hash = $1 AND expires_at > NOW()
+            RETURNING user_id, token_hash, code_hash, attempts, expires_at, created_at
+            "#,
+            token_hash
+        )
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// Consumes the user's pending link when `code_hash` matches. A wrong code
+    /// counts as an attempt, and the link stops accepting codes after
+    /// `max_attempts` of them.
+    async fn take_magic_link_by_code(
+        &self,
+        user_id: Uuid,
+        code_hash: &str,
+        max_attempts: i32,
+    ) -> Result<Option<MagicLink>, Error> {
+        let magic_link = sqlx::query_as!(
+            MagicLink,
+            r#"
+            DELETE FROM magic_links
+            WHERE user_id = $1 AND code_hash = $2 AND attempts < $3 AND expires_at > NOW()
+            RETURNING user_id, token_hash, code_hash, attempts, expires_at, created_at
+            "#,
+            user_id,
+            code_hash,
+            max_attempts
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        if magic_link.is_none() {
+            sqlx::query!(
+                r#"
+                UPDATE magic_links
+                SET attempts = attempts + 1
+                WHERE user_id = $1
+                "#,
+                user_id
+            )
+            .execute(&self.pool)
+            .await?;
+        }
+
+        Ok(magic_link)
+    }
+}
+
+/// Moves the user's current hash into `password_history` before it is
+/// replaced, and prunes entries that no longer count towards the last
+/// `history_size` passwords.
+async fn archive_current_password(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    history_size: usize,
+) -> Result<(), Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO password_history (user_id, password_hash)
+        SELECT id, password
+        FROM users
+        WHERE id = $1
+        "#,
+        user_id
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        DELETE FROM password_history
+        WHERE user_id = $1 AND id NOT IN (
+            SELECT id
+            FROM password_history
+            WHERE user_id = $1
+            ORDER BY created_at DESC
+            LIMIT $2
+        )
+        "#,
+        user_id,
+        history_size.saturating_sub(1) as i64
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
 }
@@ -0,0 +1,203 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::Error;
+use uuid::Uuid;
+
+use crate::{
+    database::DBClient,
+    models::{Identity, OAuthState, User},
+};
+
+#[async_trait]
+pub trait IdentityExt {
+    async fn save_oauth_state(
+        &self,
+        state: &str,
+        provider: &str,
+        code_verifier: &str,
+        nonce: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), Error>;
+
+    async fn take_oauth_state(
+        &self,
+        state: &str,
+        provider: &str,
+    ) -> Result<Option<OAuthState>, Error>;
+
+    async fn get_identity(&self, provider: &str, subject: &str) -> Result<Option<Identity>, Error>;
+
+    async fn record_identity_login(&self, identity_id: Uuid) -> Result<(), Error>;
+
+    async fn link_identity(
+        &self,
+        user_id: Uuid,
+        provider: &str,
+        subject: &str,
+        email: Option<&str>,
+    ) -> Result<Identity, Error>;
+
+    async fn create_user_with_identity(
+        &self,
+        name: &str,
+        email: &str,
+        password: &str,
+        provider: &str,
+        subject: &str,
+    ) -> Result<User, Error>;
+}
+
+#[async_trait]
+impl IdentityExt for DBClient {
+    async fn save_oauth_state(
+        &self,
+        state: &str,
+        provider: &str,
+        code_verifier: &str,
+        nonce: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), Error> {
+        // Abandoned sign-ins are never taken, so clear them out here.
+        sqlx::query!(
+            r#"
+            DELETE FROM oauth_states
+            WHERE expires_at < NOW()
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO oauth_states (state, provider, code_verifier, nonce, expires_at)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+            state,
+            provider,
+            code_verifier,
+            nonce,
+            expires_at
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Consumes a pending authorization request so its state is single use.
+    async fn take_oauth_state(
+        &self,
+        state: &str,
+        provider: &str,
+    ) -> Result<Option<OAuthState>, Error> {
+        sqlx::query_as!(
+            OAuthState,
+            r#"
+            DELETE FROM oauth_states
+            WHERE state = $1 AND provider = $2 AND expires_at > NOW()
+            RETURNING state, provider, code_verifier, nonce, expires_at, created_at
+            "#,
+            state,
+            provider
+        )
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    async fn get_identity(&self, provider: &str, subject: &str) -> Result<Option<Identity>, Error> {
+        sqlx::query_as!(
+            Identity,
+            r#"
+            SELECT id, user_id, provider, subject, email, created_at, last_login_at
+            FROM identities
+            WHERE provider = $1 AND subject = $2
+            "#,
+            provider,
+            subject
+        )
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    async fn record_identity_login(&self, identity_id: Uuid) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+            UPDATE identities
+            SET last_login_at = NOW()
+            WHERE id = $1
+            "#,
+            identity_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn link_identity(
+        &self,
+        user_id: Uuid,
+        provider: &str,
+        subject: &str,
+        email: Option<&str>,
+    ) -> Result<Identity, Error> {
+        sqlx::query_as!(
+            Identity,
+            r#"
+            INSERT INTO identities (user_id, provider, subject, email)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id, user_id, provider, subject, email, created_at, last_login_at
+            "#,
+            user_id,
+            provider,
+            subject,
+            email
+        )
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    /// Signs up a user from a provider identity. The provider already verified
+    /// the email, so the account starts out verified.
+    async fn create_user_with_identity(
+        &self,
+        name: &str,
+        email: &str,
+        password: &str,
+        provider: &str,
+        subject: &str,
+    ) -> Result<User, Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let user = sqlx::query_as!(
+            User,
+            r#"
+            INSERT INTO users (name, email, password, email_verified)
+            VALUES ($1, $2, $3, TRUE)
+            RETURNING id, name, email, password, pending_email, email_verified, pending_email_expires_at, pending_email_token_hash, password_changed_at, service_account_workspace_id, created_at, updated_at
+            "#,
+            name,
+            email,
+            password
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO identities (user_id, provider, subject, email)
+            VALUES ($1, $2, $3, $4)
+            "#,
+            user.id,
+            provider,
+            subject,
+            email
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(user)
+    }
+}
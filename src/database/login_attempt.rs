@@ -0,0 +1,135 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::Error;
+use uuid::Uuid;
+
+use crate::{database::DBClient, models::LoginAttempt};
+
+#[async_trait]
+pub trait LoginAttemptExt {
+    async fn get_login_attempt(&self, kind: &str, key: &str)
+    -> Result<Option<LoginAttempt>, Error>;
+
+    async fn record_login_failure(
+        &self,
+        kind: &str,
+        key: &str,
+        window_seconds: i64,
+    ) -> Result<LoginAttempt, Error>;
+
+    async fn lock_login(
+        &self,
+        kind: &str,
+        key: &str,
+        locked_until: DateTime<Utc>,
+    ) -> Result<(), Error>;
+
+    async fn clear_login_failures(&self, kind: &str, key: &str) -> Result<bool, Error>;
+
+    async fn record_login_unlock(&self, user_id: Uuid, unlocked_by: Uuid) -> Result<(), Error>;
+}
+
+#[async_trait]
+impl LoginAttemptExt for DBClient {
+    async fn get_login_attempt(
+        &self,
+        kind: &str,
+        key: &str,
+    ) -> Result<Option<LoginAttempt>, Error> {
+        sqlx::query_as!(
+            LoginAttempt,
+            r#"
+            SELECT kind, key, failed_count, last_failed_at, locked_until
+            FROM login_attempts
+            WHERE kind = $1 AND key = $2
+            "#,
+            kind,
+            key
+        )
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// Counts a failed sign-in. Failures older than the window are forgotten,
+    /// so the count starts over after a quiet period.
+    async fn record_login_failure(
+        &self,
+        kind: &str,
+        key: &str,
+        window_seconds: i64,
+    ) -> Result<LoginAttempt, Error> {
+        sqlx::query_as!(
+            LoginAttempt,
+            r#"
+            INSERT INTO login_attempts (kind, key, failed_count, last_failed_at)
+            VALUES ($1, $2, 1, NOW())
+            ON CONFLICT (kind, key) DO UPDATE
+            SET failed_count = CASE
+                    WHEN login_attempts.last_failed_at < NOW() - make_interval(secs => $3)
+                    THEN 1
+                    ELSE login_attempts.failed_count + 1
+                END,
+                last_failed_at = NOW()
+            RETURNING kind, key, failed_count, last_failed_at, locked_until
+            "#,
+            kind,
+            key,
+            window_seconds as f64
+        )
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    async fn lock_login(
+        &self,
+        kind: &str,
+        key: &str,
+        locked_until: DateTime<Utc>,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+            UPDATE login_attempts
+            SET locked_until = $3
+            WHERE kind = $1 AND key = $2
+            "#,
+            kind,
+            key,
+            locked_until
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Forgets failures and lifts any lockout. Returns whether anything was cleared.
+    async fn clear_login_failures(&self, kind: &str, key: &str) -> Result<bool, Error> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM login_attempts
+            WHERE kind = $1 AND key = $2
+            "#,
+            kind,
+            key
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn record_login_unlock(&self, user_id: Uuid, unlocked_by: Uuid) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO login_unlocks (user_id, unlocked_by)
+            VALUES ($1, $2)
+            "#,
+            user_id,
+            unlocked_by
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
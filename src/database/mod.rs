@@ -1,19 +1,38 @@
+use std::sync::{Arc, Mutex};
+
 use sqlx::{Pool, Postgres};
 
+use crate::database::workspace::PermissionsVersionCache;
+
 pub mod auth;
+pub mod identity;
+pub mod login_attempt;
 pub mod permissions;
+pub mod personal_access_token;
+pub mod rate_limit;
+pub mod refresh_token;
+pub mod revoked_token;
 pub mod role;
+pub mod security_policy;
+pub mod service_account;
+pub mod session;
+pub mod two_factor;
 pub mod user;
+pub mod webauthn;
 pub mod workspace;
 pub mod workspace_user;
 
 #[derive(Debug, Clone)]
 pub struct DBClient {
     pool: Pool<Postgres>,
+    permissions_versions: Arc<Mutex<PermissionsVersionCache>>,
 }
 
 impl DBClient {
     pub fn new(pool: Pool<Postgres>) -> Self {
-        Self { pool }
+        Self {
+            pool,
+            permissions_versions: Arc::new(Mutex::new(PermissionsVersionCache::default())),
+        }
     }
 }
@@ -0,0 +1,137 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::Error;
+use uuid::Uuid;
+
+use crate::{database::DBClient, models::PersonalAccessToken};
+
+#[async_trait]
+pub trait PersonalAccessTokenExt {
+    async fn create_personal_access_token(
+        &self,
+        user_id: Uuid,
+        workspace_id: Uuid,
+        name: &str,
+        token_hash: &str,
+        permissions: &[String],
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<PersonalAccessToken, Error>;
+
+    async fn get_personal_access_tokens(
+        &self,
+        user_id: Uuid,
+    ) -> Result<Vec<PersonalAccessToken>, Error>;
+
+    async fn use_personal_access_token(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<PersonalAccessToken>, Error>;
+
+    async fn delete_personal_access_token(
+        &self,
+        user_id: Uuid,
+        token_id: Uuid,
+    ) -> Result<bool, Error>;
+
+    async fn delete_user_personal_access_tokens(&self, user_id: Uuid) -> Result<u64, Error>;
+}
+
+#[async_trait]
+impl PersonalAccessTokenExt for DBClient {
+    async fn create_personal_access_token(
+        &self,
+        user_id: Uuid,
+        workspace_id: Uuid,
+        name: &str,
+        token_hash: &str,
+        permissions: &[String],
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<PersonalAccessToken, Error> {
+        sqlx::query_as!(
+            PersonalAccessToken,
+            r#"
+            INSERT INTO personal_access_tokens (user_id, workspace_id, name, token_hash, permissions, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING id, user_id, workspace_id, name, token_hash, permissions, expires_at, last_used_at, created_at
+            "#,
+            user_id,
+            workspace_id,
+            name,
+            token_hash,
+            permissions,
+            expires_at
+        )
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    async fn get_personal_access_tokens(
+        &self,
+        user_id: Uuid,
+    ) -> Result<Vec<PersonalAccessToken>, Error> {
+        sqlx::query_as!(
+            PersonalAccessToken,
+            r#"
+            SELECT id, user_id, workspace_id, name, token_hash, permissions, expires_at, last_used_at, created_at
+            FROM personal_access_tokens
+            WHERE user_id = $1
+            ORDER BY created_at
+            "#,
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Returns the token if it exists and has not expired, recording the use.
+    async fn use_personal_access_token(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<PersonalAccessToken>, Error> {
+        sqlx::query_as!(
+            PersonalAccessToken,
+            r#"
+            UPDATE personal_access_tokens
+            SET last_used_at = NOW()
+            WHERE token_hash = $1 AND (expires_at IS NULL OR expires_at > NOW())
+            RETURNING id, user_id, workspace_id, name, token_hash, permissions, expires_at, last_used_at, created_at
+            "#,
+            token_hash
+        )
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    async fn delete_personal_access_token(
+        &self,
+        user_id: Uuid,
+        token_id: Uuid,
+    ) -> Result<bool, Error> {
+        let deleted = sqlx::query!(
+            r#"
+            DELETE FROM personal_access_tokens
+            WHERE id = $1 AND user_id = $2
+            "#,
+            token_id,
+            user_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(deleted.rows_affected() > 0)
+    }
+
+    async fn delete_user_personal_access_tokens(&self, user_id: Uuid) -> Result<u64, Error> {
+        let deleted = sqlx::query!(
+            r#"
+            DELETE FROM personal_access_tokens
+            WHERE user_id = $1
+            "#,
+            user_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(deleted.rows_affected())
+    }
+}
@@ -0,0 +1,98 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use sqlx::Error;
+
+use crate::{database::DBClient, utils::rate_limit::BucketPolicy};
+
+/// How often buckets that have refilled are deleted.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+#[async_trait]
+pub trait RateLimitExt {
+    async fn take_rate_limit_token(
+        &self,
+        key: &str,
+        policy: &BucketPolicy,
+    ) -> Result<Option<u64>, Error>;
+}
+
+#[async_trait]
+impl RateLimitExt for DBClient {
+    /// Takes a token from the bucket for `key`, returning the seconds to wait
+    /// if it is empty. The row is locked while it is updated, so instances
+    /// sharing the database never hand out the same token twice.
+    async fn take_rate_limit_token(
+        &self,
+        key: &str,
+        policy: &BucketPolicy,
+    ) -> Result<Option<u64>, Error> {
+        let now = Utc::now();
+        let mut tx = self.pool.begin().await?;
+
+        // The no-op update locks an existing row and returns it, so a new
+        // bucket and an existing one are read the same way.
+        let bucket = sqlx::query!(
+            r#"
+            INSERT INTO rate_limit_buckets (key, tokens, updated_at, expires_at)
+            VALUES ($1, $2, $3, $3)
+            ON CONFLICT (key) DO UPDATE SET key = EXCLUDED.key
+            RETURNING tokens, updated_at
+            "#,
+            key,
+            f64::from(policy.capacity),
+            now
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let (tokens, retry_after) = policy.take(bucket.tokens, bucket.updated_at, now);
+
+        sqlx::query!(
+            r#"
+            UPDATE rate_limit_buckets
+            SET tokens = $2, updated_at = $3, expires_at = $4
+            WHERE key = $1
+            "#,
+            key,
+            tokens,
+            now,
+            policy.full_at(tokens, now)
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(retry_after)
+    }
+}
+
+impl DBClient {
+    /// Deletes buckets past `expires_at` every minute for as long as the server
+    /// runs. A full bucket behaves like a missing one, so this only keeps the
+    /// table small, and does it off the request path.
+    pub fn sweep_rate_limit_buckets(&self) {
+        let db_client = self.clone();
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+            loop {
+                interval.tick().await;
+                let result = sqlx::query!(
+                    r#"
+                    DELETE FROM rate_limit_buckets
+                    WHERE expires_at < NOW()
+                    "#
+                )
+                .execute(&db_client.pool)
+                .await;
+
+                if let Err(e) = result {
+                    tracing::warn!(error = %e, "failed to delete expired rate limit buckets");
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,132 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::Error;
+use uuid::Uuid;
+
+use crate::{database::DBClient, models::RefreshToken};
+
+#[async_trait]
+pub trait RefreshTokenExt {
+    async fn save_refresh_token(
+        &self,
+        user_id: Uuid,
+        session_id: Uuid,
+        family_id: Uuid,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), Error>;
+
+    async fn get_refresh_token(&self, token_hash: &str) -> Result<Option<RefreshToken>, Error>;
+
+    async fn rotate_refresh_token(
+        &self,
+        current: &RefreshToken,
+        new_token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<bool, Error>;
+
+    async fn revoke_refresh_token_family(&self, family_id: Uuid) -> Result<(), Error>;
+}
+
+#[async_trait]
+impl RefreshTokenExt for DBClient {
+    async fn save_refresh_token(
+        &self,
+        user_id: Uuid,
+        session_id: Uuid,
+        family_id: Uuid,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO refresh_tokens (user_id, session_id, family_id, token_hash, expires_at)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+            user_id,
+            session_id,
+            family_id,
+            token_hash,
+            expires_at
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_refresh_token(&self, token_hash: &str) -> Result<Option<RefreshToken>, Error> {
+        sqlx::query_as!(
+            RefreshToken,
+            r#"
+            SELECT id, user_id, family_id, session_id, token_hash, expires_at, used_at, revoked_at, created_at
+            FROM refresh_tokens
+            WHERE token_hash = $1
+            "#,
+            token_hash
+        )
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// Marks `current` as used and stores its successor in the same family.
+    /// Returns `false` when `current` was already used or revoked, which means
+    /// another request won the race and the presented token is being replayed.
+    async fn rotate_refresh_token(
+        &self,
+        current: &RefreshToken,
+        new_token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<bool, Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let consumed = sqlx::query!(
+            r#"
+            UPDATE refresh_tokens
+            SET used_at = NOW()
+            WHERE id = $1 AND used_at IS NULL AND revoked_at IS NULL
+            "#,
+            current.id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        if consumed.rows_affected() == 0 {
+            tx.rollback().await?;
+            return Ok(false);
+        }
+
+        sqlx::query!(
+            r#"
+            INSERT INTO refresh_tokens (user_id, session_id, family_id, token_hash, expires_at)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+            current.user_id,
+            current.session_id,
+            current.family_id,
+            new_token_hash,
+            expires_at
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    async fn revoke_refresh_token_family(&self, family_id: Uuid) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+            UPDATE refresh_tokens
+            SET revoked_at = NOW()
+            WHERE family_id = $1 AND revoked_at IS NULL
+            "#,
+            family_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
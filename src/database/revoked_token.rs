@@ -0,0 +1,67 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::Error;
+use uuid::Uuid;
+
+use crate::database::DBClient;
+
+#[async_trait]
+pub trait RevokedTokenExt {
+    async fn revoke_token(
+        &self,
+        jti: Uuid,
+        user_id: Uuid,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), Error>;
+
+    async fn is_token_revoked(&self, jti: Uuid) -> Result<bool, Error>;
+}
+
+#[async_trait]
+impl RevokedTokenExt for DBClient {
+    async fn revoke_token(
+        &self,
+        jti: Uuid,
+        user_id: Uuid,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO revoked_tokens (jti, user_id, expires_at)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (jti) DO NOTHING
+            "#,
+            jti,
+            user_id,
+            expires_at
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Expired tokens are rejected by signature validation anyway, so their
+        // denylist entries can be dropped.
+        sqlx::query!(
+            r#"
+            DELETE FROM revoked_tokens
+            WHERE expires_at < NOW()
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn is_token_revoked(&self, jti: Uuid) -> Result<bool, Error> {
+        let revoked = sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS (SELECT 1 FROM revoked_tokens WHERE jti = $1)
+            "#,
+            jti
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(revoked.unwrap_or(false))
+    }
+}
@@ -34,6 +34,8 @@ pub trait RoleExt {
         workspace_id: Uuid,
         name: String,
     ) -> Result<Uuid, sqlx::Error>;
+
+    async fn get_role_permissions(&self, role_id: Uuid) -> Result<Vec<String>, sqlx::Error>;
 }
 
 #[async_trait]
@@ -218,4 +220,18 @@ impl RoleExt for DBClient {
 
         Ok(role_id)
     }
+
+    async fn get_role_permissions(&self, role_id: Uuid) -> Result<Vec<String>, sqlx::Error> {
+        sqlx::query_scalar!(
+            r#"
+            SELECT p.name
+            FROM role_permissions rp
+                JOIN permissions p ON p.id = rp.permission_id
+            WHERE rp.role_id = $1
+            "#,
+            role_id
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
 }
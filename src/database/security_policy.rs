@@ -0,0 +1,69 @@
+use async_trait::async_trait;
+use sqlx::Error;
+use uuid::Uuid;
+
+use crate::{database::DBClient, models::WorkspaceSecurityPolicy};
+
+#[async_trait]
+pub trait SecurityPolicyExt {
+    async fn get_security_policy(
+        &self,
+        workspace_id: Uuid,
+    ) -> Result<Option<WorkspaceSecurityPolicy>, Error>;
+
+    async fn save_security_policy(
+        &self,
+        workspace_id: Uuid,
+        require_two_factor: bool,
+        max_session_age_minutes: Option<i32>,
+        idle_timeout_minutes: Option<i32>,
+    ) -> Result<WorkspaceSecurityPolicy, Error>;
+}
+
+#[async_trait]
+impl SecurityPolicyExt for DBClient {
+    async fn get_security_policy(
+        &self,
+        workspace_id: Uuid,
+    ) -> Result<Option<WorkspaceSecurityPolicy>, Error> {
+        sqlx::query_as!(
+            WorkspaceSecurityPolicy,
+            r#"
+            SELECT workspace_id, require_two_factor, max_session_age_minutes, idle_timeout_minutes, created_at, updated_at
+            FROM workspace_security_policies
+            WHERE workspace_id = $1
+            "#,
+            workspace_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    async fn save_security_policy(
+        &self,
+        workspace_id: Uuid,
+        require_two_factor: bool,
+        max_session_age_minutes: Option<i32>,
+        idle_timeout_minutes: Option<i32>,
+    ) -> Result<WorkspaceSecurityPolicy, Error> {
+        sqlx::query_as!(
+            WorkspaceSecurityPolicy,
+            r#"
+            INSERT INTO workspace_security_policies (workspace_id, require_two_factor, max_session_age_minutes, idle_timeout_minutes)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (workspace_id) DO UPDATE
+            SET require_two_factor = EXCLUDED.require_two_factor,
+                max_session_age_minutes = EXCLUDED.max_session_age_minutes,
+                idle_timeout_minutes = EXCLUDED.idle_timeout_minutes,
+                updated_at = NOW()
+            RETURNING workspace_id, require_two_factor, max_session_age_minutes, idle_timeout_minutes, created_at, updated_at
+            "#,
+            workspace_id,
+            require_two_factor,
+            max_session_age_minutes,
+            idle_timeout_minutes
+        )
+        .fetch_one(&self.pool)
+        .await
+    }
+}
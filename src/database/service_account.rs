@@ -0,0 +1,272 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::Error;
+use uuid::Uuid;
+
+use crate::{
+    database::DBClient, dtos::service_account::ServiceAccountDto, models::ServiceAccountKey,
+};
+
+#[async_trait]
+pub trait ServiceAccountExt {
+    async fn create_service_account(
+        &self,
+        workspace_id: Uuid,
+        name: &str,
+        role_id: Uuid,
+        key_hash: &str,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<ServiceAccountDto, Error>;
+
+    async fn get_service_accounts(
+        &self,
+        workspace_id: Uuid,
+    ) -> Result<Vec<ServiceAccountDto>, Error>;
+
+    async fn service_account_exists(
+        &self,
+        workspace_id: Uuid,
+        service_account_id: Uuid,
+    ) -> Result<bool, Error>;
+
+    async fn delete_service_account(
+        &self,
+        workspace_id: Uuid,
+        service_account_id: Uuid,
+    ) -> Result<bool, Error>;
+
+    async fn create_service_account_key(
+        &self,
+        service_account_id: Uuid,
+        key_hash: &str,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<ServiceAccountKey, Error>;
+
+    async fn get_service_account_keys(
+        &self,
+        service_account_id: Uuid,
+    ) -> Result<Vec<ServiceAccountKey>, Error>;
+
+    async fn use_service_account_key(
+        &self,
+        key_hash: &str,
+    ) -> Result<Option<ServiceAccountKey>, Error>;
+
+    async fn delete_service_account_key(
+        &self,
+        service_account_id: Uuid,
+        key_id: Uuid,
+    ) -> Result<bool, Error>;
+}
+
+#[async_trait]
+impl ServiceAccountExt for DBClient {
+    /// Creates the account, its membership and its first key together. The
+    /// account gets a placeholder email and a password no hash can match.
+    async fn create_service_account(
+        &self,
+        workspace_id: Uuid,
+        name: &str,
+        role_id: Uuid,
+        key_hash: &str,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<ServiceAccountDto, Error> {
+        let id = Uuid::new_v4();
+        let email = format!("{}@service-accounts.invalid", id);
+
+        let mut tx = self.pool.begin().await?;
+
+        let created_at = sqlx::query_scalar!(
+            r#"
+            INSERT INTO users (id, name, email, password, email_verified, service_account_workspace_id)
+            VALUES ($1, $2, $3, '!', true, $4)
+            RETURNING created_at
+            "#,
+            id,
+            name,
+            email,
+            workspace_id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let role_name = sqlx::query_scalar!(
+            r#"
+            WITH member AS (
+                INSERT INTO workspace_users (workspace_id, user_id, role_id)
+                VALUES ($1, $2, $3)
+                RETURNING role_id
+            )
+            SELECT r.name
+            FROM member m
+                JOIN roles r ON r.id = m.role_id
+            "#,
+            workspace_id,
+            id,
+            role_id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO service_account_keys (service_account_id, key_hash, expires_at)
+            VALUES ($1, $2, $3)
+            "#,
+            id,
+            key_hash,
+            expires_at
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(ServiceAccountDto {
+            id,
+            name: name.to_string(),
+            role_name,
+            created_at,
+        })
+    }
+
+    async fn get_service_accounts(
+        &self,
+        workspace_id: Uuid,
+    ) -> Result<Vec<ServiceAccountDto>, Error> {
+        sqlx::query_as!(
+            ServiceAccountDto,
+            r#"
+            SELECT u.id,
+                u.name,
+                r.name as role_name,
+                u.created_at
+            FROM users u
+                JOIN workspace_users wu ON wu.user_id = u.id AND wu.workspace_id = u.service_account_workspace_id
+                JOIN roles r ON r.id = wu.role_id
+            WHERE u.service_account_workspace_id = $1
+            ORDER BY u.created_at
+            "#,
+            workspace_id
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn service_account_exists(
+        &self,
+        workspace_id: Uuid,
+        service_account_id: Uuid,
+    ) -> Result<bool, Error> {
+        let exists = sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM users
+                WHERE id = $1 AND service_account_workspace_id = $2
+            ) as "exists!"
+            "#,
+            service_account_id,
+            workspace_id
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(exists)
+    }
+
+    async fn delete_service_account(
+        &self,
+        workspace_id: Uuid,
+        service_account_id: Uuid,
+    ) -> Result<bool, Error> {
+        let deleted = sqlx::query!(
+            r#"
+            DELETE FROM users
+            WHERE id = $1 AND service_account_workspace_id = $2
+            "#,
+            service_account_id,
+            workspace_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(deleted.rows_affected() > 0)
+    }
+
+    async fn create_service_account_key(
+        &self,
+        service_account_id: Uuid,
+        key_hash: &str,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<ServiceAccountKey, Error> {
+        sqlx::query_as!(
+            ServiceAccountKey,
+            r#"
+            INSERT INTO service_account_keys (service_account_id, key_hash, expires_at)
+            VALUES ($1, $2, $3)
+            RETURNING id, service_account_id, key_hash, expires_at, last_used_at, created_at
+            "#,
+            service_account_id,
+            key_hash,
+            expires_at
+        )
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    async fn get_service_account_keys(
+        &self,
+        service_account_id: Uuid,
+    ) -> Result<Vec<ServiceAccountKey>, Error> {
+        sqlx::query_as!(
+            ServiceAccountKey,
+            r#"
+            SELECT id, service_account_id, key_hash, expires_at, last_used_at, created_at
+            FROM service_account_keys
+            WHERE service_account_id = $1
+            ORDER BY created_at
+            "#,
+            service_account_id
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Returns the key if it exists and has not expired, recording the use.
+    async fn use_service_account_key(
+        &self,
+        key_hash: &str,
+    ) -> Result<Option<ServiceAccountKey>, Error> {
+        sqlx::query_as!(
+            ServiceAccountKey,
+            r#"
+            UPDATE service_account_keys
+            SET last_used_at = NOW()
+            WHERE key_hash = $1 AND (expires_at IS NULL OR expires_at > NOW())
+            RETURNING id, service_account_id, key_hash, expires_at, last_used_at, created_at
+            "#,
+            key_hash
+        )
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    async fn delete_service_account_key(
+        &self,
+        service_account_id: Uuid,
+        key_id: Uuid,
+    ) -> Result<bool, Error> {
+        let deleted = sqlx::query!(
+            r#"
+            DELETE FROM service_account_keys
+            WHERE id = $1 AND service_account_id = $2
+            "#,
+            key_id,
+            service_account_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(deleted.rows_affected() > 0)
+    }
+}
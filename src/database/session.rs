@@ -0,0 +1,194 @@
+use async_trait::async_trait;
+use sqlx::Error;
+use uuid::Uuid;
+
+use crate::{database::DBClient, models::UserSession};
+
+#[async_trait]
+pub trait SessionExt {
+    async fn create_session(
+        &self,
+        user_id: Uuid,
+        user_agent: Option<&str>,
+        ip_address: Option<&str>,
+    ) -> Result<UserSession, Error>;
+
+    async fn touch_session(
+        &self,
+        user_id: Uuid,
+        session_id: Uuid,
+    ) -> Result<Option<UserSession>, Error>;
+
+    async fn get_user_sessions(&self, user_id: Uuid) -> Result<Vec<UserSession>, Error>;
+
+    async fn revoke_session(&self, user_id: Uuid, session_id: Uuid) -> Result<bool, Error>;
+
+    async fn revoke_other_sessions(
+        &self,
+        user_id: Uuid,
+        current_session_id: Uuid,
+    ) -> Result<u64, Error>;
+
+    async fn revoke_all_sessions(&self, user_id: Uuid) -> Result<u64, Error>;
+}
+
+#[async_trait]
+impl SessionExt for DBClient {
+    async fn create_session(
+        &self,
+        user_id: Uuid,
+        user_agent: Option<&str>,
+        ip_address: Option<&str>,
+    ) -> Result<UserSession, Error> {
+        sqlx::query_as!(
+            UserSession,
+            r#"
+            INSERT INTO user_sessions (user_id, user_agent, ip_address)
+            VALUES ($1, $2, $3)
+            RETURNING id, user_id, user_agent, ip_address, created_at, last_seen_at, revoked_at
+            "#,
+            user_id,
+            user_agent,
+            ip_address
+        )
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    /// Records activity on a live session. The returned session carries the
+    /// `last_seen_at` from before this call so idle time can be checked.
+    /// Returns `None` when the session was revoked or does not belong to the user.
+    async fn touch_session(
+        &self,
+        user_id: Uuid,
+        session_id: Uuid,
+    ) -> Result<Option<UserSession>, Error> {
+        sqlx::query_as!(
+            UserSession,
+            r#"
+            UPDATE user_sessions s
+            SET last_seen_at = NOW()
+            FROM user_sessions previous
+            WHERE s.id = $1 AND s.user_id = $2 AND s.revoked_at IS NULL AND previous.id = s.id
+            RETURNING s.id, s.user_id, s.user_agent, s.ip_address, s.created_at, previous.last_seen_at, s.revoked_at
+            "#,
+            session_id,
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    async fn get_user_sessions(&self, user_id: Uuid) -> Result<Vec<UserSession>, Error> {
+        sqlx::query_as!(
+            UserSession,
+            r#"
+            SELECT id, user_id, user_agent, ip_address, created_at, last_seen_at, revoked_at
+            FROM user_sessions
+            WHERE user_id = $1 AND revoked_at IS NULL
+            ORDER BY last_seen_at DESC
+            "#,
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn revoke_session(&self, user_id: Uuid, session_id: Uuid) -> Result<bool, Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let revoked = sqlx::query!(
+            r#"
+            UPDATE user_sessions
+            SET revoked_at = NOW()
+            WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL
+            "#,
+            session_id,
+            user_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            UPDATE refresh_tokens
+            SET revoked_at = NOW()
+            WHERE session_id = $1 AND user_id = $2 AND revoked_at IS NULL
+            "#,
+            session_id,
+            user_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(revoked.rows_affected() > 0)
+    }
+
+    async fn revoke_other_sessions(
+        &self,
+        user_id: Uuid,
+        current_session_id: Uuid,
+    ) -> Result<u64, Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let revoked = sqlx::query!(
+            r#"
+            UPDATE user_sessions
+            SET revoked_at = NOW()
+            WHERE user_id = $1 AND id != $2 AND revoked_at IS NULL
+            "#,
+            user_id,
+            current_session_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            UPDATE refresh_tokens
+            SET revoked_at = NOW()
+            WHERE user_id = $1 AND session_id != $2 AND revoked_at IS NULL
+            "#,
+            user_id,
+            current_session_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(revoked.rows_affected())
+    }
+
+    async fn revoke_all_sessions(&self, user_id: Uuid) -> Result<u64, Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let revoked = sqlx::query!(
+            r#"
+            UPDATE user_sessions
+            SET revoked_at = NOW()
+            WHERE user_id = $1 AND revoked_at IS NULL
+            "#,
+            user_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            UPDATE refresh_tokens
+            SET revoked_at = NOW()
+            WHERE user_id = $1 AND revoked_at IS NULL
+            "#,
+            user_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(revoked.rows_affected())
+    }
+}
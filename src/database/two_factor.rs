@@ -0,0 +1,209 @@
+use async_trait::async_trait;
+use sqlx::Error;
+use uuid::Uuid;
+
+use crate::{database::DBClient, models::UserTwoFactor};
+
+#[async_trait]
+pub trait TwoFactorExt {
+    async fn get_two_factor(&self, user_id: Uuid) -> Result<Option<UserTwoFactor>, Error>;
+
+    async fn save_pending_two_factor(&self, user_id: Uuid, totp_secret: &str) -> Result<(), Error>;
+
+    async fn enable_two_factor(
+        &self,
+        user_id: Uuid,
+        used_step: i64,
+        recovery_code_hashes: Vec<String>,
+    ) -> Result<(), Error>;
+
+    async fn disable_two_factor(&self, user_id: Uuid) -> Result<(), Error>;
+
+    async fn record_totp_step(&self, user_id: Uuid, step: i64) -> Result<bool, Error>;
+
+    async fn consume_recovery_code(&self, user_id: Uuid, code_hash: &str) -> Result<bool, Error>;
+
+    async fn replace_recovery_codes(
+        &self,
+        user_id: Uuid,
+        recovery_code_hashes: Vec<String>,
+    ) -> Result<(), Error>;
+}
+
+#[async_trait]
+impl TwoFactorExt for DBClient {
+    async fn get_two_factor(&self, user_id: Uuid) -> Result<Option<UserTwoFactor>, Error> {
+        sqlx::query_as!(
+            UserTwoFactor,
+            r#"
+            SELECT user_id, totp_secret, enabled, last_used_step, created_at, enabled_at
+            FROM user_two_factor
+            WHERE user_id = $1
+            "#,
+            user_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// Stores a secret awaiting confirmation. An already enabled secret is
+    /// never overwritten; it has to be disabled first.
+    async fn save_pending_two_factor(&self, user_id: Uuid, totp_secret: &str) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO user_two_factor (user_id, totp_secret)
+            VALUES ($1, $2)
+            ON CONFLICT (user_id) DO UPDATE
+            SET totp_secret = EXCLUDED.totp_secret, last_used_step = NULL, created_at = NOW()
+            WHERE user_two_factor.enabled = FALSE
+            "#,
+            user_id,
+            totp_secret
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn enable_two_factor(
+        &self,
+        user_id: Uuid,
+        used_step: i64,
+        recovery_code_hashes: Vec<String>,
+    ) -> Result<(), Error> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query!(
+            r#"
+            UPDATE user_two_factor
+            SET enabled = TRUE, enabled_at = NOW(), last_used_step = $2
+            WHERE user_id = $1
+            "#,
+            user_id,
+            used_step
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM two_factor_recovery_codes
+            WHERE user_id = $1
+            "#,
+            user_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO two_factor_recovery_codes (user_id, code_hash)
+            SELECT $1, UNNEST($2::TEXT[])
+            "#,
+            user_id,
+            &recovery_code_hashes
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn disable_two_factor(&self, user_id: Uuid) -> Result<(), Error> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM user_two_factor
+            WHERE user_id = $1
+            "#,
+            user_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM two_factor_recovery_codes
+            WHERE user_id = $1
+            "#,
+            user_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Remembers the last accepted TOTP step. Returns `false` if an equal or
+    /// later step was already used, i.e. the code is being replayed.
+    async fn record_totp_step(&self, user_id: Uuid, step: i64) -> Result<bool, Error> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE user_two_factor
+            SET last_used_step = $2
+            WHERE user_id = $1 AND (last_used_step IS NULL OR last_used_step < $2)
+            "#,
+            user_id,
+            step
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn consume_recovery_code(&self, user_id: Uuid, code_hash: &str) -> Result<bool, Error> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE two_factor_recovery_codes
+            SET used_at = NOW()
+            WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL
+            "#,
+            user_id,
+            code_hash
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn replace_recovery_codes(
+        &self,
+        user_id: Uuid,
+        recovery_code_hashes: Vec<String>,
+    ) -> Result<(), Error> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM two_factor_recovery_codes
+            WHERE user_id = $1
+            "#,
+            user_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO two_factor_recovery_codes (user_id, code_hash)
+            SELECT $1, UNNEST($2::TEXT[])
+            "#,
+            user_id,
+            &recovery_code_hashes
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+}
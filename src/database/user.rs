@@ -10,11 +10,11 @@ pub trait UserExt {
         &self,
         user_id: Uuid,
         email: String,
-        token: Uuid,
+        token_hash: &str,
         token_expires_at: DateTime<Utc>,
     ) -> Result<User, sqlx::Error>;
 
-    async fn verify_email_change(&self, token: Uuid) -> Result<(), sqlx::Error>;
+    async fn verify_email_change(&self, token_hash: &str) -> Result<bool, sqlx::Error>;
 }
 
 #[async_trait]
@@ -23,19 +23,19 @@ impl UserExt for DBClient {
         &self,
         user_id: Uuid,
         email: String,
-        token: Uuid,
+        token_hash: &str,
         token_expires_at: DateTime<Utc>,
     ) -> Result<User, sqlx::Error> {
         sqlx::query_as!(
             User,
             r#"
             UPDATE users
-            SET pending_email = $1, pending_email_token = $2::UUID, pending_email_expires_at = $3
+            SET pending_email = $1, pending_email_token_hash = $2, pending_email_expires_at = $3
             WHERE id = $4
             RETURNING *
             "#,
             email,
-            token,
+            token_hash,
             token_expires_at,
             user_id
         )
@@ -43,18 +43,20 @@ impl UserExt for DBClient {
         .await
     }
 
-    async fn verify_email_change(&self, token: Uuid) -> Result<(), sqlx::Error> {
-        sqlx::query!(
+    /// Applies the pending email change the token belongs to. Clearing the
+    /// token in the same statement means it can only be used once.
+    async fn verify_email_change(&self, token_hash: &str) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
             r#"
             UPDATE users
-            SET email = pending_email, pending_email = NULL, pending_email_token = NULL, pending_email_expires_at = NULL
-            WHERE pending_email_token = $1
+            SET email = pending_email, pending_email = NULL, pending_email_token_hash = NULL, pending_email_expires_at = NULL
+            WHERE pending_email_token_hash = $1 AND pending_email_expires_at > NOW()
             "#,
-            token
+            token_hash
         )
         .execute(&self.pool)
         .await?;
 
-        Ok(())
+        Ok(result.rows_affected() > 0)
     }
 }
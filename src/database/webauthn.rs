@@ -0,0 +1,220 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::Error;
+use uuid::Uuid;
+
+use crate::{
+    database::DBClient,
+    models::{WebauthnChallenge, WebauthnCredential},
+    utils::webauthn::VerifiedRegistration,
+};
+
+#[async_trait]
+pub trait WebauthnExt {
+    async fn save_webauthn_challenge(
+        &self,
+        user_id: Option<Uuid>,
+        ceremony: &str,
+        challenge: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Uuid, Error>;
+
+    async fn take_webauthn_challenge(
+        &self,
+        challenge_id: Uuid,
+        ceremony: &str,
+    ) -> Result<Option<WebauthnChallenge>, Error>;
+
+    async fn save_passkey(
+        &self,
+        user_id: Uuid,
+        credential: &VerifiedRegistration,
+        transports: &[String],
+        name: &str,
+    ) -> Result<WebauthnCredential, Error>;
+
+    async fn get_passkey_by_credential_id(
+        &self,
+        credential_id: &str,
+    ) -> Result<Option<WebauthnCredential>, Error>;
+
+    async fn get_user_passkeys(&self, user_id: Uuid) -> Result<Vec<WebauthnCredential>, Error>;
+
+    async fn record_passkey_use(&self, passkey_id: Uuid, sign_count: i64) -> Result<(), Error>;
+
+    async fn rename_passkey(
+        &self,
+        user_id: Uuid,
+        passkey_id: Uuid,
+        name: &str,
+    ) -> Result<Option<WebauthnCredential>, Error>;
+
+    async fn delete_passkey(&self, user_id: Uuid, passkey_id: Uuid) -> Result<bool, Error>;
+}
+
+#[async_trait]
+impl WebauthnExt for DBClient {
+    async fn save_webauthn_challenge(
+        &self,
+        user_id: Option<Uuid>,
+        ceremony: &str,
+        challenge: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Uuid, Error> {
+        // Abandoned ceremonies are never taken, so clear them out here.
+        sqlx::query!(
+            r#"
+            DELETE FROM webauthn_challenges
+            WHERE expires_at < NOW()
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query_scalar!(
+            r#"
+            INSERT INTO webauthn_challenges (user_id, ceremony, challenge, expires_at)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id
+            "#,
+            user_id,
+            ceremony,
+            challenge,
+            expires_at
+        )
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    /// Consumes a pending ceremony so its challenge can only be answered once.
+    async fn take_webauthn_challenge(
+        &self,
+        challenge_id: Uuid,
+        ceremony: &str,
+    ) -> Result<Option<WebauthnChallenge>, Error> {
+        sqlx::query_as!(
+            WebauthnChallenge,
+            r#"
+            DELETE FROM webauthn_challenges
+            WHERE id = $1 AND ceremony = $2 AND expires_at > NOW()
+            RETURNING id, user_id, ceremony, challenge, expires_at, created_at
+            "#,
+            challenge_id,
+            ceremony
+        )
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    async fn save_passkey(
+        &self,
+        user_id: Uuid,
+        credential: &VerifiedRegistration,
+        transports: &[String],
+        name: &str,
+    ) -> Result<WebauthnCredential, Error> {
+        sqlx::query_as!(
+            WebauthnCredential,
+            r#"
+            INSERT INTO webauthn_credentials (user_id, credential_id, public_key, algorithm, sign_count, transports, aaguid, name)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            RETURNING id, user_id, credential_id, public_key, algorithm, sign_count, transports, aaguid, name, created_at, last_used_at
+            "#,
+            user_id,
+            credential.credential_id,
+            credential.public_key,
+            credential.algorithm,
+            i64::from(credential.sign_count),
+            transports,
+            credential.aaguid,
+            name
+        )
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    async fn get_passkey_by_credential_id(
+        &self,
+        credential_id: &str,
+    ) -> Result<Option<WebauthnCredential>, Error> {
+        sqlx::query_as!(
+            WebauthnCredential,
+            r#"
+            SELECT id, user_id, credential_id, public_key, algorithm, sign_count, transports, aaguid, name, created_at, last_used_at
+            FROM webauthn_credentials
+            WHERE credential_id = $1
+            "#,
+            credential_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    async fn get_user_passkeys(&self, user_id: Uuid) -> Result<Vec<WebauthnCredential>, Error> {
+        sqlx::query_as!(
+            WebauthnCredential,
+            r#"
+            SELECT id, user_id, credential_id, public_key, algorithm, sign_count, transports, aaguid, name, created_at, last_used_at
+            FROM webauthn_credentials
+            WHERE user_id = $1
+            ORDER BY created_at
+            "#,
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    async fn record_passkey_use(&self, passkey_id: Uuid, sign_count: i64) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+            UPDATE webauthn_credentials
+            SET sign_count = $2, last_used_at = NOW()
+            WHERE id = $1
+            "#,
+            passkey_id,
+            sign_count
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn rename_passkey(
+        &self,
+        user_id: Uuid,
+        passkey_id: Uuid,
+        name: &str,
+    ) -> Result<Option<WebauthnCredential>, Error> {
+        sqlx::query_as!(
+            WebauthnCredential,
+            r#"
+            UPDATE webauthn_credentials
+            SET name = $3
+            WHERE id = $1 AND user_id = $2
+            RETURNING id, user_id, credential_id, public_key, algorithm, sign_count, transports, aaguid, name, created_at, last_used_at
+            "#,
+            passkey_id,
+            user_id,
+            name
+        )
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    async fn delete_passkey(&self, user_id: Uuid, passkey_id: Uuid) -> Result<bool, Error> {
+        let deleted = sqlx::query!(
+            r#"
+            DELETE FROM webauthn_credentials
+            WHERE id = $1 AND user_id = $2
+            "#,
+            passkey_id,
+            user_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(deleted.rows_affected() > 0)
+    }
+}
@@ -1,5 +1,7 @@
+use std::{collections::HashMap, time::Duration};
+
 use async_trait::async_trait;
-use sqlx::QueryBuilder;
+use sqlx::{QueryBuilder, postgres::PgListener};
 use uuid::Uuid;
 
 use crate::{
@@ -29,6 +31,8 @@ pub trait WorkspaceExt {
         &self,
         user_id: Uuid,
     ) -> Result<Vec<WorkspaceListDto>, sqlx::Error>;
+    async fn get_permissions_version(&self, workspace_id: Uuid)
+    -> Result<Option<i64>, sqlx::Error>;
 }
 
 #[async_trait]
@@ -178,4 +182,177 @@ impl WorkspaceExt for DBClient {
 
         Ok(workspace)
     }
+
+    async fn get_permissions_version(
+        &self,
+        workspace_id: Uuid,
+    ) -> Result<Option<i64>, sqlx::Error> {
+        let generation = {
+            let cache = self.permissions_versions.lock().unwrap();
+            if let Some(version) = cache.get(workspace_id) {
+                return Ok(version);
+            }
+            cache.generation
+        };
+
+        let version = sqlx::query_scalar!(
+            r#"
+            SELECT permissions_version FROM workspaces WHERE id = $1
+            "#,
+            workspace_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        if let Some(version) = version {
+            self.permissions_versions.lock().unwrap().store(
+                generation,
+                workspace_id,
+                Some(version),
+            );
+        }
+
+        Ok(version)
+    }
+}
+
+const PERMISSIONS_VERSION_CHANNEL: &str = "workspace_permissions_version";
+const LISTENER_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Permissions versions already read from the database. Entries are only
+/// kept while a listener receives the `workspace_permissions_version`
+/// notifications that replace them.
+#[derive(Debug, Default)]
+pub struct PermissionsVersionCache {
+    /// `None` marks a deleted workspace.
+    versions: HashMap<Uuid, Option<i64>>,
+    listening: bool,
+    /// Moves on whenever notifications may have been missed, so reads that
+    /// started before cannot store what they saw.
+    generation: u64,
+}
+
+impl PermissionsVersionCache {
+    fn get(&self, workspace_id: Uuid) -> Option<Option<i64>> {
+        self.versions.get(&workspace_id).copied()
+    }
+
+    fn store(&mut self, generation: u64, workspace_id: Uuid, version: Option<i64>) {
+        if !self.listening || generation != self.generation {
+            return;
+        }
+
+        // Versions only grow and deleted workspaces stay deleted, so a read
+        // that lost a race with a notification cannot undo it.
+        let entry = self.versions.entry(workspace_id).or_insert(version);
+        *entry = match (*entry, version) {
+            (Some(cached), Some(version)) => Some(cached.max(version)),
+            _ => None,
+        };
+    }
+
+    fn reset(&mut self, listening: bool) {
+        self.versions.clear();
+        self.listening = listening;
+        self.generation += 1;
+    }
+
+    /// Applies a `<workspace id>:<version>` payload; an empty version means
+    /// the workspace was deleted.
+    fn apply_notification(&mut self, payload: &str) {
+        let Some((workspace_id, version)) = payload.split_once(':') else {
+            return;
+        };
+        let Ok(workspace_id) = Uuid::parse_str(workspace_id) else {
+            return;
+        };
+        let version = match version {
+            "" => None,
+            version => match version.parse() {
+                Ok(version) => Some(version),
+                Err(_) => return,
+            },
+        };
+
+        self.store(self.generation, workspace_id, version);
+    }
+}
+
+impl DBClient {
+    /// Starts caching permissions versions, which every workspace request
+    /// checks. Changes reach the cache through Postgres notifications; while
+    /// the listener is disconnected, reads go to the database.
+    pub async fn watch_permissions_versions(&self) -> Result<(), sqlx::Error> {
+        let mut listener = self.listen_for_permissions_versions().await?;
+        let db_client = self.clone();
+
+        tokio::spawn(async move {
+            loop {
+                match listener.try_recv().await {
+                    Ok(Some(notification)) => db_client
+                        .permissions_versions
+                        .lock()
+                        .unwrap()
+                        .apply_notification(notification.payload()),
+                    Ok(None) | Err(_) => {
+                        db_client.permissions_versions.lock().unwrap().reset(false);
+                        listener = loop {
+                            match db_client.listen_for_permissions_versions().await {
+                                Ok(listener) => break listener,
+                                Err(e) => {
+                                    tracing::warn!(
+                                        error = %e,
+                                        "failed to listen for permissions version changes"
+                                    );
+                                    tokio::time::sleep(LISTENER_RETRY_DELAY).await;
+                                }
+                            }
+                        };
+                    }
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    async fn listen_for_permissions_versions(&self) -> Result<PgListener, sqlx::Error> {
+        let mut listener = PgListener::connect_with(&self.pool).await?;
+        listener.listen(PERMISSIONS_VERSION_CHANNEL).await?;
+        self.permissions_versions.lock().unwrap().reset(true);
+        Ok(listener)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn permissions_version_cache_keeps_the_newest_version() {
+        let workspace_id = Uuid::new_v4();
+        let mut cache = PermissionsVersionCache::default();
+
+        cache.store(cache.generation, workspace_id, Some(1));
+        assert_eq!(
+            cache.get(workspace_id),
+            None,
+            "nothing is cached without a listener"
+        );
+
+        cache.reset(true);
+        let stale_read = cache.generation;
+        cache.apply_notification(&format!("{}:3", workspace_id));
+        cache.store(stale_read, workspace_id, Some(2));
+        assert_eq!(cache.get(workspace_id), Some(Some(3)));
+
+        cache.apply_notification(&format!("{}:", workspace_id));
+        cache.store(stale_read, workspace_id, Some(4));
+        assert_eq!(cache.get(workspace_id), Some(None));
+
+        let before_reconnect = cache.generation;
+        cache.reset(true);
+        cache.store(before_reconnect, workspace_id, Some(5));
+        assert_eq!(cache.get(workspace_id), None);
+    }
 }
@@ -81,8 +81,14 @@ impl WorkspaceUserExt for DBClient {
             r#"
                 SELECT u.id as user_id,
                     u.name as user_name,
-                    u.email as user_email,
-                    r.name as role_name
+                    CASE
+                        WHEN u.service_account_workspace_id IS NULL THEN u.email
+                    END as user_email,
+                    r.name as role_name,
+                    CASE
+                        WHEN u.service_account_workspace_id IS NULL THEN 'user'
+                        ELSE 'service_account'
+                    END as "account_type!"
                 FROM workspace_users wu
                     JOIN users u ON wu.user_id = u.id
                     JOIN roles r ON wu.role_id = r.id
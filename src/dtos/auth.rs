@@ -1,37 +1,8 @@
-use regex::Regex;
 use serde::{Deserialize, Serialize};
-use validator::{Validate, ValidationError};
+use validator::Validate;
 
 use crate::dtos::{user::FilterUserDto, workspace::WorkspaceWithRoleAndPermissions};
 
-pub fn validate_password_complexity(password: &str) -> Result<(), ValidationError> {
-    let uppercase = Regex::new(r"[A-Z]").unwrap();
-    let lowercase = Regex::new(r"[a-z]").unwrap();
-    let number = Regex::new(r"[0-9]").unwrap();
-    let special = Regex::new(r"[^A-Za-z0-9]").unwrap();
-    if !uppercase.is_match(password) {
-        return Err(ValidationError::new(
-            "Password must contain at least one uppercase letter",
-        ));
-    }
-    if !lowercase.is_match(password) {
-        return Err(ValidationError::new(
-            "Password must contain at least one lowercase letter",
-        ));
-    }
-    if !number.is_match(password) {
-        return Err(ValidationError::new(
-            "Password must contain at least one number",
-        ));
-    }
-    if !special.is_match(password) {
-        return Err(ValidationError::new(
-            "Password must contain at least one special character",
-        ));
-    }
-    Ok(())
-}
-
 #[derive(Debug, Clone, Validate, Serialize, Deserialize)]
 pub struct RegisterUserDto {
     #[validate(length(min = 1, message = "Name is required"))]
@@ -47,24 +18,10 @@ pub struct RegisterUserDto {
     )]
     pub email: String,
 
-    #[validate(
-        length(
-            min = 8,
-            max = 64,
-            message = "Password must be between 8 and 64 characters"
-        ),
-        custom(function = "validate_password_complexity",)
-    )]
+    #[validate(length(min = 1, message = "Password is required"))]
     pub password: String,
 
-    #[validate(
-        length(
-            min = 8,
-            max = 64,
-            message = "Password must be between 8 and 64 characters"
-        ),
-        must_match(other = "password", message = "Passwords do not match")
-    )]
+    #[validate(must_match(other = "password", message = "Passwords do not match"))]
     #[serde(rename = "passwordConfirm")]
     pub password_confirm: String,
 }
@@ -81,14 +38,7 @@ pub struct LoginUserDto {
     )]
     pub email: String,
 
-    #[validate(
-        length(
-            min = 8,
-            max = 64,
-            message = "Password must be between 8 and 64 characters"
-        ),
-        custom(function = "validate_password_complexity",)
-    )]
+    #[validate(length(min = 1, message = "Password is required"))]
     pub password: String,
 }
 
@@ -98,6 +48,19 @@ pub struct VerifyEmailQueryDto {
     pub token: String,
 }
 
+#[derive(Debug, Default, Clone, Validate, Serialize, Deserialize)]
+pub struct ResendVerificationDto {
+    #[validate(
+        length(
+            min = 5,
+            max = 254,
+            message = "Email must be between 5 and 254 characters"
+        ),
+        email(message = "Invalid email address")
+    )]
+    pub email: String,
+}
+
 #[derive(Debug, Default, Clone, Validate, Serialize, Deserialize)]
 pub struct ForgotPasswordDto {
     #[validate(
@@ -116,24 +79,10 @@ pub struct ResetPasswordDto {
     #[validate(length(min = 1, message = "Token is required"))]
     pub token: String,
 
-    #[validate(
-        length(
-            min = 8,
-            max = 64,
-            message = "Password must be between 8 and 64 characters"
-        ),
-        custom(function = "validate_password_complexity",)
-    )]
+    #[validate(length(min = 1, message = "Password is required"))]
     pub password: String,
 
-    #[validate(
-        length(
-            min = 8,
-            max = 64,
-            message = "Password must be between 8 and 64 characters"
-        ),
-        must_match(other = "password", message = "Passwords do not match")
-    )]
+    #[validate(must_match(other = "password", message = "Passwords do not match"))]
     #[serde(rename = "passwordConfirm")]
     pub password_confirm: String,
 }
@@ -142,6 +91,11 @@ pub struct ResetPasswordDto {
 pub struct LoginResponse {
     pub status: &'static str,
     pub token: String,
+    #[serde(rename = "refreshToken")]
+    pub refresh_token: String,
+    /// Echoed in the `X-CSRF-Token` header by cookie-authenticated requests.
+    #[serde(rename = "csrfToken")]
+    pub csrf_token: String,
     pub data: UserDataResponse,
 }
 
@@ -150,3 +104,79 @@ pub struct UserDataResponse {
     pub user: FilterUserDto,
     pub workspace: Option<WorkspaceWithRoleAndPermissions>,
 }
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RefreshTokenDto {
+    #[serde(rename = "refreshToken")]
+    pub refresh_token: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenResponse {
+    pub status: &'static str,
+    pub token: String,
+    #[serde(rename = "refreshToken")]
+    pub refresh_token: String,
+    /// Echoed in the `X-CSRF-Token` header by cookie-authenticated requests.
+    #[serde(rename = "csrfToken")]
+    pub csrf_token: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CsrfTokenResponse {
+    pub status: &'static str,
+    #[serde(rename = "csrfToken")]
+    pub csrf_token: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TwoFactorChallengeResponse {
+    pub status: &'static str,
+    #[serde(rename = "challengeToken")]
+    pub challenge_token: String,
+}
+
+#[derive(Debug, Clone, Validate, Serialize, Deserialize)]
+pub struct TwoFactorLoginDto {
+    #[validate(length(min = 1, message = "Challenge token is required"))]
+    #[serde(rename = "challengeToken")]
+    pub challenge_token: String,
+
+    pub code: Option<String>,
+
+    #[serde(rename = "recoveryCode")]
+    pub recovery_code: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OAuthCallbackQueryDto {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, Validate, Serialize, Deserialize)]
+pub struct MagicLinkDto {
+    #[validate(
+        length(
+            min = 5,
+            max = 254,
+            message = "Email must be between 5 and 254 characters"
+        ),
+        email(message = "Invalid email address")
+    )]
+    pub email: String,
+}
+
+/// Browsers send the `token` from the emailed link; mobile clients send the
+/// `email` and the 6-digit `code` instead.
+#[derive(Debug, Clone, Validate, Serialize, Deserialize)]
+pub struct ConsumeMagicLinkDto {
+    pub token: Option<String>,
+
+    #[validate(email(message = "Invalid email address"))]
+    pub email: Option<String>,
+
+    #[validate(length(equal = 6, message = "Code must be 6 digits"))]
+    pub code: Option<String>,
+}
@@ -3,7 +3,9 @@ use serde::{Deserialize, Serialize};
 pub mod auth;
 pub mod permissions;
 pub mod role;
+pub mod service_account;
 pub mod user;
+pub mod webauthn;
 pub mod workspace;
 pub mod workspace_user;
 
@@ -0,0 +1,88 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::models::ServiceAccountKey;
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ServiceAccountDto {
+    pub id: Uuid,
+    pub name: String,
+    pub role_name: String,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServiceAccountKeyDto {
+    pub id: Uuid,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl ServiceAccountKeyDto {
+    pub fn from_key(key: &ServiceAccountKey) -> Self {
+        Self {
+            id: key.id,
+            expires_at: key.expires_at,
+            last_used_at: key.last_used_at,
+            created_at: key.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateServiceAccountDto {
+    #[validate(length(min = 1, max = 64, message = "Name must be 1-64 characters"))]
+    pub name: String,
+
+    #[validate(length(min = 1, message = "Role name is required"))]
+    pub role_name: String,
+
+    /// Expiry of the first API key.
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateServiceAccountKeyDto {
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServiceAccountList {
+    pub service_accounts: Vec<ServiceAccountDto>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServiceAccountListResponse {
+    pub status: &'static str,
+    pub data: ServiceAccountList,
+}
+
+/// Returned when a service account is created, with its first API key.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServiceAccountCreatedResponse {
+    pub status: &'static str,
+    pub key: String,
+    pub data: ServiceAccountDto,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServiceAccountKeyList {
+    pub keys: Vec<ServiceAccountKeyDto>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServiceAccountKeyListResponse {
+    pub status: &'static str,
+    pub data: ServiceAccountKeyList,
+}
+
+/// The only response that contains a key itself.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServiceAccountKeyCreatedResponse {
+    pub status: &'static str,
+    pub key: String,
+    pub data: ServiceAccountKeyDto,
+}
@@ -3,7 +3,7 @@ use serde::{self, Deserialize, Serialize};
 use uuid::Uuid;
 use validator::Validate;
 
-use crate::{dtos::auth::validate_password_complexity, models::User};
+use crate::models::{PersonalAccessToken, User, UserSession, WebauthnCredential};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FilterUserDto {
@@ -35,6 +35,15 @@ pub struct UserData {
     pub user: FilterUserDto,
 }
 
+/// Carries a fresh access token, since the change rejects every token issued
+/// before it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PasswordUpdateResponse {
+    pub status: &'static str,
+    pub message: String,
+    pub token: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserResponse {
     pub status: &'static str,
@@ -43,37 +52,15 @@ pub struct UserResponse {
 
 #[derive(Debug, Clone, Serialize, Deserialize, Validate)]
 pub struct UserPasswordUpdate {
-    #[validate(
-        length(
-            min = 8,
-            max = 64,
-            message = "Password must be between 8 and 64 characters"
-        ),
-        custom(function = "validate_password_complexity",)
-    )]
+    #[validate(length(min = 1, message = "Current password is required"))]
     #[serde(rename = "currentPassword")]
     pub current_password: String,
 
-    #[validate(
-        length(
-            min = 8,
-            max = 64,
-            message = "Password must be between 8 and 64 characters"
-        ),
-        custom(function = "validate_password_complexity",)
-    )]
+    #[validate(length(min = 1, message = "New password is required"))]
     #[serde(rename = "newPassword")]
     pub new_password: String,
 
-    #[validate(
-        length(
-            min = 8,
-            max = 64,
-            message = "Password must be between 8 and 64 characters"
-        ),
-        must_match(other = "new_password", message = "Passwords do not match"),
-        custom(function = "validate_password_complexity",)
-    )]
+    #[validate(must_match(other = "new_password", message = "Passwords do not match"))]
     #[serde(rename = "confirmPassword")]
     pub confirm_password: String,
 }
@@ -96,3 +83,187 @@ pub struct UserEmailChangeVerificationDto {
     #[validate(length(min = 1, message = "Token is required"))]
     pub token: String,
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionDto {
+    pub id: Uuid,
+    #[serde(rename = "userAgent")]
+    pub user_agent: Option<String>,
+    #[serde(rename = "ipAddress")]
+    pub ip_address: Option<String>,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    #[serde(rename = "lastSeenAt")]
+    pub last_seen_at: DateTime<Utc>,
+    pub current: bool,
+}
+
+impl SessionDto {
+    pub fn from_session(session: &UserSession, current_session_id: Uuid) -> Self {
+        Self {
+            id: session.id,
+            user_agent: session.user_agent.clone(),
+            ip_address: session.ip_address.clone(),
+            created_at: session.created_at,
+            last_seen_at: session.last_seen_at,
+            current: session.id == current_session_id,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionList {
+    pub sessions: Vec<SessionDto>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionListResponse {
+    pub status: &'static str,
+    pub data: SessionList,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TwoFactorSetupData {
+    pub secret: String,
+    #[serde(rename = "otpauthUrl")]
+    pub otpauth_url: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TwoFactorSetupResponse {
+    pub status: &'static str,
+    pub data: TwoFactorSetupData,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct TwoFactorConfirmDto {
+    #[validate(length(equal = 6, message = "Code must be 6 digits"))]
+    pub code: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TwoFactorVerifyDto {
+    pub code: Option<String>,
+
+    #[serde(rename = "recoveryCode")]
+    pub recovery_code: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecoveryCodesData {
+    #[serde(rename = "recoveryCodes")]
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecoveryCodesResponse {
+    pub status: &'static str,
+    pub data: RecoveryCodesData,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PasskeyDto {
+    pub id: Uuid,
+    pub name: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    #[serde(rename = "lastUsedAt")]
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+impl PasskeyDto {
+    pub fn from_passkey(passkey: &WebauthnCredential) -> Self {
+        Self {
+            id: passkey.id,
+            name: passkey.name.clone(),
+            created_at: passkey.created_at,
+            last_used_at: passkey.last_used_at,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PasskeyResponse {
+    pub status: &'static str,
+    pub data: PasskeyDto,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PasskeyList {
+    pub passkeys: Vec<PasskeyDto>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PasskeyListResponse {
+    pub status: &'static str,
+    pub data: PasskeyList,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct RenamePasskeyDto {
+    #[validate(length(min = 1, max = 64, message = "Passkey name must be 1-64 characters"))]
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct CreatePersonalAccessTokenDto {
+    #[validate(length(min = 1, max = 64, message = "Token name must be 1-64 characters"))]
+    pub name: String,
+
+    #[serde(rename = "workspaceId")]
+    pub workspace_id: Uuid,
+
+    #[validate(length(min = 1, message = "At least one permission is required"))]
+    pub permissions: Vec<String>,
+
+    #[serde(rename = "expiresAt")]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PersonalAccessTokenDto {
+    pub id: Uuid,
+    pub name: String,
+    #[serde(rename = "workspaceId")]
+    pub workspace_id: Uuid,
+    pub permissions: Vec<String>,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(rename = "lastUsedAt")]
+    pub last_used_at: Option<DateTime<Utc>>,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+}
+
+impl PersonalAccessTokenDto {
+    pub fn from_token(token: &PersonalAccessToken) -> Self {
+        Self {
+            id: token.id,
+            name: token.name.clone(),
+            workspace_id: token.workspace_id,
+            permissions: token.permissions.clone(),
+            expires_at: token.expires_at,
+            last_used_at: token.last_used_at,
+            created_at: token.created_at,
+        }
+    }
+}
+
+/// The only response that contains the token itself.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PersonalAccessTokenCreatedResponse {
+    pub status: &'static str,
+    pub token: String,
+    pub data: PersonalAccessTokenDto,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PersonalAccessTokenList {
+    pub tokens: Vec<PersonalAccessTokenDto>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PersonalAccessTokenListResponse {
+    pub status: &'static str,
+    pub data: PersonalAccessTokenList,
+}
@@ -0,0 +1,140 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelyingPartyEntity {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserEntity {
+    pub id: String,
+    pub name: String,
+    #[serde(rename = "displayName")]
+    pub display_name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CredentialParameter {
+    #[serde(rename = "type")]
+    pub credential_type: &'static str,
+    pub alg: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CredentialDescriptor {
+    #[serde(rename = "type")]
+    pub credential_type: &'static str,
+    pub id: String,
+    pub transports: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthenticatorSelection {
+    #[serde(rename = "residentKey")]
+    pub resident_key: &'static str,
+    /// The Level 1 spelling of `residentKey: "required"`, for older browsers.
+    #[serde(rename = "requireResidentKey")]
+    pub require_resident_key: bool,
+    #[serde(rename = "userVerification")]
+    pub user_verification: &'static str,
+}
+
+/// Options for `navigator.credentials.create({ publicKey })`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreationOptions {
+    pub challenge: String,
+    pub rp: RelyingPartyEntity,
+    pub user: UserEntity,
+    #[serde(rename = "pubKeyCredParams")]
+    pub pub_key_cred_params: Vec<CredentialParameter>,
+    pub timeout: i64,
+    #[serde(rename = "excludeCredentials")]
+    pub exclude_credentials: Vec<CredentialDescriptor>,
+    #[serde(rename = "authenticatorSelection")]
+    pub authenticator_selection: AuthenticatorSelection,
+    pub attestation: &'static str,
+}
+
+/// Options for `navigator.credentials.get({ publicKey })`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RequestOptions {
+    pub challenge: String,
+    #[serde(rename = "rpId")]
+    pub rp_id: String,
+    #[serde(rename = "allowCredentials")]
+    pub allow_credentials: Vec<CredentialDescriptor>,
+    pub timeout: i64,
+    #[serde(rename = "userVerification")]
+    pub user_verification: &'static str,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CeremonyData<T> {
+    #[serde(rename = "ceremonyId")]
+    pub ceremony_id: Uuid,
+    #[serde(rename = "publicKey")]
+    pub public_key: T,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CeremonyResponse<T> {
+    pub status: &'static str,
+    pub data: CeremonyData<T>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttestationResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    #[serde(rename = "attestationObject")]
+    pub attestation_object: String,
+    #[serde(default)]
+    pub transports: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegistrationCredential {
+    #[serde(rename = "rawId")]
+    pub raw_id: String,
+    pub response: AttestationResponse,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct FinishRegistrationDto {
+    #[serde(rename = "ceremonyId")]
+    pub ceremony_id: Uuid,
+
+    #[validate(length(min = 1, max = 64, message = "Passkey name must be 1-64 characters"))]
+    pub name: Option<String>,
+
+    pub credential: RegistrationCredential,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AssertionResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    #[serde(rename = "authenticatorData")]
+    pub authenticator_data: String,
+    pub signature: String,
+    #[serde(rename = "userHandle")]
+    pub user_handle: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthenticationCredential {
+    #[serde(rename = "rawId")]
+    pub raw_id: String,
+    pub response: AssertionResponse,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FinishAuthenticationDto {
+    #[serde(rename = "ceremonyId")]
+    pub ceremony_id: Uuid,
+
+    pub credential: AuthenticationCredential,
+}
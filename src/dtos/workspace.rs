@@ -3,6 +3,8 @@ use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use validator::Validate;
 
+use crate::models::WorkspaceSecurityPolicy;
+
 #[derive(Debug, Clone, Serialize, Deserialize, Validate)]
 pub struct WorkspaceCreateDto {
     #[validate(length(min = 1, message = "Workspace name is required"))]
@@ -81,3 +83,39 @@ pub struct WorkspaceListResponse {
 pub struct WorkspaceList {
     pub workspaces: Vec<WorkspaceListDto>,
 }
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Validate)]
+pub struct SecurityPolicyDto {
+    #[serde(rename = "requireTwoFactor")]
+    pub require_two_factor: bool,
+    #[serde(rename = "maxSessionAgeMinutes")]
+    #[validate(range(min = 1, message = "Maximum session age must be at least 1 minute"))]
+    pub max_session_age_minutes: Option<i32>,
+    #[serde(rename = "idleTimeoutMinutes")]
+    #[validate(range(min = 1, message = "Idle timeout must be at least 1 minute"))]
+    pub idle_timeout_minutes: Option<i32>,
+}
+
+impl SecurityPolicyDto {
+    pub fn from_policy(policy: &WorkspaceSecurityPolicy) -> Self {
+        Self {
+            require_two_factor: policy.require_two_factor,
+            max_session_age_minutes: policy.max_session_age_minutes,
+            idle_timeout_minutes: policy.idle_timeout_minutes,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkspaceTokenResponse {
+    pub status: &'static str,
+    pub token: String,
+    #[serde(rename = "expiresIn")]
+    pub expires_in: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SecurityPolicyResponse {
+    pub status: &'static str,
+    pub data: SecurityPolicyDto,
+}
@@ -6,8 +6,11 @@ use validator::Validate;
 pub struct WorkspaceUserWithRoleDto {
     pub user_id: Uuid,
     pub user_name: String,
-    pub user_email: String,
+    /// `None` for service accounts, which have no real email.
+    pub user_email: Option<String>,
     pub role_name: String,
+    /// `"user"` or `"service_account"`.
+    pub account_type: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
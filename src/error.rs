@@ -2,7 +2,7 @@ use std::fmt;
 
 use axum::{
     Json,
-    http::StatusCode,
+    http::{StatusCode, header},
     response::{IntoResponse, Response},
 };
 use serde::{Deserialize, Serialize};
@@ -23,7 +23,6 @@ impl fmt::Display for ErrorResponse {
 pub enum ErrorMessage {
     EmptyPassword,
     ExceededMaxPasswordLength(usize),
-    PasswordTooShort(usize),
     InvalidHashFormat,
     HashingError,
     InvalidToken,
@@ -33,6 +32,25 @@ pub enum ErrorMessage {
     UserNoLongerExists,
     TokenNotProvided,
     PermissionDenied,
+    RefreshTokenReused,
+    SessionRevoked,
+    InvalidTwoFactorCode,
+    TwoFactorAlreadyEnabled,
+    TwoFactorNotEnabled,
+    TwoFactorEnrollmentRequired,
+    ReauthenticationRequired,
+    PasskeyVerificationFailed,
+    PasskeyAlreadyRegistered,
+    OAuthLoginFailed,
+    OAuthEmailNotVerified,
+    EmailNotVerified,
+    WorkspaceTokenOutdated,
+    TooManyLoginAttempts,
+    TooManyRequests,
+    ServerBusy,
+    PasswordRecentlyUsed,
+    PersonalAccessTokenNotAllowed,
+    CsrfTokenMismatch,
 }
 
 impl ToString for ErrorMessage {
@@ -46,7 +64,6 @@ impl ErrorMessage {
         match self {
             ErrorMessage::EmptyPassword => "EmptyPassword".to_string(),
             ErrorMessage::ExceededMaxPasswordLength(_) => "ExceededMaxPasswordLength".to_string(),
-            ErrorMessage::PasswordTooShort(_) => "PasswordTooShort".to_string(),
             ErrorMessage::InvalidHashFormat => "InvalidHashFormat".to_string(),
             ErrorMessage::HashingError => "HashingError".to_string(),
             ErrorMessage::InvalidToken => "InvalidToken".to_string(),
@@ -56,6 +73,27 @@ impl ErrorMessage {
             ErrorMessage::UserNoLongerExists => "UserNoLongerExists".to_string(),
             ErrorMessage::TokenNotProvided => "TokenNotProvided".to_string(),
             ErrorMessage::PermissionDenied => "PermissionDenied".to_string(),
+            ErrorMessage::RefreshTokenReused => "RefreshTokenReused".to_string(),
+            ErrorMessage::SessionRevoked => "SessionRevoked".to_string(),
+            ErrorMessage::InvalidTwoFactorCode => "InvalidTwoFactorCode".to_string(),
+            ErrorMessage::TwoFactorAlreadyEnabled => "TwoFactorAlreadyEnabled".to_string(),
+            ErrorMessage::TwoFactorNotEnabled => "TwoFactorNotEnabled".to_string(),
+            ErrorMessage::TwoFactorEnrollmentRequired => "TwoFactorEnrollmentRequired".to_string(),
+            ErrorMessage::ReauthenticationRequired => "ReauthenticationRequired".to_string(),
+            ErrorMessage::PasskeyVerificationFailed => "PasskeyVerificationFailed".to_string(),
+            ErrorMessage::PasskeyAlreadyRegistered => "PasskeyAlreadyRegistered".to_string(),
+            ErrorMessage::OAuthLoginFailed => "OAuthLoginFailed".to_string(),
+            ErrorMessage::OAuthEmailNotVerified => "OAuthEmailNotVerified".to_string(),
+            ErrorMessage::EmailNotVerified => "EmailNotVerified".to_string(),
+            ErrorMessage::WorkspaceTokenOutdated => "WorkspaceTokenOutdated".to_string(),
+            ErrorMessage::TooManyLoginAttempts => "TooManyLoginAttempts".to_string(),
+            ErrorMessage::TooManyRequests => "TooManyRequests".to_string(),
+            ErrorMessage::ServerBusy => "ServerBusy".to_string(),
+            ErrorMessage::PasswordRecentlyUsed => "PasswordRecentlyUsed".to_string(),
+            ErrorMessage::PersonalAccessTokenNotAllowed => {
+                "PersonalAccessTokenNotAllowed".to_string()
+            }
+            ErrorMessage::CsrfTokenMismatch => "CsrfTokenMismatch".to_string(),
         }
     }
 }
@@ -64,6 +102,7 @@ impl ErrorMessage {
 pub struct HttpError {
     pub status: StatusCode,
     pub message: String,
+    pub retry_after: Option<u64>,
 }
 
 impl HttpError {
@@ -71,6 +110,7 @@ impl HttpError {
         Self {
             status: status,
             message: message.into(),
+            retry_after: None,
         }
     }
 
@@ -86,6 +126,26 @@ impl HttpError {
         Self::new(StatusCode::UNAUTHORIZED, message)
     }
 
+    pub fn forbidden(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::FORBIDDEN, message)
+    }
+
+    /// 429 with a `Retry-After` header telling the client how many seconds to wait.
+    pub fn too_many_requests(message: impl Into<String>, retry_after: u64) -> Self {
+        Self {
+            retry_after: Some(retry_after),
+            ..Self::new(StatusCode::TOO_MANY_REQUESTS, message)
+        }
+    }
+
+    /// 503 with a `Retry-After` header, for load the server sheds on purpose.
+    pub fn service_unavailable(message: impl Into<String>, retry_after: u64) -> Self {
+        Self {
+            retry_after: Some(retry_after),
+            ..Self::new(StatusCode::SERVICE_UNAVAILABLE, message)
+        }
+    }
+
     pub fn unique_constraint_violation(message: impl Into<String>) -> Self {
         Self::new(StatusCode::CONFLICT, message)
     }
@@ -96,7 +156,13 @@ impl HttpError {
             message: self.message,
         });
 
-        (self.status, json_response).into_response()
+        let mut response = (self.status, json_response).into_response();
+        if let Some(retry_after) = self.retry_after {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, retry_after.into());
+        }
+        response
     }
 }
 
@@ -2,38 +2,275 @@ use std::sync::Arc;
 
 use axum::{
     Extension, Json,
-    extract::Query,
-    http::{HeaderMap, StatusCode, header},
+    extract::{OriginalUri, Query},
+    http::{HeaderMap, Method, StatusCode},
     response::{IntoResponse, Redirect},
 };
-use axum_extra::extract::cookie::Cookie;
-use chrono::{Duration, Utc};
+use axum_extra::extract::CookieJar;
+use chrono::{DateTime, Duration, Utc};
 use uuid::Uuid;
 use validator::Validate;
 
 use crate::{
     AppState,
-    database::{auth::AuthExt, workspace::WorkspaceExt},
+    config::cookie_config::SessionCookie,
+    database::{
+        auth::AuthExt, login_attempt::LoginAttemptExt,
+        personal_access_token::PersonalAccessTokenExt, refresh_token::RefreshTokenExt,
+        revoked_token::RevokedTokenExt, session::SessionExt, two_factor::TwoFactorExt,
+        workspace::WorkspaceExt,
+    },
     dtos::{
         Response,
         auth::{
-            ForgotPasswordDto, LoginResponse, LoginUserDto, RegisterUserDto, ResetPasswordDto,
+            ConsumeMagicLinkDto, CsrfTokenResponse, ForgotPasswordDto, LoginResponse, LoginUserDto,
+            MagicLinkDto, RefreshTokenDto, RegisterUserDto, ResendVerificationDto,
+            ResetPasswordDto, TokenResponse, TwoFactorChallengeResponse, TwoFactorLoginDto,
             UserDataResponse, VerifyEmailQueryDto,
         },
         user::FilterUserDto,
     },
     error::{ErrorMessage, HttpError},
-    mail::mail::{send_password_reset_email, send_verification_email, send_welcome_email},
-    utils::{password, token},
+    handlers::{oauth::oauth_handler, webauthn::webauthn_handler},
+    mail::mail::{
+        send_account_locked_email, send_magic_link_email, send_password_reset_email,
+        send_verification_email, send_welcome_email,
+    },
+    middleware::jwt_auth_middleware::{JwtAuthMiddleware, auth_middleware, check_email_verified},
+    models::User,
+    utils::{
+        client_info::ClientInfo,
+        csrf,
+        login_throttle::{self, ThrottlePolicy},
+        password, token, totp,
+    },
 };
 
 pub fn auth_handler() -> axum::Router {
     axum::Router::new()
         .route("/register", axum::routing::post(register))
         .route("/login", axum::routing::post(login))
+        .route("/login/2fa", axum::routing::post(login_two_factor))
         .route("/verify", axum::routing::get(verify_email))
+        .route(
+            "/resend-verification",
+            axum::routing::post(resend_verification),
+        )
         .route("/forgot-password", axum::routing::post(forgot_password))
         .route("/reset-password", axum::routing::post(reset_password))
+        .route("/magic-link", axum::routing::post(request_magic_link))
+        .route(
+            "/magic-link/consume",
+            axum::routing::post(consume_magic_link),
+        )
+        .route("/refresh", axum::routing::post(refresh))
+        .route(
+            "/csrf-token",
+            axum::routing::get(get_csrf_token).layer(axum::middleware::from_fn(auth_middleware)),
+        )
+        .route(
+            "/logout",
+            axum::routing::post(logout).layer(axum::middleware::from_fn(auth_middleware)),
+        )
+        .nest("/webauthn", webauthn_handler())
+        .nest("/oauth", oauth_handler())
+}
+
+const TWO_FACTOR_CHALLENGE_MAXAGE_SECONDS: i64 = 5 * 60;
+const MAGIC_LINK_MAXAGE_MINUTES: i64 = 15;
+const MAGIC_LINK_CODE_DIGITS: u32 = 6;
+const MAGIC_LINK_MAX_CODE_ATTEMPTS: i32 = 5;
+const EMAIL_VERIFICATION_MAXAGE_HOURS: i64 = 24;
+const VERIFICATION_RESEND_COOLDOWN_SECONDS: i64 = 60;
+
+pub struct AuthTokens {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub csrf_token: String,
+}
+
+pub fn create_access_token(
+    app_state: &AppState,
+    user_id: Uuid,
+    session_id: Uuid,
+) -> Result<String, HttpError> {
+    token::create_token(
+        &user_id.to_string(),
+        &session_id.to_string(),
+        &app_state.jwt_config,
+        app_state.env.jwt_maxage * 60,
+    )
+    .map_err(|e| HttpError::server_error(e.to_string()))
+}
+
+/// Starts a new session and issues its access token together with the first
+/// refresh token of a new family.
+pub async fn issue_auth_tokens(
+    app_state: &AppState,
+    user_id: Uuid,
+    client_info: &ClientInfo,
+) -> Result<AuthTokens, HttpError> {
+    let session = app_state
+        .db_client
+        .create_session(
+            user_id,
+            client_info.user_agent.as_deref(),
+            client_info.ip_address.as_deref(),
+        )
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let access_token = create_access_token(app_state, user_id, session.id)?;
+
+    let refresh_token = token::generate_opaque_token();
+    let expires_at = Utc::now() + Duration::days(app_state.env.refresh_token_maxage);
+
+    app_state
+        .db_client
+        .save_refresh_token(
+            user_id,
+            session.id,
+            Uuid::new_v4(),
+            &token::hash_opaque_token(&refresh_token),
+            expires_at,
+        )
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    Ok(AuthTokens {
+        access_token,
+        refresh_token,
+        csrf_token: csrf::session_token(app_state, session.id),
+    })
+}
+
+pub fn auth_cookie_headers(app_state: &AppState, tokens: &AuthTokens) -> HeaderMap {
+    let cookies = &app_state.cookie_config;
+    let mut headers = HeaderMap::new();
+
+    cookies.set(
+        &mut headers,
+        SessionCookie::AccessToken,
+        &tokens.access_token,
+    );
+    cookies.set(
+        &mut headers,
+        SessionCookie::RefreshToken,
+        &tokens.refresh_token,
+    );
+    cookies.set(&mut headers, SessionCookie::Csrf, &tokens.csrf_token);
+
+    headers
+}
+
+/// Emails a fresh verification link that replaces the previous one. If a
+/// link was sent within the cooldown, nothing is sent and the seconds left
+/// are returned instead.
+pub async fn resend_verification_email(
+    app_state: &AppState,
+    user: &User,
+) -> Result<Option<u64>, HttpError> {
+    let verification_token = token::generate_opaque_token();
+    let now = Utc::now();
+
+    let last_sent_at = app_state
+        .db_client
+        .rotate_email_verification_token(
+            user.id,
+            &token::hash_opaque_token(&verification_token),
+            now + Duration::hours(EMAIL_VERIFICATION_MAXAGE_HOURS),
+            VERIFICATION_RESEND_COOLDOWN_SECONDS,
+        )
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if let Some(last_sent_at) = last_sent_at {
+        let retry_after =
+            last_sent_at + Duration::seconds(VERIFICATION_RESEND_COOLDOWN_SECONDS) - now;
+        return Ok(Some(retry_after.num_seconds().max(1) as u64));
+    }
+
+    let send_email_result = send_verification_email(
+        &app_state.mail_config,
+        &app_state.env.backend_base_url,
+        &app_state.mail_config.mail_template_path,
+        &user.email,
+        &user.name,
+        &verification_token,
+    )
+    .await;
+
+    if let Err(e) = send_email_result {
+        eprintln!("Failed to send verification email: {}", e);
+        return Err(HttpError::server_error(e.to_string()));
+    }
+
+    Ok(None)
+}
+
+/// Checks a password a user is about to set against the password policy and,
+/// if configured, known breaches. An unreachable breach corpus is logged and
+/// does not block the change.
+pub async fn check_new_password(
+    app_state: &AppState,
+    password: &str,
+    user_inputs: &[&str],
+) -> Result<(), HttpError> {
+    app_state
+        .password_policy
+        .check(password, user_inputs)
+        .map_err(HttpError::bad_request)?;
+
+    if let Some(breach_checker) = &app_state.breach_checker {
+        match breach_checker.is_breached(password).await {
+            Ok(true) => {
+                return Err(HttpError::bad_request(
+                    "This password has appeared in a data breach. Please choose another",
+                ));
+            }
+            Ok(false) => {}
+            Err(e) => eprintln!("Failed to check password against breaches: {}", e.message),
+        }
+    }
+
+    Ok(())
+}
+
+/// Rejects a new password that matches the user's current one or any of
+/// their recent ones, as far back as `PASSWORD_HISTORY_SIZE` reaches.
+pub async fn check_password_reuse(
+    app_state: &AppState,
+    user: &User,
+    password: &str,
+) -> Result<(), HttpError> {
+    let history_size = app_state.password_policy.history_size;
+    if history_size == 0 {
+        return Ok(());
+    }
+
+    let mut hashes = vec![user.password.clone()];
+    hashes.extend(
+        app_state
+            .db_client
+            .get_password_history(user.id, history_size - 1)
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?,
+    );
+
+    for hash in hashes {
+        let reused = app_state
+            .password_hasher
+            .compare(password.to_string(), hash)
+            .await?
+            .unwrap_or(false);
+        if reused {
+            return Err(HttpError::bad_request(
+                ErrorMessage::PasswordRecentlyUsed.to_string(),
+            ));
+        }
+    }
+
+    Ok(())
 }
 
 pub async fn register(
@@ -44,10 +281,20 @@ pub async fn register(
         .validate()
         .map_err(|e| HttpError::bad_request(e.to_string()))?;
 
-    let verification_token = Uuid::new_v4();
-    let expires_at = Utc::now() + Duration::hours(24);
+    check_new_password(
+        &app_state,
+        &payload.password,
+        &[&payload.name, &payload.email],
+    )
+    .await?;
+
+    let verification_token = token::generate_opaque_token();
+    let expires_at = Utc::now() + Duration::hours(EMAIL_VERIFICATION_MAXAGE_HOURS);
 
-    let hash_password = password::hash_password(&payload.password)
+    let hash_password = app_state
+        .password_hasher
+        .hash(payload.password.clone())
+        .await?
         .map_err(|e| HttpError::bad_request(e.to_string()))?;
 
     let result = app_state
@@ -56,7 +303,7 @@ pub async fn register(
             &payload.name,
             &payload.email,
             &hash_password,
-            verification_token,
+            &token::hash_opaque_token(&verification_token),
             expires_at,
         )
         .await;
@@ -69,7 +316,7 @@ pub async fn register(
                 &app_state.mail_config.mail_template_path,
                 &user.email,
                 &user.name,
-                &verification_token.to_string(),
+                &verification_token,
             )
             .await;
 
@@ -95,49 +342,403 @@ pub async fn register(
 
 pub async fn login(
     Extension(app_state): Extension<Arc<AppState>>,
+    client_info: ClientInfo,
     Json(payload): Json<LoginUserDto>,
 ) -> Result<impl IntoResponse, HttpError> {
     payload
         .validate()
         .map_err(|e| HttpError::bad_request(e.to_string()))?;
 
+    let email_key = login_throttle::email_key(&payload.email);
+    check_login_throttle(&app_state, &email_key, client_info.ip_address.as_deref()).await?;
+
     let result = app_state
         .db_client
         .get_user(None, None, Some(&payload.email))
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
-    let user = result.ok_or(HttpError::bad_request(
-        ErrorMessage::WrongeCredentials.to_string(),
-    ))?;
+    // Unknown emails count as failures too, and still pay for a hash check,
+    // so neither throttling nor timing reveals whether an account exists.
+    let password_matched = match &result {
+        Some(user) => app_state
+            .password_hasher
+            .compare(payload.password.clone(), user.password.clone())
+            .await?
+            .unwrap_or(false),
+        None => {
+            app_state
+                .password_hasher
+                .compare_dummy(payload.password.clone())
+                .await?;
+            false
+        }
+    };
 
-    let password_matched = password::compare(&payload.password, &user.password)
-        .map_err(|_e| HttpError::bad_request(ErrorMessage::WrongeCredentials.to_string()))?;
+    let user = match result {
+        Some(user) if password_matched => user,
+        user => {
+            record_login_failure(
+                &app_state,
+                &email_key,
+                client_info.ip_address.as_deref(),
+                user.as_ref(),
+            )
+            .await?;
+            return Err(HttpError::bad_request(
+                ErrorMessage::WrongeCredentials.to_string(),
+            ));
+        }
+    };
 
-    if !password_matched {
-        return Err(HttpError::bad_request(
-            ErrorMessage::WrongeCredentials.to_string(),
+    app_state
+        .db_client
+        .clear_login_failures(login_throttle::EMAIL, &email_key)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    // Upgrading the hash is best-effort; the old one still verifies, so a
+    // failure here must not fail the sign-in.
+    if password::needs_rehash(&user.password)
+        && let Err(e) = rehash_password(&app_state, &user, payload.password.clone()).await
+    {
+        eprintln!("Failed to rehash password: {}", e.message);
+    }
+
+    check_email_verified(&app_state, &user, false)?;
+
+    if let Some(challenge_token) = two_factor_challenge(&app_state, user.id).await? {
+        return Ok(Json(TwoFactorChallengeResponse {
+            status: "two_factor_required",
+            challenge_token,
+        })
+        .into_response());
+    }
+
+    complete_login(&app_state, &user, &client_info).await
+}
+
+/// Replaces the user's hash with one made with the current Argon2 parameters.
+async fn rehash_password(
+    app_state: &AppState,
+    user: &User,
+    password: String,
+) -> Result<(), HttpError> {
+    let new_hash = app_state
+        .password_hasher
+        .hash(password)
+        .await?
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    app_state
+        .db_client
+        .rehash_user_password(user.id, &user.password, &new_hash)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))
+}
+
+/// Rejects the sign-in with 429 while the email or the client IP is locked
+/// out or still has to wait after recent failures.
+async fn check_login_throttle(
+    app_state: &AppState,
+    email_key: &str,
+    ip_address: Option<&str>,
+) -> Result<(), HttpError> {
+    let keys = [
+        Some((
+            login_throttle::EMAIL,
+            email_key,
+            &login_throttle::EMAIL_POLICY,
+        )),
+        ip_address.map(|ip| (login_throttle::IP, ip, &login_throttle::IP_POLICY)),
+    ];
+
+    let now = Utc::now();
+    let mut retry_after = None;
+    for (kind, key, policy) in keys.into_iter().flatten() {
+        let attempt = app_state
+            .db_client
+            .get_login_attempt(kind, key)
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+        let wait = attempt.and_then(|attempt| login_throttle::retry_after(&attempt, policy, now));
+        retry_after = retry_after.max(wait);
+    }
+
+    match retry_after {
+        Some(seconds) => Err(HttpError::too_many_requests(
+            ErrorMessage::TooManyLoginAttempts.to_string(),
+            seconds,
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Counts a failed password sign-in against the email and the client IP,
+/// locking either once it reaches its policy's threshold. The account owner
+/// is emailed when their account becomes locked.
+async fn record_login_failure(
+    app_state: &AppState,
+    email_key: &str,
+    ip_address: Option<&str>,
+    user: Option<&User>,
+) -> Result<(), HttpError> {
+    let email_locked = lock_after_failure(
+        app_state,
+        login_throttle::EMAIL,
+        email_key,
+        &login_throttle::EMAIL_POLICY,
+    )
+    .await?;
+
+    if let Some(ip) = ip_address {
+        lock_after_failure(
+            app_state,
+            login_throttle::IP,
+            ip,
+            &login_throttle::IP_POLICY,
+        )
+        .await?;
+    }
+
+    if let (true, Some(user)) = (email_locked, user) {
+        // Sent in the background so the response time does not reveal
+        // whether the email has an account.
+        let mail_config = app_state.mail_config.clone();
+        let frontend_base_url = app_state.env.frontend_base_url.clone();
+        let (email, name) = (user.email.clone(), user.name.clone());
+        tokio::spawn(async move {
+            if let Err(e) = send_account_locked_email(
+                &mail_config,
+                &email,
+                &frontend_base_url,
+                &name,
+                login_throttle::LOCKOUT_MINUTES,
+            )
+            .await
+            {
+                eprintln!("Failed to send account locked email: {}", e);
+            }
+        });
+    }
+
+    Ok(())
+}
+
+/// Returns true when this failure is the one that locked the key.
+async fn lock_after_failure(
+    app_state: &AppState,
+    kind: &str,
+    key: &str,
+    policy: &ThrottlePolicy,
+) -> Result<bool, HttpError> {
+    let attempt = app_state
+        .db_client
+        .record_login_failure(kind, key, login_throttle::FAILURE_WINDOW_MINUTES * 60)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if attempt.failed_count < policy.lockout_threshold {
+        return Ok(false);
+    }
+
+    app_state
+        .db_client
+        .lock_login(
+            kind,
+            key,
+            Utc::now() + Duration::minutes(login_throttle::LOCKOUT_MINUTES),
+        )
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    Ok(attempt.failed_count == policy.lockout_threshold)
+}
+
+pub async fn login_two_factor(
+    Extension(app_state): Extension<Arc<AppState>>,
+    client_info: ClientInfo,
+    Json(payload): Json<TwoFactorLoginDto>,
+) -> Result<impl IntoResponse, HttpError> {
+    payload
+        .validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let user_id = token::decode_challenge_token(
+        &payload.challenge_token,
+        token::TWO_FACTOR_CHALLENGE,
+        &app_state.jwt_config,
+    )?;
+
+    let user_id = Uuid::parse_str(&user_id)
+        .map_err(|_| HttpError::unauthorized(ErrorMessage::InvalidToken.to_string()))?;
+
+    let user = app_state
+        .db_client
+        .get_user(Some(user_id), None, None)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or(HttpError::unauthorized(
+            ErrorMessage::UserNoLongerExists.to_string(),
+        ))?;
+
+    // Wrong codes also count against the email and IP, like wrong passwords.
+    let email_key = login_throttle::email_key(&user.email);
+    let ip_address = client_info.ip_address.as_deref();
+    check_login_throttle(&app_state, &email_key, ip_address).await?;
+
+    if let Err(e) = verify_second_factor(
+        &app_state,
+        user.id,
+        payload.code.as_deref(),
+        payload.recovery_code.as_deref(),
+    )
+    .await
+    {
+        if e.status == StatusCode::UNAUTHORIZED {
+            record_login_failure(&app_state, &email_key, ip_address, Some(&user)).await?;
+        }
+        return Err(e);
+    }
+
+    complete_login(&app_state, &user, &client_info).await
+}
+
+/// Verifies a TOTP code, or failing that a one-time recovery code, for a user
+/// with two-factor authentication enabled. Wrong codes are counted per user,
+/// and the second factor is locked once they reach the threshold.
+pub async fn verify_second_factor(
+    app_state: &AppState,
+    user_id: Uuid,
+    code: Option<&str>,
+    recovery_code: Option<&str>,
+) -> Result<(), HttpError> {
+    let two_factor = app_state
+        .db_client
+        .get_two_factor(user_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .filter(|two_factor| two_factor.enabled)
+        .ok_or(HttpError::bad_request(
+            ErrorMessage::TwoFactorNotEnabled.to_string(),
+        ))?;
+
+    let throttle_key = user_id.to_string();
+    let attempt = app_state
+        .db_client
+        .get_login_attempt(login_throttle::TWO_FACTOR, &throttle_key)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if let Some(seconds) = attempt.and_then(|attempt| {
+        login_throttle::retry_after(&attempt, &login_throttle::TWO_FACTOR_POLICY, Utc::now())
+    }) {
+        return Err(HttpError::too_many_requests(
+            ErrorMessage::TooManyLoginAttempts.to_string(),
+            seconds,
         ));
     }
 
-    let token = token::create_token(
-        &user.id.to_string(),
-        &app_state.env.jwt_secret.as_bytes(),
-        app_state.env.jwt_maxage,
+    let verified = if let Some(code) = code {
+        match totp::verify_code(&two_factor.totp_secret, code, two_factor.last_used_step) {
+            Some(step) => app_state
+                .db_client
+                .record_totp_step(user_id, step)
+                .await
+                .map_err(|e| HttpError::server_error(e.to_string()))?,
+            None => false,
+        }
+    } else if let Some(recovery_code) = recovery_code {
+        app_state
+            .db_client
+            .consume_recovery_code(user_id, &totp::hash_recovery_code(recovery_code))
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?
+    } else {
+        false
+    };
+
+    if !verified {
+        lock_after_failure(
+            app_state,
+            login_throttle::TWO_FACTOR,
+            &throttle_key,
+            &login_throttle::TWO_FACTOR_POLICY,
+        )
+        .await?;
+        return Err(HttpError::unauthorized(
+            ErrorMessage::InvalidTwoFactorCode.to_string(),
+        ));
+    }
+
+    app_state
+        .db_client
+        .clear_login_failures(login_throttle::TWO_FACTOR, &throttle_key)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    Ok(())
+}
+
+/// Returns a challenge token for `/login/2fa` when the user has two-factor
+/// authentication enabled and still has to present a second factor.
+pub async fn two_factor_challenge(
+    app_state: &AppState,
+    user_id: Uuid,
+) -> Result<Option<String>, HttpError> {
+    let two_factor_enabled = app_state
+        .db_client
+        .get_two_factor(user_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .is_some_and(|two_factor| two_factor.enabled);
+
+    if !two_factor_enabled {
+        return Ok(None);
+    }
+
+    token::create_challenge_token(
+        &user_id.to_string(),
+        token::TWO_FACTOR_CHALLENGE,
+        &app_state.jwt_config,
+        TWO_FACTOR_CHALLENGE_MAXAGE_SECONDS,
     )
-    .map_err(|e| HttpError::server_error(e.to_string()))?;
+    .map(Some)
+    .map_err(|e| HttpError::server_error(e.to_string()))
+}
 
-    let mut headers = HeaderMap::new();
+/// Sends a browser that arrived by redirect to the frontend's 2FA page with
+/// the challenge token.
+pub fn two_factor_redirect(
+    app_state: &AppState,
+    challenge_token: &str,
+) -> Result<Redirect, HttpError> {
+    let mut url = url::Url::parse(&app_state.env.frontend_base_url)
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+    url.path_segments_mut()
+        .map_err(|_| HttpError::server_error("Invalid FRONTEND_BASE_URL".to_string()))?
+        .pop_if_empty()
+        .extend(["login", "2fa"]);
+    // A fragment never reaches server logs or `Referer` headers.
+    let fragment = url::form_urlencoded::Serializer::new(String::new())
+        .append_pair("challengeToken", challenge_token)
+        .finish();
+    url.set_fragment(Some(&fragment));
 
-    let cookie_duration = time::Duration::minutes(app_state.env.jwt_maxage * 60);
+    Ok(Redirect::to(url.as_str()))
+}
 
-    let cookie = Cookie::build(("token", token.clone()))
-        .path("/")
-        .max_age(cookie_duration)
-        .http_only(true)
-        .build();
+/// Issues the token pair and cookies for a fully authenticated user and
+/// returns the login response with their default workspace.
+pub async fn complete_login(
+    app_state: &AppState,
+    user: &User,
+    client_info: &ClientInfo,
+) -> Result<axum::response::Response, HttpError> {
+    let tokens = issue_auth_tokens(app_state, user.id, client_info).await?;
 
-    headers.append(header::SET_COOKIE, cookie.to_string().parse().unwrap());
+    let mut headers = auth_cookie_headers(app_state, &tokens);
 
     let workspace = app_state
         .db_client
@@ -146,22 +747,20 @@ pub async fn login(
         .ok();
 
     if let Some(ref workspace) = workspace {
-        let workspace_cookie = Cookie::build(("workpace", workspace.workspace.id.to_string()))
-            .path("/")
-            .max_age(cookie_duration)
-            .http_only(true)
-            .build();
-        headers.append(
-            header::SET_COOKIE,
-            workspace_cookie.to_string().parse().unwrap(),
+        app_state.cookie_config.set(
+            &mut headers,
+            SessionCookie::Workspace,
+            workspace.workspace.id.to_string(),
         );
     }
 
-    let filter_user = FilterUserDto::filter_user(&user);
+    let filter_user = FilterUserDto::filter_user(user);
 
     let response = axum::response::Json(LoginResponse {
         status: "success",
-        token,
+        token: tokens.access_token,
+        refresh_token: tokens.refresh_token,
+        csrf_token: tokens.csrf_token,
         data: UserDataResponse {
             user: filter_user,
             workspace,
@@ -176,16 +775,15 @@ pub async fn login(
 pub async fn verify_email(
     Query(query_params): Query<VerifyEmailQueryDto>,
     Extension(app_state): Extension<Arc<AppState>>,
+    client_info: ClientInfo,
 ) -> Result<impl IntoResponse, HttpError> {
     query_params
         .validate()
         .map_err(|e| HttpError::bad_request(e.to_string()))?;
 
-    let token = Uuid::parse_str(&query_params.token).unwrap();
-
     let result = app_state
         .db_client
-        .get_user_id_by_token(token)
+        .take_email_verification(&token::hash_opaque_token(&query_params.token))
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
@@ -215,37 +813,26 @@ pub async fn verify_email(
         ErrorMessage::WrongeCredentials.to_string(),
     ))?;
 
-    let send_welcome_email_result = send_welcome_email(
+    if let Err(e) = send_welcome_email(
         &app_state.mail_config,
         &user.email,
         &app_state.env.frontend_base_url,
         &user.name,
     )
-    .await;
-
-    if let Err(e) = send_welcome_email_result {
+    .await
+    {
         eprintln!("Failed to send welcome email: {}", e);
         return Err(HttpError::server_error(e.to_string()));
     }
 
-    let token = token::create_token(
-        &user.id.to_string(),
-        &app_state.env.jwt_secret.as_bytes(),
-        app_state.env.jwt_maxage,
-    )
-    .map_err(|e| HttpError::server_error(e.to_string()))?;
-
-    let mut headers = HeaderMap::new();
-
-    let cookie_duration = time::Duration::minutes(app_state.env.jwt_maxage * 60);
+    // The link proves the mailbox, not the second factor.
+    if let Some(challenge_token) = two_factor_challenge(&app_state, user.id).await? {
+        return Ok(two_factor_redirect(&app_state, &challenge_token)?.into_response());
+    }
 
-    let cookie = Cookie::build(("token", token.clone()))
-        .path("/")
-        .max_age(cookie_duration)
-        .http_only(true)
-        .build();
+    let tokens = issue_auth_tokens(&app_state, user.id, &client_info).await?;
 
-    headers.append(header::SET_COOKIE, cookie.to_string().parse().unwrap());
+    let headers = auth_cookie_headers(&app_state, &tokens);
 
     let redirect = Redirect::to(&app_state.env.frontend_base_url);
 
@@ -271,12 +858,12 @@ pub async fn forgot_password(
             ErrorMessage::WrongeCredentials.to_string(),
         ))?;
 
-    let reset_token = Uuid::new_v4();
+    let reset_token = token::generate_opaque_token();
     let expires_at = Utc::now() + Duration::hours(24);
 
     app_state
         .db_client
-        .save_password_reset_token(user.id, reset_token, expires_at)
+        .save_password_reset_token(user.id, &token::hash_opaque_token(&reset_token), expires_at)
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
@@ -285,7 +872,7 @@ pub async fn forgot_password(
         &user.email,
         &app_state.env.frontend_base_url,
         &user.name,
-        &reset_token.to_string(),
+        &reset_token,
     )
     .await;
 
@@ -303,6 +890,191 @@ pub async fn forgot_password(
     ))
 }
 
+/// Sends a new verification link to an unverified account. The response is
+/// the same whether or not the email belongs to one, and during the resend
+/// cooldown nothing is sent.
+pub async fn resend_verification(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Json(payload): Json<ResendVerificationDto>,
+) -> Result<impl IntoResponse, HttpError> {
+    payload
+        .validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let user = app_state
+        .db_client
+        .get_user(None, None, Some(&payload.email))
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if let Some(user) = user.filter(|user| user.email_verified != Some(true)) {
+        resend_verification_email(&app_state, &user).await?;
+    }
+
+    Ok((
+        StatusCode::OK,
+        Json(Response {
+            status: "success",
+            message:
+                "If an unverified account exists for this email, a verification link has been sent"
+                    .to_string(),
+        }),
+    ))
+}
+
+/// Emails a single-use sign-in link and code. The response is the same
+/// whether or not the email belongs to an account.
+pub async fn request_magic_link(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Json(payload): Json<MagicLinkDto>,
+) -> Result<impl IntoResponse, HttpError> {
+    payload
+        .validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let user = app_state
+        .db_client
+        .get_user(None, None, Some(&payload.email))
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if let Some(user) = user {
+        let magic_token = token::generate_opaque_token();
+        let code = token::generate_numeric_code(MAGIC_LINK_CODE_DIGITS);
+        let expires_at = Utc::now() + Duration::minutes(MAGIC_LINK_MAXAGE_MINUTES);
+
+        app_state
+            .db_client
+            .save_magic_link(
+                user.id,
+                &token::hash_opaque_token(&magic_token),
+                &token::hmac_sha256(&app_state.env.hmac_secret, &code),
+                expires_at,
+            )
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+        let send_magic_link_email_result = send_magic_link_email(
+            &app_state.mail_config,
+            &user.email,
+            &app_state.env.frontend_base_url,
+            &user.name,
+            &magic_token,
+            &code,
+        )
+        .await;
+
+        if let Err(e) = send_magic_link_email_result {
+            eprintln!("Failed to send magic link email: {}", e);
+            return Err(HttpError::server_error(e.to_string()));
+        }
+    }
+
+    Ok((
+        StatusCode::OK,
+        Json(Response {
+            status: "success",
+            message: "If an account exists for this email, a sign-in link has been sent"
+                .to_string(),
+        }),
+    ))
+}
+
+pub async fn consume_magic_link(
+    Extension(app_state): Extension<Arc<AppState>>,
+    client_info: ClientInfo,
+    Json(payload): Json<ConsumeMagicLinkDto>,
+) -> Result<impl IntoResponse, HttpError> {
+    payload
+        .validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let magic_link = match (&payload.token, &payload.email, &payload.code) {
+        (Some(magic_token), _, _) => app_state
+            .db_client
+            .take_magic_link_by_token(&token::hash_opaque_token(magic_token))
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?,
+        (None, Some(email), Some(code)) => {
+            // Codes are short enough to guess, so wrong ones are throttled
+            // like wrong passwords.
+            let email_key = login_throttle::email_key(email);
+            let ip_address = client_info.ip_address.as_deref();
+            check_login_throttle(&app_state, &email_key, ip_address).await?;
+
+            let user = app_state
+                .db_client
+                .get_user(None, None, Some(email))
+                .await
+                .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+            let magic_link = match &user {
+                Some(user) => app_state
+                    .db_client
+                    .take_magic_link_by_code(
+                        user.id,
+                        &token::hmac_sha256(&app_state.env.hmac_secret, code),
+                        MAGIC_LINK_MAX_CODE_ATTEMPTS,
+                    )
+                    .await
+                    .map_err(|e| HttpError::server_error(e.to_string()))?,
+                None => None,
+            };
+
+            match magic_link {
+                Some(_) => app_state
+                    .db_client
+                    .clear_login_failures(login_throttle::EMAIL, &email_key)
+                    .await
+                    .map(|_| ())
+                    .map_err(|e| HttpError::server_error(e.to_string()))?,
+                None => {
+                    record_login_failure(&app_state, &email_key, ip_address, user.as_ref()).await?
+                }
+            }
+            magic_link
+        }
+        _ => {
+            return Err(HttpError::bad_request(
+                "Provide either a token or an email and code".to_string(),
+            ));
+        }
+    };
+
+    let magic_link = magic_link.ok_or(HttpError::unauthorized(
+        ErrorMessage::InvalidToken.to_string(),
+    ))?;
+
+    let mut user = app_state
+        .db_client
+        .get_user(Some(magic_link.user_id), None, None)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or(HttpError::unauthorized(
+            ErrorMessage::UserNoLongerExists.to_string(),
+        ))?;
+
+    // Receiving the link proves the user controls the mailbox.
+    if user.email_verified != Some(true) {
+        app_state
+            .db_client
+            .verify_user(user.id)
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+        user.email_verified = Some(true);
+    }
+
+    if let Some(challenge_token) = two_factor_challenge(&app_state, user.id).await? {
+        return Ok(Json(TwoFactorChallengeResponse {
+            status: "two_factor_required",
+            challenge_token,
+        })
+        .into_response());
+    }
+
+    complete_login(&app_state, &user, &client_info).await
+}
+
 pub async fn reset_password(
     Extension(app_state): Extension<Arc<AppState>>,
     Json(payload): Json<ResetPasswordDto>,
@@ -311,17 +1083,15 @@ pub async fn reset_password(
         .validate()
         .map_err(|e| HttpError::bad_request(e.to_string()))?;
 
-    let reset_token = Uuid::parse_str(&payload.token)
-        .map_err(|_e| HttpError::bad_request(ErrorMessage::InvalidToken.to_string()))?;
+    let token_hash = token::hash_opaque_token(&payload.token);
+    let invalid_token = || HttpError::bad_request(ErrorMessage::WrongeCredentials.to_string());
 
     let password_reset = app_state
         .db_client
-        .get_password_reset_token(reset_token)
+        .get_password_reset_token(&token_hash)
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?
-        .ok_or(HttpError::bad_request(
-            ErrorMessage::WrongeCredentials.to_string(),
-        ))?;
+        .ok_or_else(invalid_token)?;
 
     if Utc::now() > password_reset.expires_at {
         return Err(HttpError::bad_request(
@@ -329,12 +1099,60 @@ pub async fn reset_password(
         ));
     }
 
-    let hash_password = password::hash_password(&payload.password)
+    let user = app_state
+        .db_client
+        .get_user(Some(password_reset.user_id), None, None)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or_else(invalid_token)?;
+
+    // The token is only used up once the new password passes every check, so
+    // a rejected password can be retried.
+    check_new_password(&app_state, &payload.password, &[&user.name, &user.email]).await?;
+    check_password_reuse(&app_state, &user, &payload.password).await?;
+
+    let hash_password = app_state
+        .password_hasher
+        .hash(payload.password.clone())
+        .await?
         .map_err(|e| HttpError::bad_request(e.to_string()))?;
 
+    let reset = app_state
+        .db_client
+        .reset_password(
+            &token_hash,
+            user.id,
+            &hash_password,
+            app_state.password_policy.history_size,
+        )
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if !reset {
+        return Err(invalid_token());
+    }
+
+    // Whoever knew the old password is signed out everywhere, and loses any
+    // personal access tokens they minted with it.
     app_state
         .db_client
-        .reset_password(password_reset.user_id, &hash_password)
+        .revoke_all_sessions(user.id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    app_state
+        .db_client
+        .delete_user_personal_access_tokens(user.id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    // Proving control of the mailbox also lifts a sign-in lockout.
+    app_state
+        .db_client
+        .clear_login_failures(
+            login_throttle::EMAIL,
+            &login_throttle::email_key(&user.email),
+        )
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
@@ -345,3 +1163,176 @@ pub async fn reset_password(
 
     Ok(response)
 }
+
+pub async fn refresh(
+    cookie_jar: CookieJar,
+    Extension(app_state): Extension<Arc<AppState>>,
+    method: Method,
+    OriginalUri(uri): OriginalUri,
+    headers: HeaderMap,
+    payload: Option<Json<RefreshTokenDto>>,
+) -> Result<impl IntoResponse, HttpError> {
+    let body_token = payload.and_then(|Json(payload)| payload.refresh_token);
+    let from_cookie = body_token.is_none();
+    let presented_token = match body_token {
+        Some(token) => token,
+        None => app_state
+            .cookie_config
+            .get(&cookie_jar, SessionCookie::RefreshToken)
+            .map(str::to_string)
+            .ok_or(HttpError::unauthorized(
+                ErrorMessage::TokenNotProvided.to_string(),
+            ))?,
+    };
+
+    let stored_token = app_state
+        .db_client
+        .get_refresh_token(&token::hash_opaque_token(&presented_token))
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or(HttpError::unauthorized(
+            ErrorMessage::InvalidToken.to_string(),
+        ))?;
+
+    // Checked before anything is rotated or revoked.
+    if from_cookie {
+        csrf::verify_request(
+            &app_state,
+            stored_token.session_id,
+            &method,
+            uri.path(),
+            &headers,
+        )?;
+    }
+
+    // A refresh token that was already exchanged is being replayed: either the
+    // legitimate client or an attacker holds a stolen copy, so the whole family
+    // is revoked and the user has to log in again.
+    if stored_token.used_at.is_some() {
+        app_state
+            .db_client
+            .revoke_refresh_token_family(stored_token.family_id)
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+        return Err(HttpError::unauthorized(
+            ErrorMessage::RefreshTokenReused.to_string(),
+        ));
+    }
+
+    if stored_token.revoked_at.is_some() || Utc::now() > stored_token.expires_at {
+        return Err(HttpError::unauthorized(
+            ErrorMessage::InvalidToken.to_string(),
+        ));
+    }
+
+    let user = app_state
+        .db_client
+        .get_user(Some(stored_token.user_id), None, None)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or(HttpError::unauthorized(
+            ErrorMessage::UserNoLongerExists.to_string(),
+        ))?;
+
+    let refresh_token = token::generate_opaque_token();
+    let expires_at = Utc::now() + Duration::days(app_state.env.refresh_token_maxage);
+
+    let rotated = app_state
+        .db_client
+        .rotate_refresh_token(
+            &stored_token,
+            &token::hash_opaque_token(&refresh_token),
+            expires_at,
+        )
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if !rotated {
+        app_state
+            .db_client
+            .revoke_refresh_token_family(stored_token.family_id)
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+        return Err(HttpError::unauthorized(
+            ErrorMessage::RefreshTokenReused.to_string(),
+        ));
+    }
+
+    let tokens = AuthTokens {
+        access_token: create_access_token(&app_state, user.id, stored_token.session_id)?,
+        refresh_token,
+        csrf_token: csrf::session_token(&app_state, stored_token.session_id),
+    };
+
+    let headers = auth_cookie_headers(&app_state, &tokens);
+
+    let mut response = Json(TokenResponse {
+        status: "success",
+        token: tokens.access_token,
+        refresh_token: tokens.refresh_token,
+        csrf_token: tokens.csrf_token,
+    })
+    .into_response();
+    response.headers_mut().extend(headers);
+    Ok(response)
+}
+
+/// Returns the current session's CSRF token, for clients that signed in
+/// through a redirect and cannot read the cookie, and sets the cookie again.
+pub async fn get_csrf_token(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JwtAuthMiddleware>,
+) -> Result<impl IntoResponse, HttpError> {
+    let session = user.require_session()?;
+    let csrf_token = csrf::session_token(&app_state, session.id);
+
+    let mut headers = HeaderMap::new();
+    app_state
+        .cookie_config
+        .set(&mut headers, SessionCookie::Csrf, &csrf_token);
+
+    Ok((
+        headers,
+        Json(CsrfTokenResponse {
+            status: "success",
+            csrf_token,
+        }),
+    ))
+}
+
+pub async fn logout(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JwtAuthMiddleware>,
+) -> Result<impl IntoResponse, HttpError> {
+    let session = user.require_session()?;
+    let jti = Uuid::parse_str(&user.claims.jti)
+        .map_err(|_| HttpError::unauthorized(ErrorMessage::InvalidToken.to_string()))?;
+    let expires_at = DateTime::<Utc>::from_timestamp(user.claims.exp as i64, 0).ok_or(
+        HttpError::unauthorized(ErrorMessage::InvalidToken.to_string()),
+    )?;
+
+    app_state
+        .db_client
+        .revoke_token(jti, user.user.id, expires_at)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    // Ending the session also revokes every refresh token issued for it.
+    app_state
+        .db_client
+        .revoke_session(user.user.id, session.id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let mut response = Json(Response {
+        status: "success",
+        message: "Logged out successfully".to_string(),
+    })
+    .into_response();
+    app_state
+        .cookie_config
+        .clear_session(response.headers_mut());
+    Ok(response)
+}
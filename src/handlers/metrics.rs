@@ -0,0 +1,59 @@
+use std::{fmt::Write, sync::Arc};
+
+use axum::{Extension, http::header, response::IntoResponse};
+
+use crate::{AppState, utils::password::QUEUE_WAIT_BUCKETS};
+
+pub fn metrics_handler() -> axum::Router {
+    axum::Router::new().route("/", axum::routing::get(metrics))
+}
+
+/// Password hashing pool metrics in the Prometheus text format.
+pub async fn metrics(Extension(app_state): Extension<Arc<AppState>>) -> impl IntoResponse {
+    let metrics = app_state.password_hasher.metrics();
+    let mut body = String::new();
+
+    let _ = writeln!(
+        body,
+        "# HELP password_hashing_queue_wait_seconds Time password hashing jobs waited for a worker."
+    );
+    let _ = writeln!(body, "# TYPE password_hashing_queue_wait_seconds histogram");
+    for (le, count) in QUEUE_WAIT_BUCKETS.iter().zip(metrics.queue_wait_buckets) {
+        let _ = writeln!(
+            body,
+            "password_hashing_queue_wait_seconds_bucket{{le=\"{}\"}} {}",
+            le, count
+        );
+    }
+    let _ = writeln!(
+        body,
+        "password_hashing_queue_wait_seconds_bucket{{le=\"+Inf\"}} {}",
+        metrics.queue_wait_count
+    );
+    let _ = writeln!(
+        body,
+        "password_hashing_queue_wait_seconds_sum {}",
+        metrics.queue_wait_sum_seconds
+    );
+    let _ = writeln!(
+        body,
+        "password_hashing_queue_wait_seconds_count {}",
+        metrics.queue_wait_count
+    );
+
+    let _ = writeln!(
+        body,
+        "# HELP password_hashing_rejected_total Password hashing jobs turned away because the queue was full."
+    );
+    let _ = writeln!(body, "# TYPE password_hashing_rejected_total counter");
+    let _ = writeln!(body, "password_hashing_rejected_total {}", metrics.rejected);
+
+    let _ = writeln!(
+        body,
+        "# HELP password_hashing_pending Password hashing jobs running or waiting."
+    );
+    let _ = writeln!(body, "# TYPE password_hashing_pending gauge");
+    let _ = writeln!(body, "password_hashing_pending {}", metrics.pending);
+
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body)
+}
@@ -1,6 +1,12 @@
 pub mod auth;
+pub mod metrics;
+pub mod oauth;
+pub mod operator;
 pub mod permissions;
 pub mod role;
+pub mod service_account;
 pub mod user;
+pub mod webauthn;
+pub mod well_known;
 pub mod workspace;
 pub mod workspace_user;
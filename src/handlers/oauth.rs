@@ -0,0 +1,249 @@
+use std::sync::Arc;
+
+use axum::{
+    Extension,
+    extract::{Path, Query},
+    http::StatusCode,
+    response::{IntoResponse, Redirect},
+};
+use axum_extra::extract::CookieJar;
+use chrono::{Duration, Utc};
+
+use crate::{
+    AppState,
+    config::{config::OidcProviderConfig, cookie_config::SessionCookie},
+    database::{auth::AuthExt, identity::IdentityExt},
+    dtos::auth::OAuthCallbackQueryDto,
+    error::{ErrorMessage, HttpError},
+    handlers::auth::{
+        auth_cookie_headers, issue_auth_tokens, two_factor_challenge, two_factor_redirect,
+    },
+    models::User,
+    utils::{client_info::ClientInfo, oidc::IdTokenClaims, token},
+};
+
+pub fn oauth_handler() -> axum::Router {
+    axum::Router::new()
+        .route("/{provider}", axum::routing::get(start_oauth_login))
+        .route("/{provider}/callback", axum::routing::get(oauth_callback))
+}
+
+fn find_provider<'a>(
+    app_state: &'a AppState,
+    provider: &str,
+) -> Result<&'a OidcProviderConfig, HttpError> {
+    app_state.env.oauth_provider(provider).ok_or(HttpError::new(
+        StatusCode::NOT_FOUND,
+        "OAuth provider not found".to_string(),
+    ))
+}
+
+fn redirect_uri(app_state: &AppState, provider: &OidcProviderConfig) -> String {
+    format!(
+        "{}/auth/oauth/{}/callback",
+        app_state.env.backend_base_url, provider.name
+    )
+}
+
+/// Redirects the browser to the provider's consent screen.
+pub async fn start_oauth_login(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Path(provider): Path<String>,
+) -> Result<impl IntoResponse, HttpError> {
+    let provider = find_provider(&app_state, &provider)?;
+
+    let state_maxage = app_state.cookie_config.max_age(SessionCookie::OAuthState);
+    let state = token::generate_opaque_token();
+    let nonce = token::generate_opaque_token();
+    let code_verifier = token::generate_opaque_token();
+
+    app_state
+        .db_client
+        .save_oauth_state(
+            &state,
+            &provider.name,
+            &code_verifier,
+            &nonce,
+            Utc::now() + Duration::seconds(state_maxage.whole_seconds()),
+        )
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let authorization_url = app_state
+        .oidc_client
+        .authorization_url(
+            provider,
+            &redirect_uri(&app_state, provider),
+            &state,
+            &nonce,
+            &code_verifier,
+        )
+        .await?;
+
+    let mut response = Redirect::to(&authorization_url).into_response();
+    app_state
+        .cookie_config
+        .set(response.headers_mut(), SessionCookie::OAuthState, state);
+    Ok(response)
+}
+
+/// Finds the user an external identity belongs to, linking it to an existing
+/// account with the same verified email or signing up a new user.
+async fn resolve_identity_user(
+    app_state: &AppState,
+    provider: &OidcProviderConfig,
+    claims: &IdTokenClaims,
+) -> Result<User, HttpError> {
+    let identity = app_state
+        .db_client
+        .get_identity(&provider.name, &claims.sub)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if let Some(identity) = identity {
+        app_state
+            .db_client
+            .record_identity_login(identity.id)
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+        return app_state
+            .db_client
+            .get_user(Some(identity.user_id), None, None)
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?
+            .ok_or(HttpError::unauthorized(
+                ErrorMessage::UserNoLongerExists.to_string(),
+            ));
+    }
+
+    let email = claims
+        .email
+        .as_deref()
+        .filter(|_| claims.email_verified())
+        .ok_or(HttpError::bad_request(
+            ErrorMessage::OAuthEmailNotVerified.to_string(),
+        ))?;
+
+    let existing_user = app_state
+        .db_client
+        .get_user(None, None, Some(email))
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if let Some(user) = existing_user {
+        // Linking to an unverified account would hand it to whoever registered
+        // the address first, so the owner has to verify it before linking.
+        if user.email_verified != Some(true) {
+            return Err(HttpError::forbidden(
+                ErrorMessage::EmailNotVerified.to_string(),
+            ));
+        }
+
+        app_state
+            .db_client
+            .link_identity(user.id, &provider.name, &claims.sub, Some(email))
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+        return Ok(user);
+    }
+
+    let name = claims
+        .name
+        .as_deref()
+        .filter(|name| !name.trim().is_empty())
+        .unwrap_or_else(|| email.split('@').next().unwrap_or(email));
+    let name: String = name.trim().chars().take(100).collect();
+
+    // Social accounts have no usable password until the user resets one.
+    let unusable_password = app_state
+        .password_hasher
+        .hash(token::generate_opaque_token())
+        .await?
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    app_state
+        .db_client
+        .create_user_with_identity(
+            &name,
+            email,
+            &unusable_password,
+            &provider.name,
+            &claims.sub,
+        )
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(db_err) if db_err.is_unique_violation() => {
+                HttpError::unique_constraint_violation(ErrorMessage::EmailExit.to_string())
+            }
+            e => HttpError::server_error(e.to_string()),
+        })
+}
+
+pub async fn oauth_callback(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Path(provider): Path<String>,
+    Query(query_params): Query<OAuthCallbackQueryDto>,
+    cookie_jar: CookieJar,
+    client_info: ClientInfo,
+) -> Result<impl IntoResponse, HttpError> {
+    let provider = find_provider(&app_state, &provider)?;
+
+    let (Some(code), Some(state)) = (query_params.code, query_params.state) else {
+        return Err(HttpError::bad_request(
+            ErrorMessage::OAuthLoginFailed.to_string(),
+        ));
+    };
+
+    // The state must come back to the browser that started the flow.
+    if app_state
+        .cookie_config
+        .get(&cookie_jar, SessionCookie::OAuthState)
+        != Some(state.as_str())
+    {
+        return Err(HttpError::bad_request(
+            ErrorMessage::OAuthLoginFailed.to_string(),
+        ));
+    }
+
+    let pending = app_state
+        .db_client
+        .take_oauth_state(&state, &provider.name)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or(HttpError::bad_request(
+            ErrorMessage::OAuthLoginFailed.to_string(),
+        ))?;
+
+    let claims = app_state
+        .oidc_client
+        .exchange_code(
+            provider,
+            &redirect_uri(&app_state, provider),
+            &code,
+            &pending.code_verifier,
+            &pending.nonce,
+        )
+        .await?;
+
+    let user = resolve_identity_user(&app_state, provider, &claims).await?;
+
+    if let Some(challenge_token) = two_factor_challenge(&app_state, user.id).await? {
+        let mut response = two_factor_redirect(&app_state, &challenge_token)?.into_response();
+        app_state
+            .cookie_config
+            .clear(response.headers_mut(), SessionCookie::OAuthState);
+        return Ok(response);
+    }
+
+    let tokens = issue_auth_tokens(&app_state, user.id, &client_info).await?;
+    let mut headers = auth_cookie_headers(&app_state, &tokens);
+    app_state
+        .cookie_config
+        .clear(&mut headers, SessionCookie::OAuthState);
+
+    let mut response = Redirect::to(&app_state.env.frontend_base_url).into_response();
+    response.headers_mut().extend(headers);
+    Ok(response)
+}
@@ -0,0 +1,80 @@
+use std::sync::Arc;
+
+use axum::{Extension, Json, extract::Path, response::IntoResponse};
+use uuid::Uuid;
+
+use crate::{
+    AppState,
+    database::{auth::AuthExt, login_attempt::LoginAttemptExt},
+    dtos::Response,
+    error::{ErrorMessage, HttpError},
+    middleware::jwt_auth_middleware::JwtAuthMiddleware,
+    utils::login_throttle,
+};
+
+pub fn operator_handler() -> axum::Router {
+    axum::Router::new().route("/users/{user_id}/unlock", axum::routing::post(unlock_user))
+}
+
+/// Rejects callers who are not signed in as one of `OPERATOR_EMAILS`. The
+/// email must be verified, so registering an operator's address first does
+/// not make someone an operator.
+fn require_operator(app_state: &AppState, user: &JwtAuthMiddleware) -> Result<(), HttpError> {
+    user.require_session()?;
+
+    let email = login_throttle::email_key(&user.user.email);
+    if user.user.email_verified != Some(true) || !app_state.env.operator_emails.contains(&email) {
+        return Err(HttpError::forbidden(
+            ErrorMessage::PermissionDenied.to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Lifts a user's lockout after repeated failed sign-ins or second-factor
+/// codes, and records who lifted it.
+pub async fn unlock_user(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JwtAuthMiddleware>,
+    Path(user_id): Path<Uuid>,
+) -> Result<impl IntoResponse, HttpError> {
+    require_operator(&app_state, &user)?;
+
+    let locked_user = app_state
+        .db_client
+        .get_user(Some(user_id), None, None)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or(HttpError::bad_request(
+            ErrorMessage::UserNoLongerExists.to_string(),
+        ))?;
+
+    app_state
+        .db_client
+        .clear_login_failures(
+            login_throttle::EMAIL,
+            &login_throttle::email_key(&locked_user.email),
+        )
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    app_state
+        .db_client
+        .clear_login_failures(login_throttle::TWO_FACTOR, &locked_user.id.to_string())
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    app_state
+        .db_client
+        .record_login_unlock(locked_user.id, user.user.id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = Response {
+        status: "success",
+        message: "User unlocked successfully".to_string(),
+    };
+
+    Ok(Json(response))
+}
@@ -0,0 +1,300 @@
+use std::sync::Arc;
+
+use axum::{Extension, Json, extract::Path, http::StatusCode, response::IntoResponse};
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    AppState,
+    constants::permissions,
+    database::{role::RoleExt, service_account::ServiceAccountExt, workspace::WorkspaceExt},
+    dtos::{
+        Response,
+        service_account::{
+            CreateServiceAccountDto, CreateServiceAccountKeyDto, ServiceAccountCreatedResponse,
+            ServiceAccountKeyCreatedResponse, ServiceAccountKeyDto, ServiceAccountKeyList,
+            ServiceAccountKeyListResponse, ServiceAccountList, ServiceAccountListResponse,
+        },
+    },
+    error::{ErrorMessage, HttpError},
+    middleware::{
+        jwt_auth_middleware::JwtAuthMiddleware, workspace_middleware::WorkspaceAuthMiddleware,
+    },
+    utils::token,
+    workspace_auth,
+};
+
+pub fn service_account_handler() -> axum::Router {
+    axum::Router::new()
+        .route(
+            "/",
+            axum::routing::get(get_service_accounts)
+                .post(create_service_account)
+                .layer(workspace_auth!(permissions::MANAGE_SERVICE_ACCOUNTS)),
+        )
+        .route(
+            "/{service_account_id}",
+            axum::routing::delete(delete_service_account)
+                .layer(workspace_auth!(permissions::MANAGE_SERVICE_ACCOUNTS)),
+        )
+        .route(
+            "/{service_account_id}/keys",
+            axum::routing::get(get_service_account_keys)
+                .post(create_service_account_key)
+                .layer(workspace_auth!(permissions::MANAGE_SERVICE_ACCOUNTS)),
+        )
+        .route(
+            "/{service_account_id}/keys/{key_id}",
+            axum::routing::delete(delete_service_account_key)
+                .layer(workspace_auth!(permissions::MANAGE_SERVICE_ACCOUNTS)),
+        )
+}
+
+fn check_expiry(expires_at: Option<DateTime<Utc>>) -> Result<(), HttpError> {
+    if expires_at.is_some_and(|expires_at| expires_at <= Utc::now()) {
+        return Err(HttpError::bad_request(
+            "Expiry must be in the future".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn service_account_not_found() -> HttpError {
+    HttpError::new(
+        StatusCode::NOT_FOUND,
+        "Service account not found".to_string(),
+    )
+}
+
+/// Only a signed-in person may hand out API keys, and never with more power
+/// than they hold themselves: personal access tokens and service accounts are
+/// refused, as is any role granting a permission the caller lacks.
+fn check_can_delegate(
+    user: &JwtAuthMiddleware,
+    workspace: &WorkspaceAuthMiddleware,
+    permissions: &[String],
+) -> Result<(), HttpError> {
+    user.require_session()?;
+
+    if permissions
+        .iter()
+        .any(|permission| !workspace.permissions.contains(permission))
+    {
+        return Err(HttpError::forbidden(
+            ErrorMessage::PermissionDenied.to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Fails unless the service account belongs to the workspace.
+async fn require_service_account(
+    app_state: &AppState,
+    workspace_id: Uuid,
+    service_account_id: Uuid,
+) -> Result<(), HttpError> {
+    let exists = app_state
+        .db_client
+        .service_account_exists(workspace_id, service_account_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if !exists {
+        return Err(service_account_not_found());
+    }
+    Ok(())
+}
+
+pub async fn get_service_accounts(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(_user): Extension<JwtAuthMiddleware>,
+    Extension(workspace): Extension<WorkspaceAuthMiddleware>,
+) -> Result<impl IntoResponse, HttpError> {
+    let service_accounts = app_state
+        .db_client
+        .get_service_accounts(workspace.workspace_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = ServiceAccountListResponse {
+        status: "success",
+        data: ServiceAccountList { service_accounts },
+    };
+
+    Ok(Json(response))
+}
+
+/// Creates a service account in the workspace with the given role and
+/// returns its first API key.
+pub async fn create_service_account(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JwtAuthMiddleware>,
+    Extension(workspace): Extension<WorkspaceAuthMiddleware>,
+    Json(payload): Json<CreateServiceAccountDto>,
+) -> Result<impl IntoResponse, HttpError> {
+    payload
+        .validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    check_expiry(payload.expires_at)?;
+
+    let role_id = app_state
+        .db_client
+        .get_role_id_by_name(workspace.workspace_id, payload.role_name)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => HttpError::bad_request("Role not found".to_string()),
+            e => HttpError::server_error(e.to_string()),
+        })?;
+
+    let role_permissions = app_state
+        .db_client
+        .get_role_permissions(role_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    check_can_delegate(&user, &workspace, &role_permissions)?;
+
+    let key = token::generate_service_account_key();
+
+    let service_account = app_state
+        .db_client
+        .create_service_account(
+            workspace.workspace_id,
+            &payload.name,
+            role_id,
+            &token::hash_opaque_token(&key),
+            payload.expires_at,
+        )
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = ServiceAccountCreatedResponse {
+        status: "success",
+        key,
+        data: service_account,
+    };
+
+    Ok((StatusCode::CREATED, Json(response)))
+}
+
+pub async fn delete_service_account(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(_user): Extension<JwtAuthMiddleware>,
+    Extension(workspace): Extension<WorkspaceAuthMiddleware>,
+    Path(service_account_id): Path<Uuid>,
+) -> Result<impl IntoResponse, HttpError> {
+    let deleted = app_state
+        .db_client
+        .delete_service_account(workspace.workspace_id, service_account_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if !deleted {
+        return Err(service_account_not_found());
+    }
+
+    let response = Response {
+        status: "success",
+        message: "Service account deleted successfully".to_string(),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn get_service_account_keys(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(_user): Extension<JwtAuthMiddleware>,
+    Extension(workspace): Extension<WorkspaceAuthMiddleware>,
+    Path(service_account_id): Path<Uuid>,
+) -> Result<impl IntoResponse, HttpError> {
+    require_service_account(&app_state, workspace.workspace_id, service_account_id).await?;
+
+    let keys = app_state
+        .db_client
+        .get_service_account_keys(service_account_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = ServiceAccountKeyListResponse {
+        status: "success",
+        data: ServiceAccountKeyList {
+            keys: keys.iter().map(ServiceAccountKeyDto::from_key).collect(),
+        },
+    };
+
+    Ok(Json(response))
+}
+
+/// Adds an API key. Existing keys keep working until deleted, so a key can be
+/// rotated without downtime.
+pub async fn create_service_account_key(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JwtAuthMiddleware>,
+    Extension(workspace): Extension<WorkspaceAuthMiddleware>,
+    Path(service_account_id): Path<Uuid>,
+    Json(payload): Json<CreateServiceAccountKeyDto>,
+) -> Result<impl IntoResponse, HttpError> {
+    check_expiry(payload.expires_at)?;
+
+    require_service_account(&app_state, workspace.workspace_id, service_account_id).await?;
+
+    // A new key hands out whatever the account's role grants.
+    let service_account = app_state
+        .db_client
+        .get_workspace_details(Some(service_account_id), Some(workspace.workspace_id))
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    check_can_delegate(&user, &workspace, &service_account.permissions)?;
+
+    let key = token::generate_service_account_key();
+
+    let api_key = app_state
+        .db_client
+        .create_service_account_key(
+            service_account_id,
+            &token::hash_opaque_token(&key),
+            payload.expires_at,
+        )
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = ServiceAccountKeyCreatedResponse {
+        status: "success",
+        key,
+        data: ServiceAccountKeyDto::from_key(&api_key),
+    };
+
+    Ok((StatusCode::CREATED, Json(response)))
+}
+
+pub async fn delete_service_account_key(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(_user): Extension<JwtAuthMiddleware>,
+    Extension(workspace): Extension<WorkspaceAuthMiddleware>,
+    Path((service_account_id, key_id)): Path<(Uuid, Uuid)>,
+) -> Result<impl IntoResponse, HttpError> {
+    require_service_account(&app_state, workspace.workspace_id, service_account_id).await?;
+
+    let deleted = app_state
+        .db_client
+        .delete_service_account_key(service_account_id, key_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if !deleted {
+        return Err(HttpError::new(
+            StatusCode::NOT_FOUND,
+            "Key not found".to_string(),
+        ));
+    }
+
+    let response = Response {
+        status: "success",
+        message: "Key deleted successfully".to_string(),
+    };
+
+    Ok(Json(response))
+}
@@ -1,24 +1,44 @@
 use std::sync::Arc;
 
-use axum::{Extension, Json, extract::Query, response::IntoResponse};
+use axum::{
+    Extension, Json,
+    extract::{Path, Query},
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+};
 use chrono::{Duration, Utc};
 use uuid::Uuid;
 use validator::Validate;
 
 use crate::{
     AppState,
-    database::{auth::AuthExt, user::UserExt},
+    config::cookie_config::SessionCookie,
+    constants::permissions,
+    database::{
+        auth::AuthExt, personal_access_token::PersonalAccessTokenExt, session::SessionExt,
+        two_factor::TwoFactorExt, user::UserExt, webauthn::WebauthnExt, workspace::WorkspaceExt,
+    },
     dtos::{
         Response,
         user::{
-            FilterUserDto, UserData, UserEmailChangeRequest, UserEmailChangeVerificationDto,
-            UserPasswordUpdate, UserResponse,
+            CreatePersonalAccessTokenDto, FilterUserDto, PasskeyDto, PasskeyList,
+            PasskeyListResponse, PasskeyResponse, PasswordUpdateResponse,
+            PersonalAccessTokenCreatedResponse, PersonalAccessTokenDto, PersonalAccessTokenList,
+            PersonalAccessTokenListResponse, RecoveryCodesData, RecoveryCodesResponse,
+            RenamePasskeyDto, SessionDto, SessionList, SessionListResponse, TwoFactorConfirmDto,
+            TwoFactorSetupData, TwoFactorSetupResponse, TwoFactorVerifyDto, UserData,
+            UserEmailChangeRequest, UserEmailChangeVerificationDto, UserPasswordUpdate,
+            UserResponse,
         },
     },
-    error::HttpError,
+    error::{ErrorMessage, HttpError},
+    handlers::auth::{
+        check_new_password, check_password_reuse, create_access_token, resend_verification_email,
+        verify_second_factor,
+    },
     mail::mail::send_email_change_notification,
     middleware::jwt_auth_middleware::JwtAuthMiddleware,
-    utils::password,
+    utils::{token, totp},
 };
 
 pub fn user_handler() -> axum::Router {
@@ -27,6 +47,39 @@ pub fn user_handler() -> axum::Router {
         .route("/update-password", axum::routing::put(update_user_password))
         .route("/change-email", axum::routing::put(change_email_request))
         .route("/verify-email", axum::routing::get(verify_email_change))
+        .route(
+            "/resend-verification",
+            axum::routing::post(resend_verification),
+        )
+        .route("/sessions", axum::routing::get(get_sessions))
+        .route(
+            "/sessions/revoke-others",
+            axum::routing::post(revoke_other_sessions),
+        )
+        .route(
+            "/sessions/{session_id}",
+            axum::routing::delete(revoke_session),
+        )
+        .route("/2fa/setup", axum::routing::post(setup_two_factor))
+        .route("/2fa/confirm", axum::routing::post(confirm_two_factor))
+        .route("/2fa/disable", axum::routing::post(disable_two_factor))
+        .route(
+            "/2fa/recovery-codes",
+            axum::routing::post(regenerate_recovery_codes),
+        )
+        .route("/passkeys", axum::routing::get(get_passkeys))
+        .route(
+            "/passkeys/{passkey_id}",
+            axum::routing::patch(rename_passkey).delete(delete_passkey),
+        )
+        .route(
+            "/tokens",
+            axum::routing::get(get_personal_access_tokens).post(create_personal_access_token),
+        )
+        .route(
+            "/tokens/{token_id}",
+            axum::routing::delete(delete_personal_access_token),
+        )
 }
 
 pub async fn get_me(
@@ -44,6 +97,30 @@ pub async fn get_me(
     Ok(Json(response))
 }
 
+pub async fn resend_verification(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JwtAuthMiddleware>,
+) -> Result<impl IntoResponse, HttpError> {
+    if user.user.email_verified == Some(true) {
+        return Ok(Json(Response {
+            status: "success",
+            message: "Email is already verified".to_string(),
+        }));
+    }
+
+    if let Some(retry_after) = resend_verification_email(&app_state, &user.user).await? {
+        return Err(HttpError::too_many_requests(
+            ErrorMessage::TooManyRequests.to_string(),
+            retry_after,
+        ));
+    }
+
+    Ok(Json(Response {
+        status: "success",
+        message: "Verification email sent".to_string(),
+    }))
+}
+
 pub async fn update_user_password(
     Extension(app_state): Extension<Arc<AppState>>,
     Extension(user): Extension<JwtAuthMiddleware>,
@@ -53,9 +130,13 @@ pub async fn update_user_password(
         .validate()
         .map_err(|e| HttpError::bad_request(e.to_string()))?;
 
+    let session_id = user.require_session()?.id;
     let user = user.user;
 
-    let password_match = password::compare(&payload.current_password, &user.password)
+    let password_match = app_state
+        .password_hasher
+        .compare(payload.current_password.clone(), user.password.clone())
+        .await?
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
     if !password_match {
@@ -64,21 +145,61 @@ pub async fn update_user_password(
         ));
     }
 
-    let hash_password = password::hash_password(&payload.new_password)
+    check_new_password(
+        &app_state,
+        &payload.new_password,
+        &[&user.name, &user.email],
+    )
+    .await?;
+
+    check_password_reuse(&app_state, &user, &payload.new_password).await?;
+
+    let hash_password = app_state
+        .password_hasher
+        .hash(payload.new_password.clone())
+        .await?
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
     app_state
         .db_client
-        .update_user_password(user.id, &hash_password)
+        .update_user_password(
+            user.id,
+            &hash_password,
+            app_state.password_policy.history_size,
+        )
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
-    let response = Response {
+    // Other devices are signed out and personal access tokens revoked; this
+    // one keeps its session with a token issued after the change.
+    app_state
+        .db_client
+        .revoke_other_sessions(user.id, session_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    app_state
+        .db_client
+        .delete_user_personal_access_tokens(user.id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let token = create_access_token(&app_state, user.id, session_id)?;
+
+    let mut headers = HeaderMap::new();
+    app_state
+        .cookie_config
+        .set(&mut headers, SessionCookie::AccessToken, &token);
+
+    let mut response = Json(PasswordUpdateResponse {
         status: "success",
         message: "Password updated successfully".to_string(),
-    };
+        token,
+    })
+    .into_response();
+    response.headers_mut().extend(headers);
 
-    Ok(Json(response))
+    Ok(response)
 }
 
 pub async fn change_email_request(
@@ -91,12 +212,17 @@ pub async fn change_email_request(
         .map_err(|e| HttpError::bad_request(e.to_string()))?;
 
     let user = user.user;
-    let token = Uuid::new_v4();
+    let token = token::generate_opaque_token();
     let token_expires_at = Utc::now() + Duration::days(1);
 
     let user = app_state
         .db_client
-        .update_user_email_request(user.id, payload.email.clone(), token, token_expires_at)
+        .update_user_email_request(
+            user.id,
+            payload.email.clone(),
+            &token::hash_opaque_token(&token),
+            token_expires_at,
+        )
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
@@ -106,7 +232,7 @@ pub async fn change_email_request(
         &user.email,
         &payload.email,
         &user.name,
-        &token.to_string(),
+        &token,
     )
     .await;
 
@@ -127,17 +253,445 @@ pub async fn verify_email_change(
         .validate()
         .map_err(|e| HttpError::bad_request(e.to_string()))?;
 
-    let token = Uuid::parse_str(&query_params.token).unwrap();
+    let changed = app_state
+        .db_client
+        .verify_email_change(&token::hash_opaque_token(&query_params.token))
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if !changed {
+        return Err(HttpError::bad_request(
+            ErrorMessage::InvalidToken.to_string(),
+        ));
+    }
+
+    let response = Response {
+        status: "success",
+        message: "Email changed successfully".to_string(),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn get_sessions(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JwtAuthMiddleware>,
+) -> Result<impl IntoResponse, HttpError> {
+    let current_session_id = user.require_session()?.id;
+    let sessions = app_state
+        .db_client
+        .get_user_sessions(user.user.id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = SessionListResponse {
+        status: "success",
+        data: SessionList {
+            sessions: sessions
+                .iter()
+                .map(|session| SessionDto::from_session(session, current_session_id))
+                .collect(),
+        },
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn revoke_session(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JwtAuthMiddleware>,
+    Path(session_id): Path<Uuid>,
+) -> Result<impl IntoResponse, HttpError> {
+    let revoked = app_state
+        .db_client
+        .revoke_session(user.user.id, session_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if !revoked {
+        return Err(HttpError::new(
+            StatusCode::NOT_FOUND,
+            "Session not found".to_string(),
+        ));
+    }
+
+    let response = Response {
+        status: "success",
+        message: "Session signed out successfully".to_string(),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn revoke_other_sessions(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JwtAuthMiddleware>,
+) -> Result<impl IntoResponse, HttpError> {
+    let session = user.require_session()?;
+    let revoked = app_state
+        .db_client
+        .revoke_other_sessions(user.user.id, session.id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = Response {
+        status: "success",
+        message: format!("Signed out of {} other session(s)", revoked),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn setup_two_factor(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JwtAuthMiddleware>,
+) -> Result<impl IntoResponse, HttpError> {
+    let two_factor = app_state
+        .db_client
+        .get_two_factor(user.user.id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if two_factor.is_some_and(|two_factor| two_factor.enabled) {
+        return Err(HttpError::unique_constraint_violation(
+            ErrorMessage::TwoFactorAlreadyEnabled.to_string(),
+        ));
+    }
+
+    let secret = totp::generate_secret();
+    let otpauth_url = totp::otpauth_url(&secret, &app_state.env.totp_issuer, &user.user.email)
+        .ok_or(HttpError::server_error(
+            ErrorMessage::ServerError.to_string(),
+        ))?;
+
+    app_state
+        .db_client
+        .save_pending_two_factor(user.user.id, &secret)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = TwoFactorSetupResponse {
+        status: "success",
+        data: TwoFactorSetupData {
+            secret,
+            otpauth_url,
+        },
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn confirm_two_factor(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JwtAuthMiddleware>,
+    Json(payload): Json<TwoFactorConfirmDto>,
+) -> Result<impl IntoResponse, HttpError> {
+    payload
+        .validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let two_factor = app_state
+        .db_client
+        .get_two_factor(user.user.id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or(HttpError::bad_request(
+            ErrorMessage::TwoFactorNotEnabled.to_string(),
+        ))?;
+
+    if two_factor.enabled {
+        return Err(HttpError::unique_constraint_violation(
+            ErrorMessage::TwoFactorAlreadyEnabled.to_string(),
+        ));
+    }
+
+    let step = totp::verify_code(&two_factor.totp_secret, &payload.code, None).ok_or(
+        HttpError::bad_request(ErrorMessage::InvalidTwoFactorCode.to_string()),
+    )?;
+
+    let recovery_codes = totp::generate_recovery_codes();
+
+    app_state
+        .db_client
+        .enable_two_factor(
+            user.user.id,
+            step,
+            recovery_codes
+                .iter()
+                .map(|code| totp::hash_recovery_code(code))
+                .collect(),
+        )
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = RecoveryCodesResponse {
+        status: "success",
+        data: RecoveryCodesData { recovery_codes },
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn disable_two_factor(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JwtAuthMiddleware>,
+    Json(payload): Json<TwoFactorVerifyDto>,
+) -> Result<impl IntoResponse, HttpError> {
+    verify_second_factor(
+        &app_state,
+        user.user.id,
+        payload.code.as_deref(),
+        payload.recovery_code.as_deref(),
+    )
+    .await?;
 
     app_state
         .db_client
-        .verify_email_change(token)
+        .disable_two_factor(user.user.id)
         .await
         .map_err(|e| HttpError::server_error(e.to_string()))?;
 
     let response = Response {
         status: "success",
-        message: "Email changed successfully".to_string(),
+        message: "Two-factor authentication disabled".to_string(),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn regenerate_recovery_codes(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JwtAuthMiddleware>,
+    Json(payload): Json<TwoFactorVerifyDto>,
+) -> Result<impl IntoResponse, HttpError> {
+    verify_second_factor(
+        &app_state,
+        user.user.id,
+        payload.code.as_deref(),
+        payload.recovery_code.as_deref(),
+    )
+    .await?;
+
+    let recovery_codes = totp::generate_recovery_codes();
+
+    app_state
+        .db_client
+        .replace_recovery_codes(
+            user.user.id,
+            recovery_codes
+                .iter()
+                .map(|code| totp::hash_recovery_code(code))
+                .collect(),
+        )
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = RecoveryCodesResponse {
+        status: "success",
+        data: RecoveryCodesData { recovery_codes },
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn get_passkeys(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JwtAuthMiddleware>,
+) -> Result<impl IntoResponse, HttpError> {
+    let passkeys = app_state
+        .db_client
+        .get_user_passkeys(user.user.id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = PasskeyListResponse {
+        status: "success",
+        data: PasskeyList {
+            passkeys: passkeys.iter().map(PasskeyDto::from_passkey).collect(),
+        },
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn rename_passkey(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JwtAuthMiddleware>,
+    Path(passkey_id): Path<Uuid>,
+    Json(payload): Json<RenamePasskeyDto>,
+) -> Result<impl IntoResponse, HttpError> {
+    payload
+        .validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let passkey = app_state
+        .db_client
+        .rename_passkey(user.user.id, passkey_id, &payload.name)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or(HttpError::new(
+            StatusCode::NOT_FOUND,
+            "Passkey not found".to_string(),
+        ))?;
+
+    let response = PasskeyResponse {
+        status: "success",
+        data: PasskeyDto::from_passkey(&passkey),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn delete_passkey(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JwtAuthMiddleware>,
+    Path(passkey_id): Path<Uuid>,
+) -> Result<impl IntoResponse, HttpError> {
+    let deleted = app_state
+        .db_client
+        .delete_passkey(user.user.id, passkey_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if !deleted {
+        return Err(HttpError::new(
+            StatusCode::NOT_FOUND,
+            "Passkey not found".to_string(),
+        ));
+    }
+
+    let response = Response {
+        status: "success",
+        message: "Passkey deleted successfully".to_string(),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn get_personal_access_tokens(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JwtAuthMiddleware>,
+) -> Result<impl IntoResponse, HttpError> {
+    let tokens = app_state
+        .db_client
+        .get_personal_access_tokens(user.user.id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = PersonalAccessTokenListResponse {
+        status: "success",
+        data: PersonalAccessTokenList {
+            tokens: tokens
+                .iter()
+                .map(PersonalAccessTokenDto::from_token)
+                .collect(),
+        },
+    };
+
+    Ok(Json(response))
+}
+
+/// Creates a token that acts for the user in one workspace. It can only be
+/// granted permissions the user holds there, and at use it never has more
+/// than the user holds at that time.
+pub async fn create_personal_access_token(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JwtAuthMiddleware>,
+    Json(mut payload): Json<CreatePersonalAccessTokenDto>,
+) -> Result<impl IntoResponse, HttpError> {
+    payload
+        .validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    if let Some(unknown) = payload
+        .permissions
+        .iter()
+        .find(|permission| !permissions::ALL.contains(&permission.as_str()))
+    {
+        return Err(HttpError::bad_request(format!(
+            "Unknown permission: {}",
+            unknown
+        )));
+    }
+
+    if payload
+        .expires_at
+        .is_some_and(|expires_at| expires_at <= Utc::now())
+    {
+        return Err(HttpError::bad_request(
+            "Expiry must be in the future".to_string(),
+        ));
+    }
+
+    let membership = app_state
+        .db_client
+        .get_workspace_details(Some(user.user.id), Some(payload.workspace_id))
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => {
+                HttpError::forbidden(ErrorMessage::PermissionDenied.to_string())
+            }
+            e => HttpError::server_error(e.to_string()),
+        })?;
+
+    if payload
+        .permissions
+        .iter()
+        .any(|permission| !membership.permissions.contains(permission))
+    {
+        return Err(HttpError::forbidden(
+            ErrorMessage::PermissionDenied.to_string(),
+        ));
+    }
+
+    payload.permissions.sort();
+    payload.permissions.dedup();
+
+    let token = token::generate_personal_access_token();
+
+    let access_token = app_state
+        .db_client
+        .create_personal_access_token(
+            user.user.id,
+            payload.workspace_id,
+            &payload.name,
+            &token::hash_opaque_token(&token),
+            &payload.permissions,
+            payload.expires_at,
+        )
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = PersonalAccessTokenCreatedResponse {
+        status: "success",
+        token,
+        data: PersonalAccessTokenDto::from_token(&access_token),
+    };
+
+    Ok((StatusCode::CREATED, Json(response)))
+}
+
+pub async fn delete_personal_access_token(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JwtAuthMiddleware>,
+    Path(token_id): Path<Uuid>,
+) -> Result<impl IntoResponse, HttpError> {
+    let deleted = app_state
+        .db_client
+        .delete_personal_access_token(user.user.id, token_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    if !deleted {
+        return Err(HttpError::new(
+            StatusCode::NOT_FOUND,
+            "Token not found".to_string(),
+        ));
+    }
+
+    let response = Response {
+        status: "success",
+        message: "Token deleted successfully".to_string(),
     };
 
     Ok(Json(response))
@@ -0,0 +1,286 @@
+use std::sync::Arc;
+
+use axum::{Extension, Json, response::IntoResponse};
+use chrono::{Duration, Utc};
+use validator::Validate;
+
+use crate::{
+    AppState,
+    database::{auth::AuthExt, webauthn::WebauthnExt},
+    dtos::{
+        user::{PasskeyDto, PasskeyResponse},
+        webauthn::{
+            AuthenticatorSelection, CeremonyData, CeremonyResponse, CreationOptions,
+            CredentialDescriptor, CredentialParameter, FinishAuthenticationDto,
+            FinishRegistrationDto, RelyingPartyEntity, RequestOptions, UserEntity,
+        },
+    },
+    error::{ErrorMessage, HttpError},
+    handlers::auth::complete_login,
+    middleware::jwt_auth_middleware::{JwtAuthMiddleware, auth_middleware, check_email_verified},
+    models::WebauthnCredential,
+    utils::{
+        client_info::ClientInfo,
+        token,
+        webauthn::{self, RelyingParty},
+    },
+};
+
+pub fn webauthn_handler() -> axum::Router {
+    axum::Router::new()
+        .route(
+            "/register/start",
+            axum::routing::post(start_registration)
+                .layer(axum::middleware::from_fn(auth_middleware)),
+        )
+        .route(
+            "/register/finish",
+            axum::routing::post(finish_registration)
+                .layer(axum::middleware::from_fn(auth_middleware)),
+        )
+        .route(
+            "/authenticate/start",
+            axum::routing::post(start_authentication),
+        )
+        .route(
+            "/authenticate/finish",
+            axum::routing::post(finish_authentication),
+        )
+}
+
+const WEBAUTHN_CEREMONY_TIMEOUT_SECONDS: i64 = 5 * 60;
+const DEFAULT_PASSKEY_NAME: &str = "Passkey";
+
+fn relying_party(app_state: &AppState) -> RelyingParty<'_> {
+    RelyingParty {
+        id: &app_state.env.webauthn_rp_id,
+        origin: &app_state.env.webauthn_origin,
+    }
+}
+
+fn credential_descriptors(passkeys: &[WebauthnCredential]) -> Vec<CredentialDescriptor> {
+    passkeys
+        .iter()
+        .map(|passkey| CredentialDescriptor {
+            credential_type: "public-key",
+            id: passkey.credential_id.clone(),
+            transports: passkey.transports.clone(),
+        })
+        .collect()
+}
+
+fn ceremony_failed() -> HttpError {
+    HttpError::bad_request(ErrorMessage::PasskeyVerificationFailed.to_string())
+}
+
+pub async fn start_registration(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JwtAuthMiddleware>,
+) -> Result<impl IntoResponse, HttpError> {
+    let existing_passkeys = app_state
+        .db_client
+        .get_user_passkeys(user.user.id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let challenge = token::generate_opaque_token();
+    let ceremony_id = app_state
+        .db_client
+        .save_webauthn_challenge(
+            Some(user.user.id),
+            webauthn::REGISTRATION_CEREMONY,
+            &challenge,
+            Utc::now() + Duration::seconds(WEBAUTHN_CEREMONY_TIMEOUT_SECONDS),
+        )
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let options = CreationOptions {
+        challenge,
+        rp: RelyingPartyEntity {
+            id: app_state.env.webauthn_rp_id.clone(),
+            name: app_state.env.webauthn_rp_name.clone(),
+        },
+        user: UserEntity {
+            id: webauthn::user_handle(user.user.id),
+            name: user.user.email.clone(),
+            display_name: user.user.name.clone(),
+        },
+        pub_key_cred_params: webauthn::SUPPORTED_ALGORITHMS
+            .into_iter()
+            .map(|alg| CredentialParameter {
+                credential_type: "public-key",
+                alg,
+            })
+            .collect(),
+        timeout: WEBAUTHN_CEREMONY_TIMEOUT_SECONDS * 1000,
+        exclude_credentials: credential_descriptors(&existing_passkeys),
+        // Sign-in never names the credentials it accepts, so every passkey
+        // has to be discoverable.
+        authenticator_selection: AuthenticatorSelection {
+            resident_key: "required",
+            require_resident_key: true,
+            user_verification: "required",
+        },
+        attestation: "none",
+    };
+
+    Ok(Json(CeremonyResponse {
+        status: "success",
+        data: CeremonyData {
+            ceremony_id,
+            public_key: options,
+        },
+    }))
+}
+
+pub async fn finish_registration(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JwtAuthMiddleware>,
+    Json(payload): Json<FinishRegistrationDto>,
+) -> Result<impl IntoResponse, HttpError> {
+    payload
+        .validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    let ceremony = app_state
+        .db_client
+        .take_webauthn_challenge(payload.ceremony_id, webauthn::REGISTRATION_CEREMONY)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .filter(|ceremony| ceremony.user_id == Some(user.user.id))
+        .ok_or_else(ceremony_failed)?;
+
+    let credential = payload.credential;
+    let verified = webauthn::verify_registration(
+        &relying_party(&app_state),
+        &ceremony.challenge,
+        &credential.raw_id,
+        &credential.response.client_data_json,
+        &credential.response.attestation_object,
+    )?;
+
+    let name = payload.name.as_deref().unwrap_or(DEFAULT_PASSKEY_NAME);
+    let passkey = app_state
+        .db_client
+        .save_passkey(
+            user.user.id,
+            &verified,
+            &credential.response.transports,
+            name,
+        )
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(db_err) if db_err.is_unique_violation() => {
+                HttpError::unique_constraint_violation(
+                    ErrorMessage::PasskeyAlreadyRegistered.to_string(),
+                )
+            }
+            e => HttpError::server_error(e.to_string()),
+        })?;
+
+    Ok(Json(PasskeyResponse {
+        status: "success",
+        data: PasskeyDto::from_passkey(&passkey),
+    }))
+}
+
+/// Starts a passkey sign-in. The browser offers every discoverable passkey
+/// it holds for this site, so the response reveals nothing about which
+/// accounts exist or have passkeys.
+pub async fn start_authentication(
+    Extension(app_state): Extension<Arc<AppState>>,
+) -> Result<impl IntoResponse, HttpError> {
+    let challenge = token::generate_opaque_token();
+    let ceremony_id = app_state
+        .db_client
+        .save_webauthn_challenge(
+            None,
+            webauthn::AUTHENTICATION_CEREMONY,
+            &challenge,
+            Utc::now() + Duration::seconds(WEBAUTHN_CEREMONY_TIMEOUT_SECONDS),
+        )
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let options = RequestOptions {
+        challenge,
+        rp_id: app_state.env.webauthn_rp_id.clone(),
+        allow_credentials: Vec::new(),
+        timeout: WEBAUTHN_CEREMONY_TIMEOUT_SECONDS * 1000,
+        user_verification: "required",
+    };
+
+    Ok(Json(CeremonyResponse {
+        status: "success",
+        data: CeremonyData {
+            ceremony_id,
+            public_key: options,
+        },
+    }))
+}
+
+pub async fn finish_authentication(
+    Extension(app_state): Extension<Arc<AppState>>,
+    client_info: ClientInfo,
+    Json(payload): Json<FinishAuthenticationDto>,
+) -> Result<impl IntoResponse, HttpError> {
+    let ceremony = app_state
+        .db_client
+        .take_webauthn_challenge(payload.ceremony_id, webauthn::AUTHENTICATION_CEREMONY)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or_else(ceremony_failed)?;
+
+    let credential = payload.credential;
+    let credential_id =
+        webauthn::encode_base64url(&webauthn::decode_base64url(&credential.raw_id)?);
+    let passkey = app_state
+        .db_client
+        .get_passkey_by_credential_id(&credential_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or_else(ceremony_failed)?;
+
+    // Discoverable credentials return the account's user handle, which must
+    // belong to the passkey that signed the assertion.
+    let user_handle = credential
+        .response
+        .user_handle
+        .as_deref()
+        .filter(|handle| !handle.is_empty())
+        .map(webauthn::decode_base64url)
+        .transpose()?;
+    if user_handle.is_some_and(|handle| handle != passkey.user_id.as_bytes()) {
+        return Err(ceremony_failed());
+    }
+
+    let sign_count = webauthn::verify_authentication(
+        &relying_party(&app_state),
+        &ceremony.challenge,
+        &credential.response.client_data_json,
+        &credential.response.authenticator_data,
+        &credential.response.signature,
+        &passkey.public_key,
+        passkey.sign_count,
+    )?;
+
+    app_state
+        .db_client
+        .record_passkey_use(passkey.id, i64::from(sign_count))
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let user = app_state
+        .db_client
+        .get_user(Some(passkey.user_id), None, None)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or(HttpError::unauthorized(
+            ErrorMessage::UserNoLongerExists.to_string(),
+        ))?;
+
+    check_email_verified(&app_state, &user, false)?;
+
+    complete_login(&app_state, &user, &client_info).await
+}
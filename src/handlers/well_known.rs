@@ -0,0 +1,18 @@
+use std::sync::Arc;
+
+use axum::{Extension, Json, http::header, response::IntoResponse};
+
+use crate::AppState;
+
+pub fn well_known_handler() -> axum::Router {
+    axum::Router::new().route("/jwks.json", axum::routing::get(jwks))
+}
+
+/// Public keys for verifying access tokens. Empty while tokens are signed
+/// with the shared HS256 secret.
+pub async fn jwks(Extension(app_state): Extension<Arc<AppState>>) -> impl IntoResponse {
+    (
+        [(header::CACHE_CONTROL, "public, max-age=300")],
+        Json(app_state.jwt_config.jwks()),
+    )
+}
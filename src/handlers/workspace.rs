@@ -1,30 +1,30 @@
 use std::sync::Arc;
 
-use axum::{
-    Extension, Json,
-    extract::Path,
-    http::{HeaderMap, header},
-    response::IntoResponse,
-};
-use axum_extra::extract::cookie::Cookie;
+use axum::{Extension, Json, extract::Path, http::HeaderMap, response::IntoResponse};
+use chrono::Utc;
 use uuid::Uuid;
 use validator::Validate;
 
 use crate::{
     AppState,
+    config::cookie_config::SessionCookie,
     constants::permissions,
-    database::workspace::WorkspaceExt,
+    database::{
+        security_policy::SecurityPolicyExt, two_factor::TwoFactorExt, workspace::WorkspaceExt,
+    },
     dtos::{
         Response,
         workspace::{
-            UpdateWorkspaceDto, WorkspaceCreateDto, WorkspaceCreateResponseDto, WorkspaceList,
-            WorkspaceListResponse, WorkspaceWithRoleAndPermissions,
+            SecurityPolicyDto, SecurityPolicyResponse, UpdateWorkspaceDto, WorkspaceCreateDto,
+            WorkspaceCreateResponseDto, WorkspaceList, WorkspaceListResponse,
+            WorkspaceTokenResponse, WorkspaceWithRoleAndPermissions,
         },
     },
-    error::HttpError,
+    error::{ErrorMessage, HttpError},
     middleware::{
         jwt_auth_middleware::JwtAuthMiddleware, workspace_middleware::WorkspaceAuthMiddleware,
     },
+    utils::token::{self, WorkspaceClaims},
     workspace_auth,
 };
 
@@ -41,8 +41,18 @@ pub fn workspace_handler() -> axum::Router {
             axum::routing::delete(delete_workspace)
                 .layer(workspace_auth!(permissions::DELETE_WORKSPACE)),
         )
+        .route(
+            "/security-policy",
+            axum::routing::get(get_security_policy)
+                .put(update_security_policy)
+                .layer(workspace_auth!(permissions::MANAGE_SECURITY_POLICY)),
+        )
         .route("/", axum::routing::get(get_all_workspace))
         .route("/{workspace_id}", axum::routing::get(get_workspace_by_id))
+        .route(
+            "/{workspace_id}/token",
+            axum::routing::post(issue_workspace_token),
+        )
 }
 
 pub fn create_workspace_response(
@@ -51,17 +61,10 @@ pub fn create_workspace_response(
 ) -> Result<impl IntoResponse, HttpError> {
     let mut headers = HeaderMap::new();
 
-    let cookie_duration = time::Duration::minutes(app_state.env.jwt_maxage * 60);
-
-    let workspace_cookie = Cookie::build(("workspace", workspace_data.workspace.id.to_string()))
-        .path("/")
-        .http_only(true)
-        .max_age(cookie_duration)
-        .build();
-
-    headers.append(
-        header::SET_COOKIE,
-        workspace_cookie.to_string().parse().unwrap(),
+    app_state.cookie_config.set(
+        &mut headers,
+        SessionCookie::Workspace,
+        workspace_data.workspace.id.to_string(),
     );
 
     let response = Json(WorkspaceCreateResponseDto {
@@ -81,6 +84,8 @@ pub async fn create_workspace(
     Extension(user): Extension<JwtAuthMiddleware>,
     Json(payload): Json<WorkspaceCreateDto>,
 ) -> Result<impl IntoResponse, HttpError> {
+    user.require_session()?;
+
     payload
         .validate()
         .map_err(|e| HttpError::bad_request(e.to_string()))?;
@@ -173,6 +178,8 @@ pub async fn get_all_workspace(
     Extension(app_state): Extension<Arc<AppState>>,
     Extension(user): Extension<JwtAuthMiddleware>,
 ) -> Result<impl IntoResponse, HttpError> {
+    user.require_session()?;
+
     let workspaces = app_state
         .db_client
         .get_all_user_workspace(user.user.id)
@@ -192,6 +199,8 @@ pub async fn get_workspace_by_id(
     Extension(user): Extension<JwtAuthMiddleware>,
     Path(workspace_id): Path<Uuid>,
 ) -> Result<impl IntoResponse, HttpError> {
+    user.require_session()?;
+
     let workspace = app_state
         .db_client
         .get_workspace_details(Some(user.user.id), Some(workspace_id))
@@ -200,3 +209,125 @@ pub async fn get_workspace_by_id(
 
     create_workspace_response(workspace, app_state)
 }
+
+/// Exchanges the caller's access token for a short-lived token carrying their
+/// role and permissions in the workspace. It never outlives the caller's token.
+pub async fn issue_workspace_token(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JwtAuthMiddleware>,
+    Path(workspace_id): Path<Uuid>,
+) -> Result<impl IntoResponse, HttpError> {
+    user.require_session()?;
+
+    // Read the version first so a concurrent role change can only make the
+    // token stale, never let it carry newer permissions than its version.
+    let version = app_state
+        .db_client
+        .get_permissions_version(workspace_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?
+        .ok_or(HttpError::forbidden(
+            ErrorMessage::PermissionDenied.to_string(),
+        ))?;
+
+    let workspace = app_state
+        .db_client
+        .get_workspace_details(Some(user.user.id), Some(workspace_id))
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => {
+                HttpError::forbidden(ErrorMessage::PermissionDenied.to_string())
+            }
+            e => HttpError::server_error(e.to_string()),
+        })?;
+
+    let now = Utc::now().timestamp();
+    let expires_at = (now + app_state.env.workspace_token_maxage * 60).min(user.claims.exp as i64);
+
+    let claims = WorkspaceClaims {
+        id: workspace.workspace.id,
+        role_id: workspace.role_id,
+        permissions: workspace.permissions,
+        version,
+    };
+
+    let token = token::create_workspace_token(
+        &user.user.id.to_string(),
+        &user.claims.sid,
+        claims,
+        &app_state.jwt_config,
+        expires_at as usize,
+    )
+    .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    Ok(Json(WorkspaceTokenResponse {
+        status: "success",
+        token,
+        expires_in: expires_at - now,
+    }))
+}
+
+pub async fn get_security_policy(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(workspace): Extension<WorkspaceAuthMiddleware>,
+) -> Result<impl IntoResponse, HttpError> {
+    let policy = app_state
+        .db_client
+        .get_security_policy(workspace.workspace_id)
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = SecurityPolicyResponse {
+        status: "success",
+        data: policy
+            .as_ref()
+            .map(SecurityPolicyDto::from_policy)
+            .unwrap_or_default(),
+    };
+
+    Ok(Json(response))
+}
+
+pub async fn update_security_policy(
+    Extension(app_state): Extension<Arc<AppState>>,
+    Extension(user): Extension<JwtAuthMiddleware>,
+    Extension(workspace): Extension<WorkspaceAuthMiddleware>,
+    Json(payload): Json<SecurityPolicyDto>,
+) -> Result<impl IntoResponse, HttpError> {
+    payload
+        .validate()
+        .map_err(|e| HttpError::bad_request(e.to_string()))?;
+
+    // Requiring 2FA without having it would lock the caller out of the policy.
+    if payload.require_two_factor {
+        let two_factor = app_state
+            .db_client
+            .get_two_factor(user.user.id)
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+        if !two_factor.is_some_and(|t| t.enabled) {
+            return Err(HttpError::bad_request(
+                ErrorMessage::TwoFactorEnrollmentRequired.to_string(),
+            ));
+        }
+    }
+
+    let policy = app_state
+        .db_client
+        .save_security_policy(
+            workspace.workspace_id,
+            payload.require_two_factor,
+            payload.max_session_age_minutes,
+            payload.idle_timeout_minutes,
+        )
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+    let response = SecurityPolicyResponse {
+        status: "success",
+        data: SecurityPolicyDto::from_policy(&policy),
+    };
+
+    Ok(Json(response))
+}
@@ -45,6 +45,8 @@ pub async fn join_workspace(
     Extension(user): Extension<JwtAuthMiddleware>,
     Path(invite_code): Path<String>,
 ) -> Result<impl IntoResponse, HttpError> {
+    user.require_session()?;
+
     let workspace_id = app_state
         .db_client
         .join_workspace(user.user.id, &invite_code)
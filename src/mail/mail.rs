@@ -63,7 +63,7 @@ pub async fn send_password_reset_email(
     let subject = "Password Reset Request";
     let template_path = format!(
         "{}/{}",
-        mail_config.mail_template_path, "reset-password.html"
+        mail_config.mail_template_path, "reset-password-email.html"
     );
     let base_url = format!("{}/auth/reset-password", frontend_base_url);
     let verification_link = create_verification_link(&base_url, token);
@@ -112,3 +112,65 @@ pub async fn send_email_change_notification(
     )
     .await
 }
+
+pub async fn send_magic_link_email(
+    mail_config: &MailConfig,
+    to_email: &str,
+    frontend_base_url: &str,
+    name: &str,
+    token: &str,
+    code: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let subject = "Your sign-in link";
+    let template_path = format!(
+        "{}/{}",
+        mail_config.mail_template_path, "magic-link-email.html"
+    );
+    let base_url = format!("{}/auth/magic-link", frontend_base_url);
+    let sign_in_link = create_verification_link(&base_url, token);
+    let placeholders = vec![
+        ("{{ .Name }}".to_string(), name.to_string()),
+        ("{{ .ConfirmationURL }}".to_string(), sign_in_link),
+        ("{{ .Code }}".to_string(), code.to_string()),
+    ];
+    send_email(
+        mail_config,
+        to_email,
+        subject,
+        &template_path,
+        &placeholders,
+    )
+    .await
+}
+
+pub async fn send_account_locked_email(
+    mail_config: &MailConfig,
+    to_email: &str,
+    frontend_base_url: &str,
+    name: &str,
+    lockout_minutes: i64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let subject = "Your account has been locked";
+    let template_path = format!(
+        "{}/{}",
+        mail_config.mail_template_path, "account-locked-email.html"
+    );
+    let reset_link = format!("{}/auth/forgot-password", frontend_base_url);
+    let placeholders = vec![
+        ("{{ .Name }}".to_string(), name.to_string()),
+        ("{{ .Email }}".to_string(), to_email.to_string()),
+        (
+            "{{ .LockoutMinutes }}".to_string(),
+            lockout_minutes.to_string(),
+        ),
+        ("{{ .ResetURL }}".to_string(), reset_link),
+    ];
+    send_email(
+        mail_config,
+        to_email,
+        subject,
+        &template_path,
+        &placeholders,
+    )
+    .await
+}
@@ -1,7 +1,7 @@
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc};
 
 use axum::http::{
-    HeaderValue, Method,
+    HeaderName, HeaderValue, Method,
     header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE},
 };
 use dotenv::dotenv;
@@ -10,9 +10,21 @@ use tower_http::cors::CorsLayer;
 use tracing_subscriber::filter::LevelFilter;
 
 use crate::{
-    config::{config::Config, mail_config::MailConfig},
+    config::{
+        config::{Config, RateLimitBackend},
+        cookie_config::CookieConfig,
+        jwt_config::JwtConfig,
+        mail_config::MailConfig,
+        password_policy::PasswordPolicy,
+    },
     database::DBClient,
     routes::create_router,
+    utils::{
+        breached_passwords::{self, BreachChecker},
+        csrf,
+        oidc::OidcClient,
+        password::{self, PasswordHasherPool},
+    },
 };
 
 mod config;
@@ -25,6 +37,8 @@ mod mail;
 mod middleware;
 mod models;
 mod routes;
+#[cfg(test)]
+mod tests;
 mod utils;
 
 #[derive(Debug, Clone)]
@@ -32,6 +46,12 @@ pub struct AppState {
     pub env: Config,
     pub db_client: DBClient,
     pub mail_config: MailConfig,
+    pub jwt_config: JwtConfig,
+    pub cookie_config: CookieConfig,
+    pub password_policy: PasswordPolicy,
+    pub breach_checker: Option<Arc<dyn BreachChecker>>,
+    pub password_hasher: PasswordHasherPool,
+    pub oidc_client: OidcClient,
 }
 
 #[tokio::main]
@@ -44,6 +64,9 @@ async fn main() {
 
     let config = Config::init();
     let mail_config = MailConfig::init();
+    let jwt_config = JwtConfig::init();
+    let password_policy = PasswordPolicy::init();
+    password::init();
     let pool = match PgPoolOptions::new()
         .max_connections(10)
         .connect(&config.database_url)
@@ -61,16 +84,40 @@ async fn main() {
 
     let cors = CorsLayer::new()
         .allow_origin("http://localhost:3000".parse::<HeaderValue>().unwrap())
-        .allow_headers([AUTHORIZATION, ACCEPT, CONTENT_TYPE])
+        .allow_headers([
+            AUTHORIZATION,
+            ACCEPT,
+            CONTENT_TYPE,
+            HeaderName::from_static(csrf::CSRF_HEADER),
+        ])
         .allow_credentials(true)
-        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE]);
+        .allow_methods([
+            Method::GET,
+            Method::POST,
+            Method::PUT,
+            Method::PATCH,
+            Method::DELETE,
+        ]);
 
     let db_client = DBClient::new(pool);
+    if let Err(e) = db_client.watch_permissions_versions().await {
+        println!("Failed to listen for permissions changes: {}", e);
+        std::process::exit(1)
+    }
+    if config.rate_limit_backend == RateLimitBackend::Postgres {
+        db_client.sweep_rate_limit_buckets();
+    }
 
     let app_state = AppState {
         env: config.clone(),
         db_client: db_client,
         mail_config: mail_config,
+        jwt_config,
+        cookie_config: CookieConfig::init(&config),
+        password_policy,
+        breach_checker: breached_passwords::from_env(),
+        password_hasher: PasswordHasherPool::init(),
+        oidc_client: OidcClient::new(),
     };
 
     let app = create_router(Arc::new(app_state.clone())).layer(cors.clone());
@@ -83,5 +130,10 @@ async fn main() {
         .await
         .unwrap();
 
-    let _ = axum::serve(listener, app).await.unwrap();
+    let _ = axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .unwrap();
 }
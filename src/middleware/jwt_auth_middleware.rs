@@ -1,51 +1,164 @@
 use std::sync::Arc;
 
-use axum::{Extension, extract::Request, http::header, middleware::Next, response::IntoResponse};
+use axum::{
+    Extension,
+    extract::{OriginalUri, Request},
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
 use axum_extra::extract::CookieJar;
+use chrono::{Duration, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::{
     AppState,
-    database::auth::AuthExt,
+    config::config::EmailVerificationPolicy,
+    config::cookie_config::SessionCookie,
+    database::{
+        auth::AuthExt, personal_access_token::PersonalAccessTokenExt,
+        revoked_token::RevokedTokenExt, service_account::ServiceAccountExt, session::SessionExt,
+        workspace::WorkspaceExt,
+    },
     error::{ErrorMessage, HttpError},
-    models::User,
-    utils::token,
+    models::{User, UserSession},
+    utils::{
+        csrf,
+        token::{self, TokenClaims, WorkspaceClaims},
+    },
 };
 
+/// The authenticated user. Requests made with a personal access token or a
+/// service account key have no session, and their claims carry the workspace
+/// scope of the credential.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JwtAuthMiddleware {
     pub user: User,
+    pub claims: TokenClaims,
+    pub session: Option<UserSession>,
+}
+
+impl JwtAuthMiddleware {
+    /// The login session behind the request, for routes that personal access
+    /// tokens and service accounts may not use.
+    pub fn require_session(&self) -> Result<&UserSession, HttpError> {
+        self.session.as_ref().ok_or(HttpError::forbidden(
+            ErrorMessage::PersonalAccessTokenNotAllowed.to_string(),
+        ))
+    }
+}
+
+/// Rejects users whose email is still unverified after the grace period when
+/// `EMAIL_VERIFICATION_POLICY` blocks what they are doing: signing in at all,
+/// or acting on workspaces if `workspace_action` is set.
+pub fn check_email_verified(
+    app_state: &AppState,
+    user: &User,
+    workspace_action: bool,
+) -> Result<(), HttpError> {
+    let blocked = match app_state.env.email_verification_policy {
+        EmailVerificationPolicy::Allow => false,
+        EmailVerificationPolicy::BlockWorkspaces => workspace_action,
+        EmailVerificationPolicy::BlockLogin => true,
+    };
+    if !blocked || user.email_verified == Some(true) {
+        return Ok(());
+    }
+
+    let grace_period = Duration::hours(app_state.env.email_verification_grace_hours);
+    if user
+        .created_at
+        .is_some_and(|created_at| Utc::now() < created_at + grace_period)
+    {
+        return Ok(());
+    }
+
+    Err(HttpError::forbidden(
+        ErrorMessage::EmailNotVerified.to_string(),
+    ))
 }
 
 pub async fn auth_middleware(
     cookie_jar: CookieJar,
     Extension(app_state): Extension<Arc<AppState>>,
-    mut req: Request,
+    req: Request,
     next: Next,
 ) -> Result<impl IntoResponse, HttpError> {
-    let cookie = cookie_jar
-        .get("token")
-        .map(|c| c.value().to_string())
-        .or_else(|| {
-            req.headers()
-                .get(header::AUTHORIZATION)
-                .and_then(|h| h.to_str().ok())
-                .and_then(|h| {
-                    if h.starts_with("Bearer ") {
-                        Some(h.trim_start_matches("Bearer ").to_string())
-                    } else {
-                        None
-                    }
-                })
-        });
-
-    let token = cookie.ok_or(HttpError::unauthorized(
-        ErrorMessage::TokenNotProvided.to_string(),
-    ))?;
+    authenticate(cookie_jar, &app_state, req, next, false).await
+}
+
+/// [`auth_middleware`] for routes that act on workspaces, which
+/// `EMAIL_VERIFICATION_POLICY=block_workspaces` closes to unverified users.
+pub async fn workspace_access_middleware(
+    cookie_jar: CookieJar,
+    Extension(app_state): Extension<Arc<AppState>>,
+    req: Request,
+    next: Next,
+) -> Result<impl IntoResponse, HttpError> {
+    authenticate(cookie_jar, &app_state, req, next, true).await
+}
+
+async fn authenticate(
+    cookie_jar: CookieJar,
+    app_state: &AppState,
+    mut req: Request,
+    next: Next,
+    workspace_action: bool,
+) -> Result<Response, HttpError> {
+    let bearer = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .map(str::to_string);
 
-    let token_details = match token::decode_token(&token, app_state.env.jwt_secret.as_bytes()) {
-        Ok(details) => details,
+    let from_cookie = bearer.is_none();
+    let token = match bearer {
+        Some(token) => token,
+        None => app_state
+            .cookie_config
+            .get(&cookie_jar, SessionCookie::AccessToken)
+            .map(str::to_string)
+            .ok_or(HttpError::unauthorized(
+                ErrorMessage::TokenNotProvided.to_string(),
+            ))?,
+    };
+
+    // Only session tokens are ever set as cookies.
+    if from_cookie
+        && (token.starts_with(token::PERSONAL_ACCESS_TOKEN_PREFIX)
+            || token.starts_with(token::SERVICE_ACCOUNT_KEY_PREFIX))
+    {
+        return Err(HttpError::unauthorized(
+            ErrorMessage::InvalidToken.to_string(),
+        ));
+    }
+
+    if token.starts_with(token::PERSONAL_ACCESS_TOKEN_PREFIX) {
+        if !workspace_action {
+            return Err(HttpError::forbidden(
+                ErrorMessage::PersonalAccessTokenNotAllowed.to_string(),
+            ));
+        }
+        let auth = authenticate_personal_access_token(app_state, &token).await?;
+        req.extensions_mut().insert(auth);
+        return Ok(next.run(req).await);
+    }
+
+    if token.starts_with(token::SERVICE_ACCOUNT_KEY_PREFIX) {
+        if !workspace_action {
+            return Err(HttpError::forbidden(
+                ErrorMessage::PersonalAccessTokenNotAllowed.to_string(),
+            ));
+        }
+        let auth = authenticate_service_account_key(app_state, &token).await?;
+        req.extensions_mut().insert(auth);
+        return Ok(next.run(req).await);
+    }
+
+    let claims = match token::decode_token(&token, &app_state.jwt_config) {
+        Ok(claims) => claims,
         Err(_) => {
             return Err(HttpError::unauthorized(
                 ErrorMessage::InvalidToken.to_string(),
@@ -53,9 +166,37 @@ pub async fn auth_middleware(
         }
     };
 
-    let user_id = Uuid::parse_str(&token_details)
+    let user_id = Uuid::parse_str(&claims.sub)
+        .map_err(|_| HttpError::unauthorized(ErrorMessage::InvalidToken.to_string()))?;
+
+    let session_id = Uuid::parse_str(&claims.sid)
         .map_err(|_| HttpError::unauthorized(ErrorMessage::InvalidToken.to_string()))?;
 
+    // The browser attaches cookies to cross-site requests too.
+    // Nested routers see a shortened URI; exemptions use the full path.
+    if from_cookie {
+        let path = req
+            .extensions()
+            .get::<OriginalUri>()
+            .map_or(req.uri().path(), |uri| uri.path());
+        csrf::verify_request(app_state, session_id, req.method(), path, req.headers())?;
+    }
+
+    let jti = Uuid::parse_str(&claims.jti)
+        .map_err(|_| HttpError::unauthorized(ErrorMessage::InvalidToken.to_string()))?;
+
+    let revoked = app_state
+        .db_client
+        .is_token_revoked(jti)
+        .await
+        .map_err(|_| HttpError::server_error(ErrorMessage::ServerError.to_string()))?;
+
+    if revoked {
+        return Err(HttpError::unauthorized(
+            ErrorMessage::InvalidToken.to_string(),
+        ));
+    }
+
     let user = app_state
         .db_client
         .get_user(Some(user_id), None, None)
@@ -66,6 +207,179 @@ pub async fn auth_middleware(
         ErrorMessage::UserNoLongerExists.to_string(),
     ))?;
 
-    req.extensions_mut().insert(JwtAuthMiddleware { user });
+    // Changing the password signs out every token issued before the change,
+    // including one issued earlier in the same second.
+    if user
+        .password_changed_at
+        .is_some_and(|changed_at| claims.iat <= token::epoch_seconds(changed_at))
+    {
+        return Err(HttpError::unauthorized(
+            ErrorMessage::SessionRevoked.to_string(),
+        ));
+    }
+
+    check_email_verified(app_state, &user, workspace_action)?;
+
+    let session = app_state
+        .db_client
+        .touch_session(user.id, session_id)
+        .await
+        .map_err(|_| HttpError::server_error(ErrorMessage::ServerError.to_string()))?
+        .ok_or(HttpError::unauthorized(
+            ErrorMessage::SessionRevoked.to_string(),
+        ))?;
+
+    req.extensions_mut().insert(JwtAuthMiddleware {
+        user,
+        claims,
+        session: Some(session),
+    });
     Ok(next.run(req).await)
 }
+
+/// Resolves a personal access token to its user, with claims scoped to the
+/// token's workspace and the permissions the user holds there today that the
+/// token was granted.
+async fn authenticate_personal_access_token(
+    app_state: &AppState,
+    token: &str,
+) -> Result<JwtAuthMiddleware, HttpError> {
+    let invalid_token = || HttpError::unauthorized(ErrorMessage::InvalidToken.to_string());
+
+    let access_token = app_state
+        .db_client
+        .use_personal_access_token(&token::hash_opaque_token(token))
+        .await
+        .map_err(|_| HttpError::server_error(ErrorMessage::ServerError.to_string()))?
+        .ok_or_else(invalid_token)?;
+
+    let user = app_state
+        .db_client
+        .get_user(Some(access_token.user_id), None, None)
+        .await
+        .map_err(|_| HttpError::server_error(ErrorMessage::ServerError.to_string()))?
+        .ok_or(HttpError::unauthorized(
+            ErrorMessage::UserNoLongerExists.to_string(),
+        ))?;
+
+    check_email_verified(app_state, &user, true)?;
+
+    let membership = app_state
+        .db_client
+        .get_workspace_details(Some(user.id), Some(access_token.workspace_id))
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => {
+                HttpError::forbidden(ErrorMessage::PermissionDenied.to_string())
+            }
+            _ => HttpError::server_error(ErrorMessage::ServerError.to_string()),
+        })?;
+
+    let version = app_state
+        .db_client
+        .get_permissions_version(access_token.workspace_id)
+        .await
+        .map_err(|_| HttpError::server_error(ErrorMessage::ServerError.to_string()))?
+        .ok_or_else(invalid_token)?;
+
+    let permissions = membership
+        .permissions
+        .into_iter()
+        .filter(|permission| access_token.permissions.contains(permission))
+        .collect();
+
+    let claims = TokenClaims {
+        sub: user.id.to_string(),
+        sid: String::new(),
+        jti: access_token.id.to_string(),
+        iss: app_state.jwt_config.issuer.clone(),
+        aud: None,
+        iat: token::epoch_seconds(access_token.created_at),
+        exp: access_token
+            .expires_at
+            .map_or(usize::MAX, |expires_at| expires_at.timestamp() as usize),
+        workspace: Some(WorkspaceClaims {
+            id: access_token.workspace_id,
+            role_id: membership.role_id,
+            permissions,
+            version,
+        }),
+    };
+
+    Ok(JwtAuthMiddleware {
+        user,
+        claims,
+        session: None,
+    })
+}
+
+/// Resolves a service account API key to its account, with claims for the
+/// account's workspace and everything its role allows there.
+async fn authenticate_service_account_key(
+    app_state: &AppState,
+    key: &str,
+) -> Result<JwtAuthMiddleware, HttpError> {
+    let invalid_token = || HttpError::unauthorized(ErrorMessage::InvalidToken.to_string());
+
+    let api_key = app_state
+        .db_client
+        .use_service_account_key(&token::hash_opaque_token(key))
+        .await
+        .map_err(|_| HttpError::server_error(ErrorMessage::ServerError.to_string()))?
+        .ok_or_else(invalid_token)?;
+
+    let user = app_state
+        .db_client
+        .get_user(Some(api_key.service_account_id), None, None)
+        .await
+        .map_err(|_| HttpError::server_error(ErrorMessage::ServerError.to_string()))?
+        .ok_or(HttpError::unauthorized(
+            ErrorMessage::UserNoLongerExists.to_string(),
+        ))?;
+
+    let workspace_id = user
+        .service_account_workspace_id
+        .ok_or_else(invalid_token)?;
+
+    let membership = app_state
+        .db_client
+        .get_workspace_details(Some(user.id), Some(workspace_id))
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => {
+                HttpError::forbidden(ErrorMessage::PermissionDenied.to_string())
+            }
+            _ => HttpError::server_error(ErrorMessage::ServerError.to_string()),
+        })?;
+
+    let version = app_state
+        .db_client
+        .get_permissions_version(workspace_id)
+        .await
+        .map_err(|_| HttpError::server_error(ErrorMessage::ServerError.to_string()))?
+        .ok_or_else(invalid_token)?;
+
+    let claims = TokenClaims {
+        sub: user.id.to_string(),
+        sid: String::new(),
+        jti: api_key.id.to_string(),
+        iss: app_state.jwt_config.issuer.clone(),
+        aud: None,
+        iat: token::epoch_seconds(api_key.created_at),
+        exp: api_key
+            .expires_at
+            .map_or(usize::MAX, |expires_at| expires_at.timestamp() as usize),
+        workspace: Some(WorkspaceClaims {
+            id: workspace_id,
+            role_id: membership.role_id,
+            permissions: membership.permissions,
+            version,
+        }),
+    };
+
+    Ok(JwtAuthMiddleware {
+        user,
+        claims,
+        session: None,
+    })
+}
@@ -1,2 +1,3 @@
 pub mod jwt_auth_middleware;
+pub mod rate_limit;
 pub mod workspace_middleware;
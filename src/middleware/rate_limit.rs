@@ -0,0 +1,233 @@
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use axum::{
+    body::{Body, to_bytes},
+    extract::{FromRequestParts, Request},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use tower::{Layer, Service};
+
+use crate::{
+    AppState,
+    config::config::RateLimitBackend,
+    database::{DBClient, rate_limit::RateLimitExt},
+    error::{ErrorMessage, HttpError},
+    utils::{client_info::ClientInfo, login_throttle, rate_limit::BucketPolicy},
+};
+
+/// Same as axum's default limit for JSON bodies.
+const MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+/// The in-memory store drops full buckets once it holds this many.
+const MEMORY_PRUNE_THRESHOLD: usize = 10_000;
+
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryBucket {
+    tokens: f64,
+    updated_at: DateTime<Utc>,
+    full_at: DateTime<Utc>,
+}
+
+/// Where token buckets live.
+#[derive(Debug, Clone)]
+pub enum RateLimitStore {
+    Memory(Arc<Mutex<HashMap<String, MemoryBucket>>>),
+    Postgres(DBClient),
+}
+
+impl RateLimitStore {
+    pub fn new(app_state: &AppState) -> Self {
+        match app_state.env.rate_limit_backend {
+            RateLimitBackend::Memory => {
+                RateLimitStore::Memory(Arc::new(Mutex::new(HashMap::new())))
+            }
+            RateLimitBackend::Postgres => RateLimitStore::Postgres(app_state.db_client.clone()),
+        }
+    }
+
+    /// Takes a token for `key`, returning the seconds to wait if there is none.
+    async fn take(&self, key: &str, policy: &BucketPolicy) -> Result<Option<u64>, HttpError> {
+        match self {
+            RateLimitStore::Memory(buckets) => {
+                let now = Utc::now();
+                let mut buckets = buckets.lock().unwrap_or_else(|e| e.into_inner());
+                if buckets.len() >= MEMORY_PRUNE_THRESHOLD {
+                    buckets.retain(|_, bucket| bucket.full_at > now);
+                }
+
+                let bucket = buckets
+                    .get(key)
+                    .map(|bucket| (bucket.tokens, bucket.updated_at))
+                    .unwrap_or((f64::from(policy.capacity), now));
+                let (tokens, retry_after) = policy.take(bucket.0, bucket.1, now);
+                buckets.insert(
+                    key.to_string(),
+                    MemoryBucket {
+                        tokens,
+                        updated_at: now,
+                        full_at: policy.full_at(tokens, now),
+                    },
+                );
+
+                Ok(retry_after)
+            }
+            RateLimitStore::Postgres(db_client) => db_client
+                .take_rate_limit_token(key, policy)
+                .await
+                .map_err(|e| HttpError::server_error(e.to_string())),
+        }
+    }
+}
+
+/// The buckets a route draws from. Each configured key gets its own bucket,
+/// and a request must find a token in all of them.
+#[derive(Debug, Clone, Default)]
+pub struct RateLimit {
+    per_ip: Option<BucketPolicy>,
+    per_email: Option<BucketPolicy>,
+}
+
+impl RateLimit {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limits each client IP, as reported by [`ClientInfo`].
+    pub fn per_ip(mut self, policy: BucketPolicy) -> Self {
+        self.per_ip = Some(policy);
+        self
+    }
+
+    /// Limits each normalised `email` in the JSON body. Requests without one
+    /// are only limited by IP.
+    pub fn per_email(mut self, policy: BucketPolicy) -> Self {
+        self.per_email = Some(policy);
+        self
+    }
+}
+
+/// Tower layer that applies a [`RateLimit`] to each configured path. Paths
+/// are matched exactly against the URI the layer sees, so nested routers
+/// should be configured with the path relative to where the layer is added.
+///
+/// Must run inside the `Extension<Arc<AppState>>` layer so that client IPs
+/// honour `TRUST_PROXY_HEADERS`.
+#[derive(Debug, Clone)]
+pub struct RateLimitLayer {
+    store: RateLimitStore,
+    routes: Arc<HashMap<&'static str, RateLimit>>,
+}
+
+impl RateLimitLayer {
+    pub fn new(store: RateLimitStore) -> Self {
+        RateLimitLayer {
+            store,
+            routes: Arc::new(HashMap::new()),
+        }
+    }
+
+    pub fn route(mut self, path: &'static str, limit: RateLimit) -> Self {
+        Arc::make_mut(&mut self.routes).insert(path, limit);
+        self
+    }
+
+    /// Takes a token from every bucket the request falls into. The body is
+    /// buffered to read the email and handed back for the handler.
+    async fn check(&self, limit: &RateLimit, request: Request) -> Result<Request, HttpError> {
+        let path = request.uri().path().to_string();
+        let (mut parts, body) = request.into_parts();
+        let client_info = ClientInfo::from_request_parts(&mut parts, &())
+            .await
+            .unwrap_or_default();
+
+        if let (Some(policy), Some(ip)) = (&limit.per_ip, &client_info.ip_address) {
+            let key = format!("{}:ip:{}", path, ip);
+            if let Some(retry_after) = self.store.take(&key, policy).await? {
+                return Err(too_many_requests(retry_after));
+            }
+        }
+
+        let body = match &limit.per_email {
+            Some(policy) => {
+                let bytes = to_bytes(body, MAX_BODY_BYTES)
+                    .await
+                    .map_err(|e| HttpError::bad_request(e.to_string()))?;
+                let email = serde_json::from_slice::<serde_json::Value>(&bytes)
+                    .ok()
+                    .and_then(|json| json.get("email")?.as_str().map(login_throttle::email_key))
+                    .filter(|email| !email.is_empty());
+
+                if let Some(email) = email {
+                    let key = format!("{}:email:{}", path, email);
+                    if let Some(retry_after) = self.store.take(&key, policy).await? {
+                        return Err(too_many_requests(retry_after));
+                    }
+                }
+                Body::from(bytes)
+            }
+            None => body,
+        };
+
+        Ok(Request::from_parts(parts, body))
+    }
+}
+
+fn too_many_requests(retry_after: u64) -> HttpError {
+    HttpError::too_many_requests(ErrorMessage::TooManyRequests.to_string(), retry_after)
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimitService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimitService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RateLimitService<S> {
+    inner: S,
+    layer: RateLimitLayer,
+}
+
+impl<S> Service<Request> for RateLimitService<S>
+where
+    S: Service<Request, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        // Keep the service that was polled ready and leave its clone behind.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let layer = self.layer.clone();
+
+        Box::pin(async move {
+            let Some(limit) = layer.routes.get(request.uri().path()) else {
+                return inner.call(request).await;
+            };
+
+            match layer.check(limit, request).await {
+                Ok(request) => inner.call(request).await,
+                Err(e) => Ok(e.into_response()),
+            }
+        })
+    }
+}
@@ -2,11 +2,16 @@ use std::sync::Arc;
 
 use axum::{Extension, extract::Request, middleware::Next, response::IntoResponse};
 use axum_extra::extract::CookieJar;
+use chrono::{Duration, Utc};
 use uuid::Uuid;
 
 use crate::{
     AppState,
-    database::workspace::WorkspaceExt,
+    config::cookie_config::SessionCookie,
+    database::{
+        security_policy::SecurityPolicyExt, session::SessionExt, two_factor::TwoFactorExt,
+        workspace::WorkspaceExt,
+    },
     error::{ErrorMessage, HttpError},
     middleware::jwt_auth_middleware::JwtAuthMiddleware,
 };
@@ -17,6 +22,8 @@ pub struct RequirePermission(pub &'static str);
 #[derive(Debug, Clone)]
 pub struct WorkspaceAuthMiddleware {
     pub workspace_id: Uuid,
+    /// What the caller may do in the workspace.
+    pub permissions: Vec<String>,
 }
 
 ///
@@ -35,26 +42,47 @@ pub async fn workspace_permission_middleware(
     permission: RequirePermission,
 ) -> Result<impl IntoResponse, HttpError> {
     let user_id = user.user.id;
-    let workspace_cookie = cookie_jar
-        .get("workspace")
-        .map(|c| c.value().to_string())
-        .ok_or(HttpError::unauthorized(
-            "Workspace id not found".to_string(),
-        ))?;
 
-    let workspace_id = Uuid::parse_str(&workspace_cookie)
-        .map_err(|_| HttpError::unauthorized("Invalid workspace id".to_string()))?;
+    // Workspace tokens carry the member's permissions, which stay valid
+    // until the workspace's permissions version moves on.
+    let (workspace_id, permissions) = match &user.claims.workspace {
+        Some(workspace) => {
+            let current_version = app_state
+                .db_client
+                .get_permissions_version(workspace.id)
+                .await
+                .map_err(|_| HttpError::server_error(ErrorMessage::ServerError.to_string()))?;
 
-    let workspace_details = app_state
-        .db_client
-        .get_workspace_details(Some(user_id), Some(workspace_id))
-        .await
-        .map_err(|_| HttpError::server_error(ErrorMessage::ServerError.to_string()))?;
+            if current_version != Some(workspace.version) {
+                return Err(HttpError::unauthorized(
+                    ErrorMessage::WorkspaceTokenOutdated.to_string(),
+                ));
+            }
+
+            (workspace.id, workspace.permissions.clone())
+        }
+        None => {
+            let workspace_cookie = app_state
+                .cookie_config
+                .get(&cookie_jar, SessionCookie::Workspace)
+                .ok_or(HttpError::unauthorized(
+                    "Workspace id not found".to_string(),
+                ))?;
+
+            let workspace_id = Uuid::parse_str(workspace_cookie)
+                .map_err(|_| HttpError::unauthorized("Invalid workspace id".to_string()))?;
+
+            let workspace_details = app_state
+                .db_client
+                .get_workspace_details(Some(user_id), Some(workspace_id))
+                .await
+                .map_err(|_| HttpError::server_error(ErrorMessage::ServerError.to_string()))?;
 
-    let has_permissions = workspace_details
-        .permissions
-        .iter()
-        .any(|p| p == permission.0);
+            (workspace_id, workspace_details.permissions)
+        }
+    };
+
+    let has_permissions = permissions.iter().any(|p| p == permission.0);
 
     if !has_permissions {
         return Err(HttpError::unauthorized(
@@ -62,12 +90,82 @@ pub async fn workspace_permission_middleware(
         ));
     }
 
-    req.extensions_mut()
-        .insert(WorkspaceAuthMiddleware { workspace_id });
+    enforce_security_policy(&app_state, &user, workspace_id).await?;
+
+    req.extensions_mut().insert(WorkspaceAuthMiddleware {
+        workspace_id,
+        permissions,
+    });
 
     Ok(next.run(req).await)
 }
 
+/// Rejects members whose account or session does not satisfy the workspace's
+/// security policy. Workspaces without a stored policy impose no extra rules.
+async fn enforce_security_policy(
+    app_state: &AppState,
+    user: &JwtAuthMiddleware,
+    workspace_id: Uuid,
+) -> Result<(), HttpError> {
+    let policy = app_state
+        .db_client
+        .get_security_policy(workspace_id)
+        .await
+        .map_err(|_| HttpError::server_error(ErrorMessage::ServerError.to_string()))?;
+
+    let Some(policy) = policy else {
+        return Ok(());
+    };
+
+    // Service accounts have no second factor to enroll; their keys are the
+    // only way in.
+    let is_service_account = user.user.service_account_workspace_id.is_some();
+
+    if policy.require_two_factor && !is_service_account {
+        let two_factor = app_state
+            .db_client
+            .get_two_factor(user.user.id)
+            .await
+            .map_err(|_| HttpError::server_error(ErrorMessage::ServerError.to_string()))?;
+
+        if !two_factor.is_some_and(|t| t.enabled) {
+            return Err(HttpError::forbidden(
+                ErrorMessage::TwoFactorEnrollmentRequired.to_string(),
+            ));
+        }
+    }
+
+    // Personal access tokens and service account keys have no session to age
+    // out; they expire on their own terms.
+    let Some(session) = &user.session else {
+        return Ok(());
+    };
+
+    let now = Utc::now();
+    let session_too_old = policy
+        .max_session_age_minutes
+        .is_some_and(|max_age| now - session.created_at > Duration::minutes(max_age.into()));
+    let session_idle = policy.idle_timeout_minutes.is_some_and(|idle_timeout| {
+        now - session.last_seen_at > Duration::minutes(idle_timeout.into())
+    });
+
+    if session_too_old || session_idle {
+        // The session's activity was already refreshed for this request, so it
+        // is revoked to stop a retry from slipping past the idle timeout.
+        app_state
+            .db_client
+            .revoke_session(user.user.id, session.id)
+            .await
+            .map_err(|_| HttpError::server_error(ErrorMessage::ServerError.to_string()))?;
+
+        return Err(HttpError::unauthorized(
+            ErrorMessage::ReauthenticationRequired.to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
 #[macro_export]
 macro_rules! workspace_auth {
     ($permission:expr) => {
@@ -6,37 +6,99 @@ use tower_http::trace::TraceLayer;
 use crate::{
     AppState,
     handlers::{
-        auth::auth_handler, permissions::permissions_handler, role::role_handler,
-        user::user_handler, workspace::workspace_handler, workspace_user::workspace_user_handler,
+        auth::auth_handler, metrics::metrics_handler, operator::operator_handler,
+        permissions::permissions_handler, role::role_handler,
+        service_account::service_account_handler, user::user_handler,
+        well_known::well_known_handler, workspace::workspace_handler,
+        workspace_user::workspace_user_handler,
+    },
+    middleware::{
+        jwt_auth_middleware::{auth_middleware, workspace_access_middleware},
+        rate_limit::{RateLimit, RateLimitLayer, RateLimitStore},
     },
-    middleware::jwt_auth_middleware::auth_middleware,
 };
 
 pub fn create_router(app_state: Arc<AppState>) -> Router {
+    // Endpoints that send email or accept unauthenticated tokens.
+    let limits = &app_state.env.rate_limits;
+    let rate_limit = RateLimitLayer::new(RateLimitStore::new(&app_state))
+        .route(
+            "/auth/register",
+            RateLimit::new()
+                .per_ip(limits.register_per_ip)
+                .per_email(limits.register_per_email),
+        )
+        .route(
+            "/auth/forgot-password",
+            RateLimit::new()
+                .per_ip(limits.forgot_password_per_ip)
+                .per_email(limits.forgot_password_per_email),
+        )
+        .route(
+            "/auth/resend-verification",
+            RateLimit::new()
+                .per_ip(limits.resend_verification_per_ip)
+                .per_email(limits.resend_verification_per_email),
+        )
+        .route(
+            "/auth/magic-link",
+            RateLimit::new()
+                .per_ip(limits.magic_link_per_ip)
+                .per_email(limits.magic_link_per_email),
+        )
+        .route(
+            "/auth/reset-password",
+            RateLimit::new().per_ip(limits.reset_password_per_ip),
+        )
+        .route(
+            "/auth/verify",
+            RateLimit::new().per_ip(limits.verify_per_ip),
+        );
+
     let api_route = Router::new()
         .nest("/auth", auth_handler())
         .nest(
             "/user",
             user_handler().layer(middleware::from_fn(auth_middleware)),
         )
+        .nest(
+            "/operator",
+            operator_handler().layer(middleware::from_fn(auth_middleware)),
+        )
         .nest(
             "/workspace",
-            workspace_handler().layer(middleware::from_fn(auth_middleware)),
+            workspace_handler().layer(middleware::from_fn(workspace_access_middleware)),
         )
         .nest(
             "/role",
-            role_handler().layer(middleware::from_fn(auth_middleware)),
+            role_handler().layer(middleware::from_fn(workspace_access_middleware)),
         )
         .nest(
             "/permissions",
-            permissions_handler().layer(middleware::from_fn(auth_middleware)),
+            permissions_handler().layer(middleware::from_fn(workspace_access_middleware)),
         )
         .nest(
             "/workspace_user",
-            workspace_user_handler().layer(middleware::from_fn(auth_middleware)),
+            workspace_user_handler().layer(middleware::from_fn(workspace_access_middleware)),
+        )
+        .nest(
+            "/service_account",
+            service_account_handler().layer(middleware::from_fn(workspace_access_middleware)),
         )
+        .layer(rate_limit)
         .layer(TraceLayer::new_for_http())
-        .layer(Extension(app_state));
+        .layer(Extension(app_state.clone()));
+
+    let well_known_route = well_known_handler().layer(Extension(app_state.clone()));
+
+    let router = Router::new()
+        .nest("/api", api_route)
+        .nest("/.well-known", well_known_route);
 
-    Router::new().nest("/api", api_route)
+    // Served outside `/api` so it can be kept off the public proxy.
+    if app_state.env.metrics_enabled {
+        router.nest("/metrics", metrics_handler().layer(Extension(app_state)))
+    } else {
+        router
+    }
 }
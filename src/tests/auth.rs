@@ -0,0 +1,316 @@
+use axum::http::StatusCode;
+use chrono::{Duration, Utc};
+use serde_json::json;
+
+use crate::{
+    database::auth::AuthExt,
+    tests::{OPERATOR_EMAIL, PASSWORD, TestApp},
+    utils::{token, totp},
+};
+
+#[tokio::test]
+async fn login_issues_a_session_and_throttles_wrong_passwords() {
+    let app = TestApp::new().await;
+    app.register("Ada", "ada@example.com").await;
+
+    let token = app.access_token("ada@example.com").await;
+    let me = app.get("/api/user/me").bearer(&token).send().await;
+    assert_eq!(me.status, StatusCode::OK, "{}", me.body);
+    assert_eq!(me.string("/data/user/email"), "ada@example.com");
+
+    let unknown = app.login("nobody@example.com", PASSWORD).await;
+    assert_eq!(unknown.status, StatusCode::BAD_REQUEST);
+    assert_eq!(unknown.message(), "WrongeCredentials");
+
+    for _ in 0..4 {
+        let wrong = app.login("ada@example.com", "Wrong-Password-1").await;
+        assert_eq!(wrong.status, StatusCode::BAD_REQUEST);
+        assert_eq!(wrong.message(), "WrongeCredentials");
+    }
+
+    // Past the free attempts even the right password has to wait.
+    let throttled = app.login("ada@example.com", PASSWORD).await;
+    assert_eq!(throttled.status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(throttled.message(), "TooManyLoginAttempts");
+    assert!(throttled.headers.contains_key("retry-after"));
+}
+
+#[tokio::test]
+async fn only_verified_operators_lift_lockouts() {
+    let app = TestApp::new().await;
+    let ada = app.register("Ada", "ada@example.com").await;
+    app.register("Mallory", "mallory@example.com").await;
+    let operator = app.register("Ops", OPERATOR_EMAIL).await;
+
+    for _ in 0..4 {
+        app.login("ada@example.com", "Wrong-Password-1").await;
+    }
+    let unlock = format!("/api/operator/users/{}/unlock", ada.id);
+
+    let mallory_token = app.access_token("mallory@example.com").await;
+    let refused = app.post(&unlock).bearer(&mallory_token).send().await;
+    assert_eq!(refused.status, StatusCode::FORBIDDEN);
+    assert_eq!(refused.message(), "PermissionDenied");
+
+    // Registering the operator's address does not make an operator.
+    let operator_token = app.access_token(OPERATOR_EMAIL).await;
+    let unverified = app.post(&unlock).bearer(&operator_token).send().await;
+    assert_eq!(unverified.status, StatusCode::FORBIDDEN);
+
+    app.app_state
+        .db_client
+        .verify_user(operator.id)
+        .await
+        .unwrap();
+    let unlocked = app.post(&unlock).bearer(&operator_token).send().await;
+    assert_eq!(unlocked.status, StatusCode::OK, "{}", unlocked.body);
+
+    let login = app.login("ada@example.com", PASSWORD).await;
+    assert_eq!(login.status, StatusCode::OK, "{}", login.body);
+}
+
+#[tokio::test]
+async fn password_change_rejects_tokens_from_just_before_it() {
+    let app = TestApp::new().await;
+    app.register("Edsger", "edsger@example.com").await;
+    let token = app.access_token("edsger@example.com").await;
+
+    // Moments after the token was issued, usually within the same second.
+    let user = app.user("edsger@example.com").await;
+    app.app_state
+        .db_client
+        .update_user_password(user.id, &user.password, 0)
+        .await
+        .unwrap();
+
+    let old = app.get("/api/user/me").bearer(&token).send().await;
+    assert_eq!(old.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(old.message(), "SessionRevoked");
+
+    let token = app.access_token("edsger@example.com").await;
+    let new = app.get("/api/user/me").bearer(&token).send().await;
+    assert_eq!(new.status, StatusCode::OK, "{}", new.body);
+}
+
+#[tokio::test]
+async fn password_reset_replaces_the_password_and_ends_sessions() {
+    let app = TestApp::new().await;
+    let user = app.register("Barbara", "barbara@example.com").await;
+
+    let login = app.login("barbara@example.com", PASSWORD).await;
+    assert_eq!(login.status, StatusCode::OK, "{}", login.body);
+    let refresh_token = login.string("refreshToken");
+
+    let requested = app
+        .post("/api/auth/forgot-password")
+        .json(json!({ "email": "barbara@example.com" }))
+        .send()
+        .await;
+    assert_eq!(requested.status, StatusCode::OK, "{}", requested.body);
+
+    let reset_token = "known-reset-token";
+    app.app_state
+        .db_client
+        .save_password_reset_token(
+            user.id,
+            &token::hash_opaque_token(reset_token),
+            Utc::now() + Duration::hours(1),
+        )
+        .await
+        .unwrap();
+
+    let new_password = "Brisk-Heron-77";
+    let reset = |password: &'static str| {
+        app.post("/api/auth/reset-password").json(json!({
+            "token": reset_token,
+            "password": password,
+            "passwordConfirm": password,
+        }))
+    };
+
+    // A rejected password leaves the token usable.
+    let weak = reset("password").send().await;
+    assert_eq!(weak.status, StatusCode::BAD_REQUEST);
+
+    let done = reset(new_password).send().await;
+    assert_eq!(done.status, StatusCode::OK, "{}", done.body);
+
+    let again = reset("Other-Finch-31").send().await;
+    assert_eq!(again.status, StatusCode::BAD_REQUEST);
+
+    let refreshed = app
+        .post("/api/auth/refresh")
+        .json(json!({ "refreshToken": refresh_token }))
+        .send()
+        .await;
+    assert_eq!(refreshed.status, StatusCode::UNAUTHORIZED);
+
+    let old = app.login("barbara@example.com", PASSWORD).await;
+    assert_eq!(old.status, StatusCode::BAD_REQUEST);
+    let new = app.login("barbara@example.com", new_password).await;
+    assert_eq!(new.status, StatusCode::OK, "{}", new.body);
+}
+
+/// Enables two-factor authentication for the user and returns the code that
+/// confirmed it and the recovery codes.
+async fn enable_two_factor(app: &TestApp, email: &str) -> (String, Vec<String>) {
+    let token = app.access_token(email).await;
+
+    let setup = app.post("/api/user/2fa/setup").bearer(&token).send().await;
+    assert_eq!(setup.status, StatusCode::OK, "{}", setup.body);
+    let code = totp::current_code(&setup.string("/data/secret"));
+
+    let confirm = app
+        .post("/api/user/2fa/confirm")
+        .bearer(&token)
+        .json(json!({ "code": code }))
+        .send()
+        .await;
+    assert_eq!(confirm.status, StatusCode::OK, "{}", confirm.body);
+    let recovery_codes = confirm.body["data"]["recoveryCodes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|code| code.as_str().unwrap().to_string())
+        .collect();
+
+    (code, recovery_codes)
+}
+
+async fn two_factor_challenge(app: &TestApp, email: &str) -> String {
+    let login = app.login(email, PASSWORD).await;
+    assert_eq!(login.status, StatusCode::OK, "{}", login.body);
+    assert_eq!(login.string("status"), "two_factor_required");
+    assert!(login.body.get("token").is_none());
+    login.string("challengeToken")
+}
+
+#[tokio::test]
+async fn two_factor_login_needs_a_fresh_code() {
+    let app = TestApp::new().await;
+    app.register("Grace", "grace@example.com").await;
+    let (code, recovery_codes) = enable_two_factor(&app, "grace@example.com").await;
+
+    let challenge_token = two_factor_challenge(&app, "grace@example.com").await;
+
+    // The code that confirmed the setup cannot be replayed.
+    let replayed = app
+        .post("/api/auth/login/2fa")
+        .json(json!({
+            "challengeToken": challenge_token,
+            "code": code,
+        }))
+        .send()
+        .await;
+    assert_eq!(replayed.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(replayed.message(), "InvalidTwoFactorCode");
+
+    let recovered = app
+        .post("/api/auth/login/2fa")
+        .json(json!({
+            "challengeToken": challenge_token,
+            "recoveryCode": recovery_codes[0],
+        }))
+        .send()
+        .await;
+    assert_eq!(recovered.status, StatusCode::OK, "{}", recovered.body);
+    assert!(!recovered.string("token").is_empty());
+
+    let challenge_token = two_factor_challenge(&app, "grace@example.com").await;
+    let reused = app
+        .post("/api/auth/login/2fa")
+        .json(json!({
+            "challengeToken": challenge_token,
+            "recoveryCode": recovery_codes[0],
+        }))
+        .send()
+        .await;
+    assert_eq!(reused.status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn two_factor_login_locks_after_wrong_codes() {
+    let app = TestApp::new().await;
+    app.register("Linus", "linus@example.com").await;
+    let (_, recovery_codes) = enable_two_factor(&app, "linus@example.com").await;
+
+    let challenge_token = two_factor_challenge(&app, "linus@example.com").await;
+    for _ in 0..4 {
+        let wrong = app
+            .post("/api/auth/login/2fa")
+            .json(json!({ "challengeToken": challenge_token, "code": "000000" }))
+            .send()
+            .await;
+        assert_eq!(wrong.status, StatusCode::UNAUTHORIZED);
+    }
+
+    let locked = app
+        .post("/api/auth/login/2fa")
+        .json(json!({
+            "challengeToken": challenge_token,
+            "recoveryCode": recovery_codes[0],
+        }))
+        .send()
+        .await;
+    assert_eq!(locked.status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(locked.message(), "TooManyLoginAttempts");
+}
+
+#[tokio::test]
+async fn magic_link_signs_in_once() {
+    let app = TestApp::new().await;
+    let user = app.register("Alan", "alan@example.com").await;
+
+    // Unknown emails get the same answer.
+    for email in ["alan@example.com", "nobody@example.com"] {
+        let requested = app
+            .post("/api/auth/magic-link")
+            .json(json!({ "email": email }))
+            .send()
+            .await;
+        assert_eq!(requested.status, StatusCode::OK, "{}", requested.body);
+    }
+
+    // The emailed secrets are never readable, so known ones replace them.
+    let (magic_token, code) = ("known-magic-token", "123456");
+    app.app_state
+        .db_client
+        .save_magic_link(
+            user.id,
+            &token::hash_opaque_token(magic_token),
+            &token::hmac_sha256(&app.app_state.env.hmac_secret, code),
+            Utc::now() + Duration::minutes(15),
+        )
+        .await
+        .unwrap();
+
+    let wrong = app
+        .post("/api/auth/magic-link/consume")
+        .json(json!({ "email": "alan@example.com", "code": "654321" }))
+        .send()
+        .await;
+    assert_eq!(wrong.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(wrong.message(), "InvalidToken");
+
+    let consumed = app
+        .post("/api/auth/magic-link/consume")
+        .json(json!({ "email": "alan@example.com", "code": code }))
+        .send()
+        .await;
+    assert_eq!(consumed.status, StatusCode::OK, "{}", consumed.body);
+    assert!(!consumed.string("token").is_empty());
+
+    // The code and the link are one sign-in between them.
+    let reused = app
+        .post("/api/auth/magic-link/consume")
+        .json(json!({ "token": magic_token }))
+        .send()
+        .await;
+    assert_eq!(reused.status, StatusCode::UNAUTHORIZED);
+
+    // Receiving the link verified the email.
+    assert_eq!(
+        app.user("alan@example.com").await.email_verified,
+        Some(true)
+    );
+}
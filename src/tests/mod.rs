@@ -0,0 +1,289 @@
+//! End-to-end tests of the API. Each test gets its own database, created
+//! next to the one in `DATABASE_URL` and migrated from scratch, and drives
+//! the router the server runs.
+
+mod auth;
+mod tokens;
+
+use std::sync::Arc;
+
+use axum::{
+    Router,
+    body::Body,
+    http::{HeaderMap, Method, Request, StatusCode, header, request},
+};
+use serde_json::{Value, json};
+use sqlx::{
+    Connection, Executor, PgConnection,
+    postgres::{PgConnectOptions, PgPoolOptions},
+};
+use tower::ServiceExt;
+use uuid::Uuid;
+
+use crate::{
+    AppState,
+    config::{
+        config::{Config, EmailVerificationPolicy, RateLimitBackend},
+        cookie_config::CookieConfig,
+        jwt_config::JwtConfig,
+        mail_config::MailConfig,
+        password_policy::PasswordPolicy,
+        rate_limit_config::RateLimitConfig,
+    },
+    database::{DBClient, auth::AuthExt},
+    models::User,
+    routes::create_router,
+    utils::{oidc::OidcClient, password::PasswordHasherPool},
+};
+
+pub const PASSWORD: &str = "Quiet-Otter-42";
+pub const OPERATOR_EMAIL: &str = "ops@example.com";
+
+pub struct TestApp {
+    pub app_state: Arc<AppState>,
+    router: Router,
+    admin_options: PgConnectOptions,
+    database: String,
+}
+
+impl TestApp {
+    pub async fn new() -> Self {
+        dotenv::dotenv().ok();
+        let database_url =
+            std::env::var("DATABASE_URL").expect("DATABASE_URL must point at a test server");
+        let admin_options: PgConnectOptions =
+            database_url.parse().expect("DATABASE_URL is not valid");
+
+        let database = format!("workspace_kit_test_{}", Uuid::new_v4().simple());
+        let mut admin = PgConnection::connect_with(&admin_options)
+            .await
+            .expect("Failed to connect to DATABASE_URL");
+        admin
+            .execute(format!(r#"CREATE DATABASE "{}""#, database).as_str())
+            .await
+            .expect("Failed to create the test database");
+
+        let pool = PgPoolOptions::new()
+            .max_connections(5)
+            .connect_with(admin_options.clone().database(&database))
+            .await
+            .expect("Failed to connect to the test database");
+        sqlx::migrate!()
+            .run(&pool)
+            .await
+            .expect("Failed to migrate the test database");
+
+        let env = Config {
+            database_url,
+            jwt_maxage: 15,
+            refresh_token_maxage: 30,
+            workspace_token_maxage: 5,
+            port: 0,
+            backend_base_url: "http://localhost:8000/api".to_string(),
+            frontend_base_url: "http://localhost:3000".to_string(),
+            trust_proxy_headers: false,
+            rate_limit_backend: RateLimitBackend::Memory,
+            rate_limits: RateLimitConfig::init(),
+            email_verification_policy: EmailVerificationPolicy::Allow,
+            email_verification_grace_hours: 0,
+            totp_issuer: "Workspace Kit".to_string(),
+            webauthn_rp_id: "localhost".to_string(),
+            webauthn_rp_name: "Workspace Kit".to_string(),
+            webauthn_origin: "http://localhost:3000".to_string(),
+            oauth_providers: Vec::new(),
+            csrf_exempt_routes: Vec::new(),
+            operator_emails: vec![OPERATOR_EMAIL.to_string()],
+            hmac_secret: "test-hmac-secret-that-is-long-enough".to_string(),
+            metrics_enabled: false,
+        };
+
+        // Nothing listens on port 1, so mail fails to send without failing
+        // the request, as with an unreachable SMTP server.
+        let mail_config = MailConfig {
+            smtp_server: "localhost".to_string(),
+            smtp_port: 1,
+            smtp_username: "test".to_string(),
+            smtp_password: "test".to_string(),
+            smtp_from_address: "noreply@example.com".to_string(),
+            mail_template_path: "src/mail/templates".to_string(),
+        };
+
+        let app_state = Arc::new(AppState {
+            jwt_config: JwtConfig::hs256("test-jwt-secret", &env.backend_base_url),
+            cookie_config: CookieConfig::init(&env),
+            env,
+            db_client: DBClient::new(pool),
+            mail_config,
+            password_policy: PasswordPolicy::init(),
+            breach_checker: None,
+            password_hasher: PasswordHasherPool::init(),
+            oidc_client: OidcClient::new(),
+        });
+
+        TestApp {
+            router: create_router(app_state.clone()),
+            app_state,
+            admin_options,
+            database,
+        }
+    }
+
+    pub fn request(&self, method: Method, uri: &str) -> TestRequest<'_> {
+        TestRequest {
+            app: self,
+            builder: Request::builder().method(method).uri(uri),
+            body: Body::empty(),
+        }
+    }
+
+    pub fn get(&self, uri: &str) -> TestRequest<'_> {
+        self.request(Method::GET, uri)
+    }
+
+    pub fn post(&self, uri: &str) -> TestRequest<'_> {
+        self.request(Method::POST, uri)
+    }
+
+    pub async fn register(&self, name: &str, email: &str) -> User {
+        let response = self
+            .post("/api/auth/register")
+            .json(json!({
+                "name": name,
+                "email": email,
+                "password": PASSWORD,
+                "passwordConfirm": PASSWORD,
+            }))
+            .send()
+            .await;
+        assert_eq!(response.status, StatusCode::CREATED, "{}", response.body);
+
+        self.user(email).await
+    }
+
+    pub async fn user(&self, email: &str) -> User {
+        self.app_state
+            .db_client
+            .get_user(None, None, Some(email))
+            .await
+            .unwrap()
+            .expect("user exists")
+    }
+
+    pub async fn login(&self, email: &str, password: &str) -> TestResponse {
+        self.post("/api/auth/login")
+            .json(json!({ "email": email, "password": password }))
+            .send()
+            .await
+    }
+
+    /// Signs in with the test password and returns the access token.
+    pub async fn access_token(&self, email: &str) -> String {
+        let response = self.login(email, PASSWORD).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        response.string("token")
+    }
+}
+
+impl Drop for TestApp {
+    fn drop(&mut self) {
+        let admin_options = self.admin_options.clone();
+        let database = self.database.clone();
+
+        // Drop runs outside any async context the test might still be in.
+        let dropped = std::thread::spawn(move || {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap()
+                .block_on(async move {
+                    let mut admin = PgConnection::connect_with(&admin_options).await?;
+                    admin
+                        .execute(
+                            format!(r#"DROP DATABASE IF EXISTS "{}" WITH (FORCE)"#, database)
+                                .as_str(),
+                        )
+                        .await
+                        .map(|_| ())
+                })
+        })
+        .join();
+
+        if let Ok(Err(e)) = dropped {
+            eprintln!("Failed to drop test database {}: {}", self.database, e);
+        }
+    }
+}
+
+pub struct TestRequest<'a> {
+    app: &'a TestApp,
+    builder: request::Builder,
+    body: Body,
+}
+
+impl TestRequest<'_> {
+    pub fn bearer(mut self, token: &str) -> Self {
+        self.builder = self
+            .builder
+            .header(header::AUTHORIZATION, format!("Bearer {}", token));
+        self
+    }
+
+    pub fn workspace(mut self, workspace_id: &str) -> Self {
+        self.builder = self
+            .builder
+            .header(header::COOKIE, format!("workspace={}", workspace_id));
+        self
+    }
+
+    pub fn json(mut self, body: Value) -> Self {
+        self.builder = self
+            .builder
+            .header(header::CONTENT_TYPE, "application/json");
+        self.body = Body::from(body.to_string());
+        self
+    }
+
+    pub async fn send(self) -> TestResponse {
+        let request = self.builder.body(self.body).unwrap();
+        let response = self.app.router.clone().oneshot(request).await.unwrap();
+
+        let status = response.status();
+        let headers = response.headers().clone();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+
+        TestResponse {
+            status,
+            headers,
+            body,
+        }
+    }
+}
+
+pub struct TestResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Value,
+}
+
+impl TestResponse {
+    /// A string field of the JSON body, found by a JSON pointer such as
+    /// `/data/id`, or a top-level key.
+    pub fn string(&self, field: &str) -> String {
+        let value = if field.starts_with('/') {
+            self.body.pointer(field)
+        } else {
+            self.body.get(field)
+        };
+        value
+            .and_then(Value::as_str)
+            .unwrap_or_else(|| panic!("no string {} in {}", field, self.body))
+            .to_string()
+    }
+
+    pub fn message(&self) -> &str {
+        self.body["message"].as_str().unwrap_or_default()
+    }
+}
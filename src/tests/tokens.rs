@@ -0,0 +1,169 @@
+use axum::http::{Method, StatusCode};
+use serde_json::json;
+
+use crate::tests::{PASSWORD, TestApp, TestResponse};
+
+/// Creates a workspace owned by the holder of `token` and returns its id and
+/// invite code.
+async fn create_workspace(app: &TestApp, token: &str, name: &str) -> (String, String) {
+    let created = app
+        .post("/api/workspace/create")
+        .bearer(token)
+        .json(json!({ "name": name }))
+        .send()
+        .await;
+    assert_eq!(created.status, StatusCode::OK, "{}", created.body);
+    assert_eq!(created.string("/data/role_name"), "Admin");
+
+    (
+        created.string("/data/workspace/id"),
+        created.string("/data/workspace/invite_code"),
+    )
+}
+
+async fn create_personal_access_token(
+    app: &TestApp,
+    token: &str,
+    workspace_id: &str,
+    permissions: &[&str],
+) -> TestResponse {
+    app.post("/api/user/tokens")
+        .bearer(token)
+        .json(json!({
+            "name": "script",
+            "workspaceId": workspace_id,
+            "permissions": permissions,
+        }))
+        .send()
+        .await
+}
+
+async fn create_service_account(
+    app: &TestApp,
+    token: &str,
+    workspace_id: &str,
+    role_name: &str,
+) -> TestResponse {
+    app.post("/api/service_account")
+        .bearer(token)
+        .workspace(workspace_id)
+        .json(json!({ "name": "deploy-bot", "role_name": role_name }))
+        .send()
+        .await
+}
+
+#[tokio::test]
+async fn personal_access_token_is_scoped_and_dies_with_the_password() {
+    let app = TestApp::new().await;
+    app.register("Ada", "ada@example.com").await;
+    let token = app.access_token("ada@example.com").await;
+    let (workspace_id, _) = create_workspace(&app, &token, "Analytical Engines").await;
+
+    let unknown = create_personal_access_token(&app, &token, &workspace_id, &["fly"]).await;
+    assert_eq!(unknown.status, StatusCode::BAD_REQUEST);
+
+    let created = create_personal_access_token(&app, &token, &workspace_id, &["view_roles"]).await;
+    assert_eq!(created.status, StatusCode::CREATED, "{}", created.body);
+    let pat = created.string("token");
+
+    let roles = app.get("/api/role").bearer(&pat).send().await;
+    assert_eq!(roles.status, StatusCode::OK, "{}", roles.body);
+
+    // Only the permissions the token was given count.
+    let new_role = app
+        .post("/api/role")
+        .bearer(&pat)
+        .json(json!({ "name": "Auditors", "permissions": ["view_roles"] }))
+        .send()
+        .await;
+    assert_eq!(new_role.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(new_role.message(), "PermissionDenied");
+
+    let changed = app
+        .request(Method::PUT, "/api/user/update-password")
+        .bearer(&token)
+        .json(json!({
+            "currentPassword": PASSWORD,
+            "newPassword": "Brisk-Heron-77",
+            "confirmPassword": "Brisk-Heron-77",
+        }))
+        .send()
+        .await;
+    assert_eq!(changed.status, StatusCode::OK, "{}", changed.body);
+
+    let revoked = app.get("/api/role").bearer(&pat).send().await;
+    assert_eq!(revoked.status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn service_accounts_are_only_created_by_people_with_the_role_permissions() {
+    let app = TestApp::new().await;
+    app.register("Ada", "ada@example.com").await;
+    let bob = app.register("Bob", "bob@example.com").await;
+    let ada_token = app.access_token("ada@example.com").await;
+    let (workspace_id, invite_code) = create_workspace(&app, &ada_token, "Engines").await;
+
+    let manager = create_service_account(&app, &ada_token, &workspace_id, "Manager").await;
+    assert_eq!(manager.status, StatusCode::CREATED, "{}", manager.body);
+    let manager_key = manager.string("key");
+
+    let roles = app.get("/api/role").bearer(&manager_key).send().await;
+    assert_eq!(roles.status, StatusCode::OK, "{}", roles.body);
+
+    // API keys cannot mint more API keys, even with the permission.
+    let admin = create_service_account(&app, &ada_token, &workspace_id, "Admin").await;
+    assert_eq!(admin.status, StatusCode::CREATED, "{}", admin.body);
+    let from_service_account =
+        create_service_account(&app, &admin.string("key"), &workspace_id, "Manager").await;
+    assert_eq!(from_service_account.status, StatusCode::FORBIDDEN);
+
+    let pat = create_personal_access_token(
+        &app,
+        &ada_token,
+        &workspace_id,
+        &["manage_service_accounts"],
+    )
+    .await;
+    assert_eq!(pat.status, StatusCode::CREATED, "{}", pat.body);
+    let from_pat =
+        create_service_account(&app, &pat.string("token"), &workspace_id, "Manager").await;
+    assert_eq!(from_pat.status, StatusCode::FORBIDDEN);
+
+    // A member who may manage service accounts still cannot hand out a role
+    // with more than they hold.
+    let operators = app
+        .post("/api/role")
+        .bearer(&ada_token)
+        .workspace(&workspace_id)
+        .json(json!({
+            "name": "Operators",
+            "permissions": ["manage_service_accounts", "view_roles"],
+        }))
+        .send()
+        .await;
+    assert_eq!(operators.status, StatusCode::OK, "{}", operators.body);
+
+    let bob_token = app.access_token("bob@example.com").await;
+    let joined = app
+        .get(&format!("/api/workspace_user/invite/{}", invite_code))
+        .bearer(&bob_token)
+        .send()
+        .await;
+    assert_eq!(joined.status, StatusCode::OK, "{}", joined.body);
+
+    let promoted = app
+        .request(Method::PATCH, &format!("/api/workspace_user/{}", bob.id))
+        .bearer(&ada_token)
+        .workspace(&workspace_id)
+        .json(json!({ "role_name": "Operators" }))
+        .send()
+        .await;
+    assert_eq!(promoted.status, StatusCode::OK, "{}", promoted.body);
+
+    let escalated = create_service_account(&app, &bob_token, &workspace_id, "Admin").await;
+    assert_eq!(escalated.status, StatusCode::FORBIDDEN);
+    assert_eq!(escalated.message(), "PermissionDenied");
+
+    let same_role = create_service_account(&app, &bob_token, &workspace_id, "Operators").await;
+    assert_eq!(same_role.status, StatusCode::CREATED, "{}", same_role.body);
+}
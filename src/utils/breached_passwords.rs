@@ -0,0 +1,281 @@
+use std::{
+    env, fmt,
+    fs::File,
+    io::{self, BufRead, BufReader, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use sha1::{Digest, Sha1};
+
+use crate::error::HttpError;
+
+const DEFAULT_RANGE_API_URL: &str = "https://api.pwnedpasswords.com/range";
+const HTTP_TIMEOUT: Duration = Duration::from_secs(5);
+const PREFIX_LENGTH: usize = 5;
+
+/// Looks passwords up in a corpus of passwords exposed in data breaches.
+#[async_trait]
+pub trait BreachChecker: fmt::Debug + Send + Sync {
+    async fn is_breached(&self, password: &str) -> Result<bool, HttpError>;
+}
+
+/// Uppercase hex SHA-1, the format breach corpora are published in.
+fn sha1_hex(password: &str) -> String {
+    format!("{:X}", Sha1::digest(password.as_bytes()))
+}
+
+/// Whether a `SUFFIX:COUNT` range listing contains `suffix`. Padding entries
+/// have a count of 0 and never match.
+fn range_contains(listing: &str, suffix: &str) -> bool {
+    listing.lines().any(|line| {
+        let (line_suffix, count) = line.trim().split_once(':').unwrap_or((line.trim(), "1"));
+        line_suffix.eq_ignore_ascii_case(suffix) && count.trim() != "0"
+    })
+}
+
+/// The k-anonymity range API: only the first five hex characters of the
+/// password's SHA-1 leave the server.
+#[derive(Debug)]
+pub struct RangeApiChecker {
+    http: reqwest::Client,
+    base_url: String,
+}
+
+impl RangeApiChecker {
+    pub fn new(base_url: String) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(HTTP_TIMEOUT)
+            .build()
+            .expect("Failed to build HTTP client");
+
+        RangeApiChecker { http, base_url }
+    }
+}
+
+#[async_trait]
+impl BreachChecker for RangeApiChecker {
+    async fn is_breached(&self, password: &str) -> Result<bool, HttpError> {
+        let hash = sha1_hex(password);
+        let (prefix, suffix) = hash.split_at(PREFIX_LENGTH);
+
+        let listing = self
+            .http
+            .get(format!(
+                "{}/{}",
+                self.base_url.trim_end_matches('/'),
+                prefix
+            ))
+            .header("Add-Padding", "true")
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| HttpError::server_error(e.to_string()))?
+            .text()
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+        Ok(range_contains(&listing, suffix))
+    }
+}
+
+/// A local copy of the corpus, for deployments without internet access.
+#[derive(Debug)]
+pub enum OfflineChecker {
+    /// A directory of range files named by prefix (`21BD1.txt`), each
+    /// holding `SUFFIX:COUNT` lines like the range API returns.
+    PrefixDirectory(PathBuf),
+    /// Full SHA-1 hashes, one per line and optionally followed by `:COUNT`,
+    /// sorted by hash like the "ordered by hash" download. Lookups binary
+    /// search the file, so it is never loaded into memory.
+    SortedHashes(PathBuf),
+}
+
+/// How many leading lines `open` checks are in order, to catch a file that
+/// was not sorted by hash.
+const SORT_CHECK_LINES: usize = 1000;
+
+fn line_hash(line: &str) -> String {
+    line.split(':').next().unwrap_or("").trim().to_uppercase()
+}
+
+/// The first line starting at or after byte `pos`, with its start offset.
+fn line_at_or_after(reader: &mut BufReader<File>, pos: u64) -> io::Result<Option<(u64, String)>> {
+    let mut start = pos;
+    if pos > 0 {
+        reader.seek(SeekFrom::Start(pos - 1))?;
+        let mut skipped = Vec::new();
+        start = pos - 1 + reader.read_until(b'\n', &mut skipped)? as u64;
+    } else {
+        reader.seek(SeekFrom::Start(0))?;
+    }
+
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some((start, line)))
+}
+
+/// Binary searches a file of lines sorted by hash for `hash`.
+fn sorted_file_contains(path: &Path, hash: &str) -> io::Result<bool> {
+    let file = File::open(path)?;
+    let (mut low, mut high) = (0, file.metadata()?.len());
+    let mut reader = BufReader::new(file);
+
+    // The line holding `hash`, if any, starts in `low..high`.
+    while low < high {
+        let mid = low + (high - low) / 2;
+        match line_at_or_after(&mut reader, mid)? {
+            Some((start, line)) => match line_hash(&line).as_str().cmp(hash) {
+                std::cmp::Ordering::Equal => return Ok(true),
+                std::cmp::Ordering::Less => low = start + line.len() as u64,
+                std::cmp::Ordering::Greater => high = mid,
+            },
+            None => high = mid,
+        }
+    }
+    Ok(false)
+}
+
+impl OfflineChecker {
+    pub fn open(path: &str) -> Self {
+        let path = PathBuf::from(path);
+        if path.is_dir() {
+            return OfflineChecker::PrefixDirectory(path);
+        }
+
+        let file = File::open(&path)
+            .unwrap_or_else(|e| panic!("Failed to read {}: {}", path.display(), e));
+        let hashes: Vec<String> = BufReader::new(file)
+            .lines()
+            .take(SORT_CHECK_LINES)
+            .map(|line| {
+                line.map(|line| line_hash(&line))
+                    .unwrap_or_else(|e| panic!("Failed to read {}: {}", path.display(), e))
+            })
+            .collect();
+        if hashes.windows(2).any(|pair| pair[0] > pair[1]) {
+            panic!("{} must be sorted by hash", path.display());
+        }
+
+        OfflineChecker::SortedHashes(path)
+    }
+}
+
+#[async_trait]
+impl BreachChecker for OfflineChecker {
+    async fn is_breached(&self, password: &str) -> Result<bool, HttpError> {
+        let hash = sha1_hex(password);
+        match self {
+            OfflineChecker::PrefixDirectory(dir) => {
+                let (prefix, suffix) = hash.split_at(PREFIX_LENGTH);
+                match tokio::fs::read_to_string(dir.join(format!("{}.txt", prefix))).await {
+                    Ok(listing) => Ok(range_contains(&listing, suffix)),
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+                    Err(e) => Err(HttpError::server_error(e.to_string())),
+                }
+            }
+            OfflineChecker::SortedHashes(path) => {
+                let path = path.clone();
+                tokio::task::spawn_blocking(move || sorted_file_contains(&path, &hash))
+                    .await
+                    .map_err(|e| HttpError::server_error(e.to_string()))?
+                    .map_err(|e| HttpError::server_error(e.to_string()))
+            }
+        }
+    }
+}
+
+/// Builds the checker selected by `PASSWORD_BREACH_CHECK` (`off`, `api` or
+/// `offline`).
+pub fn from_env() -> Option<Arc<dyn BreachChecker>> {
+    match env::var("PASSWORD_BREACH_CHECK").as_deref() {
+        Err(_) | Ok("off") => None,
+        Ok("api") => {
+            let base_url = env::var("PASSWORD_BREACH_API_URL")
+                .unwrap_or_else(|_| DEFAULT_RANGE_API_URL.to_string());
+            Some(Arc::new(RangeApiChecker::new(base_url)))
+        }
+        Ok("offline") => {
+            let path = env::var("PASSWORD_BREACH_FILE")
+                .expect("PASSWORD_BREACH_FILE must be set when PASSWORD_BREACH_CHECK is offline");
+            Some(Arc::new(OfflineChecker::open(&path)))
+        }
+        Ok(other) => panic!(
+            "PASSWORD_BREACH_CHECK must be off, api or offline, got {}",
+            other
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    fn sorted_corpus(passwords: &[&str]) -> PathBuf {
+        let mut hashes: Vec<String> = passwords.iter().map(|p| sha1_hex(p)).collect();
+        hashes.sort();
+
+        let path = env::temp_dir().join(format!("breach-corpus-{}.txt", uuid::Uuid::new_v4()));
+        let mut file = File::create(&path).unwrap();
+        for (count, hash) in hashes.iter().enumerate() {
+            writeln!(file, "{}:{}", hash, count + 1).unwrap();
+        }
+        path
+    }
+
+    #[tokio::test]
+    async fn sorted_hashes_finds_every_listed_password() {
+        let passwords: Vec<String> = (0..500).map(|i| format!("password{}", i)).collect();
+        let passwords: Vec<&str> = passwords.iter().map(String::as_str).collect();
+        let path = sorted_corpus(&passwords);
+        let checker = OfflineChecker::open(path.to_str().unwrap());
+
+        for password in passwords {
+            assert!(checker.is_breached(password).await.unwrap(), "{}", password);
+        }
+        for i in 500..600 {
+            let password = format!("password{}", i);
+            assert!(
+                !checker.is_breached(&password).await.unwrap(),
+                "{}",
+                password
+            );
+        }
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn sorted_hashes_handles_single_line_and_empty_files() {
+        let path = sorted_corpus(&["password"]);
+        let checker = OfflineChecker::open(path.to_str().unwrap());
+        assert!(checker.is_breached("password").await.unwrap());
+        assert!(!checker.is_breached("Password").await.unwrap());
+        std::fs::remove_file(&path).unwrap();
+
+        let path = sorted_corpus(&[]);
+        let checker = OfflineChecker::open(path.to_str().unwrap());
+        assert!(!checker.is_breached("password").await.unwrap());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "must be sorted by hash")]
+    fn open_rejects_unsorted_file() {
+        let path = env::temp_dir().join(format!("breach-corpus-{}.txt", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "FFFF:1\n0000:1\n").unwrap();
+        OfflineChecker::open(path.to_str().unwrap());
+    }
+
+    #[test]
+    fn range_listing_ignores_padding() {
+        assert!(range_contains("ABC:3\r\nDEF:0", "abc"));
+        assert!(!range_contains("ABC:3\r\nDEF:0", "DEF"));
+    }
+}
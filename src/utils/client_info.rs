@@ -0,0 +1,60 @@
+use std::{convert::Infallible, net::SocketAddr, sync::Arc};
+
+use axum::{
+    extract::{ConnectInfo, FromRequestParts},
+    http::{header, request::Parts},
+};
+
+use crate::AppState;
+
+/// Best-effort description of the client making the request, used to label
+/// sessions and to key throttling.
+#[derive(Debug, Clone, Default)]
+pub struct ClientInfo {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for ClientInfo {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let trust_proxy_headers = parts
+            .extensions
+            .get::<Arc<AppState>>()
+            .map(|app_state| app_state.env.trust_proxy_headers)
+            .unwrap_or(false);
+
+        // X-Forwarded-For can be set by anyone, so it is only honoured when the
+        // app is deployed behind a proxy that overwrites it.
+        let forwarded_ip = trust_proxy_headers
+            .then(|| {
+                parts
+                    .headers
+                    .get("x-forwarded-for")
+                    .and_then(|h| h.to_str().ok())
+                    .and_then(|h| h.split(',').next())
+                    .map(|ip| ip.trim().to_string())
+                    .filter(|ip| !ip.is_empty())
+            })
+            .flatten();
+
+        let ip_address = forwarded_ip.or_else(|| {
+            parts
+                .extensions
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|ConnectInfo(addr)| addr.ip().to_string())
+        });
+
+        let user_agent = parts
+            .headers
+            .get(header::USER_AGENT)
+            .and_then(|h| h.to_str().ok())
+            .map(|ua| ua.chars().take(512).collect());
+
+        Ok(ClientInfo {
+            ip_address,
+            user_agent,
+        })
+    }
+}
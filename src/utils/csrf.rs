@@ -0,0 +1,81 @@
+use axum::http::{HeaderMap, Method};
+use uuid::Uuid;
+
+use crate::{
+    AppState,
+    error::{ErrorMessage, HttpError},
+    utils::token,
+};
+
+pub const CSRF_HEADER: &str = "x-csrf-token";
+
+/// The CSRF token of a session: an HMAC of its id under `HMAC_SECRET`. A
+/// cookie planted by a sibling subdomain cannot produce it, and nothing needs
+/// to be stored.
+pub fn session_token(app_state: &AppState, session_id: Uuid) -> String {
+    token::hmac_sha256(&app_state.env.hmac_secret, &format!("csrf:{}", session_id))
+}
+
+/// Whether `path` matches one of `CSRF_EXEMPT_ROUTES`. Entries ending in `*`
+/// match by prefix.
+fn is_exempt(exempt_routes: &[String], path: &str) -> bool {
+    exempt_routes
+        .iter()
+        .any(|route| match route.strip_suffix('*') {
+            Some(prefix) => path.starts_with(prefix),
+            None => path == route,
+        })
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Rejects unsafe requests whose `X-CSRF-Token` header is not the token of
+/// the session they are authenticated as. Only requests authenticated by
+/// cookie need it; browsers never attach an `Authorization` header on their
+/// own. `path` is the full request path, as matched by `CSRF_EXEMPT_ROUTES`.
+pub fn verify_request(
+    app_state: &AppState,
+    session_id: Uuid,
+    method: &Method,
+    path: &str,
+    headers: &HeaderMap,
+) -> Result<(), HttpError> {
+    if matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE
+    ) {
+        return Ok(());
+    }
+
+    if is_exempt(&app_state.env.csrf_exempt_routes, path) {
+        return Ok(());
+    }
+
+    let expected = session_token(app_state, session_id);
+    let presented = headers.get(CSRF_HEADER).and_then(|h| h.to_str().ok());
+
+    match presented {
+        Some(presented) if constant_time_eq(expected.as_bytes(), presented.as_bytes()) => Ok(()),
+        _ => Err(HttpError::forbidden(
+            ErrorMessage::CsrfTokenMismatch.to_string(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exempt_routes_match_exactly_or_by_prefix() {
+        let routes = vec!["/api/hooks/*".to_string(), "/api/status".to_string()];
+
+        assert!(is_exempt(&routes, "/api/hooks/github"));
+        assert!(is_exempt(&routes, "/api/status"));
+        assert!(!is_exempt(&routes, "/api/status/details"));
+        assert!(!is_exempt(&routes, "/api/auth/refresh"));
+        assert!(!is_exempt(&[], "/api/hooks/github"));
+    }
+}
@@ -0,0 +1,74 @@
+use chrono::{DateTime, Duration, Utc};
+
+use crate::models::LoginAttempt;
+
+pub const EMAIL: &str = "email";
+pub const IP: &str = "ip";
+/// Wrong second factors, keyed by user id.
+pub const TWO_FACTOR: &str = "two_factor";
+
+/// Failures older than this are forgotten.
+pub const FAILURE_WINDOW_MINUTES: i64 = 15;
+pub const LOCKOUT_MINUTES: i64 = 15;
+const MAX_DELAY_SECONDS: i64 = 60;
+
+/// How many failures a key gets before sign-ins are slowed down and then
+/// locked. An IP is shared by many users, so it is allowed more.
+pub struct ThrottlePolicy {
+    pub free_attempts: i32,
+    pub lockout_threshold: i32,
+}
+
+pub const EMAIL_POLICY: ThrottlePolicy = ThrottlePolicy {
+    free_attempts: 3,
+    lockout_threshold: 10,
+};
+
+pub const IP_POLICY: ThrottlePolicy = ThrottlePolicy {
+    free_attempts: 10,
+    lockout_threshold: 50,
+};
+
+/// A correct password has already been shown, so only a few codes are
+/// allowed before the second factor is locked.
+pub const TWO_FACTOR_POLICY: ThrottlePolicy = ThrottlePolicy {
+    free_attempts: 3,
+    lockout_threshold: 5,
+};
+
+/// Emails are matched case-insensitively, like at sign-in.
+pub fn email_key(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// Seconds until the key may try again, or `None` if it may try now. Each
+/// failure past the free attempts doubles the wait, up to a minute.
+pub fn retry_after(
+    attempt: &LoginAttempt,
+    policy: &ThrottlePolicy,
+    now: DateTime<Utc>,
+) -> Option<u64> {
+    if let Some(locked_until) = attempt.locked_until.filter(|until| *until > now) {
+        return Some(seconds_until(locked_until, now));
+    }
+
+    if now - attempt.last_failed_at > Duration::minutes(FAILURE_WINDOW_MINUTES) {
+        return None;
+    }
+
+    let excess = attempt.failed_count - policy.free_attempts;
+    if excess <= 0 {
+        return None;
+    }
+
+    let delay = 2i64
+        .saturating_pow(excess as u32 - 1)
+        .min(MAX_DELAY_SECONDS);
+    let next_attempt_at = attempt.last_failed_at + Duration::seconds(delay);
+    (next_attempt_at > now).then(|| seconds_until(next_attempt_at, now))
+}
+
+fn seconds_until(at: DateTime<Utc>, now: DateTime<Utc>) -> u64 {
+    // Round up so clients never retry a moment too early.
+    ((at - now).num_milliseconds().max(0) as u64).div_ceil(1000)
+}
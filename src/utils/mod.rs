@@ -1,2 +1,11 @@
+pub mod breached_passwords;
+pub mod client_info;
+pub mod csrf;
+pub mod login_throttle;
+pub mod oidc;
 pub mod password;
+pub mod password_strength;
+pub mod rate_limit;
 pub mod token;
+pub mod totp;
+pub mod webauthn;
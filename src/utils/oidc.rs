@@ -0,0 +1,511 @@
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use jsonwebtoken::{
+    Algorithm, DecodingKey, Validation, decode, decode_header,
+    jwk::{Jwk, JwkSet},
+};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tokio::sync::RwLock;
+
+use crate::{
+    config::config::OidcProviderConfig,
+    error::{ErrorMessage, HttpError},
+};
+
+const METADATA_CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The parts of the provider's discovery document the login flow needs.
+#[derive(Debug, Clone, Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Debug, Clone)]
+struct CachedProvider {
+    metadata: ProviderMetadata,
+    jwks: JwkSet,
+    fetched_at: Instant,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+/// Verified claims from a provider's ID token.
+#[derive(Debug, Clone, Deserialize)]
+pub struct IdTokenClaims {
+    pub sub: String,
+    pub email: Option<String>,
+    #[serde(default)]
+    email_verified: Option<serde_json::Value>,
+    pub name: Option<String>,
+    nonce: Option<String>,
+}
+
+impl IdTokenClaims {
+    /// Some providers send `email_verified` as a string.
+    pub fn email_verified(&self) -> bool {
+        matches!(&self.email_verified, Some(serde_json::Value::Bool(true)))
+            || matches!(&self.email_verified, Some(serde_json::Value::String(v)) if v == "true")
+    }
+}
+
+fn oauth_failed() -> HttpError {
+    HttpError::bad_request(ErrorMessage::OAuthLoginFailed.to_string())
+}
+
+/// S256 PKCE challenge for a code verifier.
+pub fn code_challenge(code_verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+/// Talks to OpenID Connect providers. Discovery documents and signing keys
+/// are cached per provider.
+#[derive(Debug, Clone)]
+pub struct OidcClient {
+    http: reqwest::Client,
+    cache: Arc<RwLock<HashMap<String, CachedProvider>>>,
+}
+
+impl OidcClient {
+    pub fn new() -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(HTTP_TIMEOUT)
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .expect("Failed to build HTTP client");
+
+        OidcClient {
+            http,
+            cache: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    async fn fetch_json<T: serde::de::DeserializeOwned>(&self, url: &str) -> Result<T, HttpError> {
+        self.http
+            .get(url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| HttpError::server_error(e.to_string()))?
+            .json()
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))
+    }
+
+    async fn load_provider(
+        &self,
+        provider: &OidcProviderConfig,
+        refresh: bool,
+    ) -> Result<CachedProvider, HttpError> {
+        if !refresh {
+            let cache = self.cache.read().await;
+            let fresh = cache
+                .get(&provider.name)
+                .filter(|cached| cached.fetched_at.elapsed() < METADATA_CACHE_TTL);
+            if let Some(cached) = fresh {
+                return Ok(cached.clone());
+            }
+        }
+
+        let discovery_url = format!(
+            "{}/.well-known/openid-configuration",
+            provider.issuer.trim_end_matches('/')
+        );
+        let metadata: ProviderMetadata = self.fetch_json(&discovery_url).await?;
+        if metadata.issuer != provider.issuer {
+            return Err(HttpError::server_error(format!(
+                "OIDC issuer mismatch for provider {}",
+                provider.name
+            )));
+        }
+        let jwks: JwkSet = self.fetch_json(&metadata.jwks_uri).await?;
+
+        let cached = CachedProvider {
+            metadata,
+            jwks,
+            fetched_at: Instant::now(),
+        };
+        self.cache
+            .write()
+            .await
+            .insert(provider.name.clone(), cached.clone());
+
+        Ok(cached)
+    }
+
+    /// URL of the provider's consent screen for an authorization-code + PKCE flow.
+    pub async fn authorization_url(
+        &self,
+        provider: &OidcProviderConfig,
+        redirect_uri: &str,
+        state: &str,
+        nonce: &str,
+        code_verifier: &str,
+    ) -> Result<String, HttpError> {
+        let cached = self.load_provider(provider, false).await?;
+        let mut url = url::Url::parse(&cached.metadata.authorization_endpoint)
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &provider.client_id)
+            .append_pair("redirect_uri", redirect_uri)
+            .append_pair("scope", &provider.scopes)
+            .append_pair("state", state)
+            .append_pair("nonce", nonce)
+            .append_pair("code_challenge", &code_challenge(code_verifier))
+            .append_pair("code_challenge_method", "S256");
+
+        Ok(url.into())
+    }
+
+    /// Redeems an authorization code and returns the verified ID token claims.
+    pub async fn exchange_code(
+        &self,
+        provider: &OidcProviderConfig,
+        redirect_uri: &str,
+        code: &str,
+        code_verifier: &str,
+        nonce: &str,
+    ) -> Result<IdTokenClaims, HttpError> {
+        let cached = self.load_provider(provider, false).await?;
+
+        let params = [
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", redirect_uri),
+            ("code_verifier", code_verifier),
+        ];
+        let body = url::form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(params)
+            .finish();
+
+        let token_response: TokenResponse = self
+            .http
+            .post(&cached.metadata.token_endpoint)
+            .basic_auth(&provider.client_id, Some(&provider.client_secret))
+            .header(
+                reqwest::header::CONTENT_TYPE,
+                "application/x-www-form-urlencoded",
+            )
+            .body(body)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|_| oauth_failed())?
+            .json()
+            .await
+            .map_err(|_| oauth_failed())?;
+
+        let header = decode_header(&token_response.id_token).map_err(|_| oauth_failed())?;
+        if matches!(
+            header.alg,
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+        ) {
+            return Err(oauth_failed());
+        }
+
+        // An unknown key id usually means the provider rotated its keys.
+        let find_key = |jwks: &JwkSet| -> Option<Jwk> {
+            match &header.kid {
+                Some(kid) => jwks.find(kid).cloned(),
+                None => jwks.keys.first().cloned(),
+            }
+        };
+        let jwk = match find_key(&cached.jwks) {
+            Some(jwk) => jwk,
+            None => find_key(&self.load_provider(provider, true).await?.jwks)
+                .ok_or_else(oauth_failed)?,
+        };
+        let key = DecodingKey::from_jwk(&jwk).map_err(|_| oauth_failed())?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&cached.metadata.issuer]);
+        validation.set_audience(&[&provider.client_id]);
+
+        let claims = decode::<IdTokenClaims>(&token_response.id_token, &key, &validation)
+            .map_err(|_| oauth_failed())?
+            .claims;
+
+        if claims.nonce.as_deref() != Some(nonce) {
+            return Err(oauth_failed());
+        }
+
+        Ok(claims)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use axum::{
+        Extension, Form, Json, Router,
+        http::{HeaderMap, StatusCode, header},
+        response::IntoResponse,
+        routing::{get, post},
+    };
+    use ed25519_dalek::{
+        SigningKey,
+        pkcs8::{EncodePrivateKey, spki::der::pem::LineEnding},
+    };
+    use jsonwebtoken::{EncodingKey, Header, encode};
+    use serde_json::{Value, json};
+
+    use super::*;
+
+    const CLIENT_ID: &str = "client";
+    const CLIENT_SECRET: &str = "secret";
+    const REDIRECT_URI: &str = "http://localhost/api/auth/oauth/mock/callback";
+    const KEY_ID: &str = "mock-key";
+
+    /// What an authorization code was issued for.
+    struct IssuedCode {
+        code_challenge: String,
+        nonce: String,
+        email_verified: bool,
+    }
+
+    struct MockIssuerState {
+        issuer: String,
+        encoding_key: EncodingKey,
+        jwk: Value,
+        codes: Mutex<HashMap<String, IssuedCode>>,
+    }
+
+    /// A local OpenID provider. It serves discovery and JWKS, and redeems
+    /// codes from `issue_code` for Ed25519-signed ID tokens after checking
+    /// the client credentials, redirect URI and PKCE verifier.
+    struct MockIssuer {
+        state: Arc<MockIssuerState>,
+    }
+
+    impl MockIssuer {
+        async fn start() -> Self {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let issuer = format!("http://{}", listener.local_addr().unwrap());
+
+            let signing_key = SigningKey::from_bytes(&rand::random());
+            let pem = signing_key.to_pkcs8_pem(LineEnding::LF).unwrap();
+            let jwk = json!({
+                "kty": "OKP",
+                "crv": "Ed25519",
+                "alg": "EdDSA",
+                "use": "sig",
+                "kid": KEY_ID,
+                "x": URL_SAFE_NO_PAD.encode(signing_key.verifying_key().to_bytes()),
+            });
+
+            let state = Arc::new(MockIssuerState {
+                issuer,
+                encoding_key: EncodingKey::from_ed_pem(pem.as_bytes()).unwrap(),
+                jwk,
+                codes: Mutex::new(HashMap::new()),
+            });
+
+            let app = Router::new()
+                .route("/.well-known/openid-configuration", get(discovery))
+                .route("/jwks", get(jwks))
+                .route("/token", post(token))
+                .layer(Extension(state.clone()));
+            tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+            MockIssuer { state }
+        }
+
+        fn provider(&self) -> OidcProviderConfig {
+            OidcProviderConfig {
+                name: "mock".to_string(),
+                issuer: self.state.issuer.clone(),
+                client_id: CLIENT_ID.to_string(),
+                client_secret: CLIENT_SECRET.to_string(),
+                scopes: "openid email profile".to_string(),
+            }
+        }
+
+        /// Simulates the user approving the consent screen.
+        fn issue_code(&self, code_verifier: &str, nonce: &str, email_verified: bool) -> String {
+            let code = uuid::Uuid::new_v4().to_string();
+            self.state.codes.lock().unwrap().insert(
+                code.clone(),
+                IssuedCode {
+                    code_challenge: code_challenge(code_verifier),
+                    nonce: nonce.to_string(),
+                    email_verified,
+                },
+            );
+            code
+        }
+    }
+
+    async fn discovery(Extension(state): Extension<Arc<MockIssuerState>>) -> Json<Value> {
+        Json(json!({
+            "issuer": state.issuer,
+            "authorization_endpoint": format!("{}/authorize", state.issuer),
+            "token_endpoint": format!("{}/token", state.issuer),
+            "jwks_uri": format!("{}/jwks", state.issuer),
+        }))
+    }
+
+    async fn jwks(Extension(state): Extension<Arc<MockIssuerState>>) -> Json<Value> {
+        Json(json!({ "keys": [state.jwk] }))
+    }
+
+    async fn token(
+        Extension(state): Extension<Arc<MockIssuerState>>,
+        headers: HeaderMap,
+        Form(form): Form<HashMap<String, String>>,
+    ) -> impl IntoResponse {
+        let expected_auth = format!(
+            "Basic {}",
+            base64::engine::general_purpose::STANDARD
+                .encode(format!("{}:{}", CLIENT_ID, CLIENT_SECRET))
+        );
+        let issued = form
+            .get("code")
+            .and_then(|code| state.codes.lock().unwrap().remove(code));
+
+        let valid = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|h| h.to_str().ok())
+            == Some(expected_auth.as_str())
+            && form.get("redirect_uri").map(String::as_str) == Some(REDIRECT_URI);
+        let issued = match issued {
+            Some(issued)
+                if valid
+                    && form.get("code_verifier").map(|v| code_challenge(v))
+                        == Some(issued.code_challenge.clone()) =>
+            {
+                issued
+            }
+            _ => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({ "error": "invalid_grant" })),
+                );
+            }
+        };
+
+        let now = chrono::Utc::now().timestamp();
+        let claims = json!({
+            "iss": state.issuer,
+            "aud": CLIENT_ID,
+            "sub": "mock-user",
+            "email": "mock-user@example.com",
+            "email_verified": issued.email_verified,
+            "name": "Mock User",
+            "nonce": issued.nonce,
+            "iat": now,
+            "exp": now + 300,
+        });
+        let mut header = Header::new(Algorithm::EdDSA);
+        header.kid = Some(KEY_ID.to_string());
+        let id_token = encode(&header, &claims, &state.encoding_key).unwrap();
+
+        (
+            StatusCode::OK,
+            Json(json!({ "access_token": "unused", "token_type": "Bearer", "id_token": id_token })),
+        )
+    }
+
+    #[tokio::test]
+    async fn authorization_url_carries_pkce_challenge() {
+        let issuer = MockIssuer::start().await;
+        let url = OidcClient::new()
+            .authorization_url(
+                &issuer.provider(),
+                REDIRECT_URI,
+                "state",
+                "nonce",
+                "verifier",
+            )
+            .await
+            .unwrap();
+
+        let url = url::Url::parse(&url).unwrap();
+        let query: HashMap<_, _> = url.query_pairs().into_owned().collect();
+        assert_eq!(url.path(), "/authorize");
+        assert_eq!(query["client_id"], CLIENT_ID);
+        assert_eq!(query["state"], "state");
+        assert_eq!(query["nonce"], "nonce");
+        assert_eq!(query["code_challenge"], code_challenge("verifier"));
+        assert_eq!(query["code_challenge_method"], "S256");
+    }
+
+    #[tokio::test]
+    async fn exchange_code_returns_verified_claims() {
+        let issuer = MockIssuer::start().await;
+        let code = issuer.issue_code("verifier", "nonce", true);
+
+        let claims = OidcClient::new()
+            .exchange_code(&issuer.provider(), REDIRECT_URI, &code, "verifier", "nonce")
+            .await
+            .unwrap();
+
+        assert_eq!(claims.sub, "mock-user");
+        assert_eq!(claims.email.as_deref(), Some("mock-user@example.com"));
+        assert!(claims.email_verified());
+    }
+
+    #[tokio::test]
+    async fn exchange_code_reports_unverified_email() {
+        let issuer = MockIssuer::start().await;
+        let code = issuer.issue_code("verifier", "nonce", false);
+
+        let claims = OidcClient::new()
+            .exchange_code(&issuer.provider(), REDIRECT_URI, &code, "verifier", "nonce")
+            .await
+            .unwrap();
+
+        assert!(!claims.email_verified());
+    }
+
+    #[tokio::test]
+    async fn exchange_code_rejects_another_logins_nonce() {
+        let issuer = MockIssuer::start().await;
+        let code = issuer.issue_code("verifier", "nonce", true);
+
+        let result = OidcClient::new()
+            .exchange_code(&issuer.provider(), REDIRECT_URI, &code, "verifier", "other")
+            .await;
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn exchange_code_rejects_wrong_code_verifier() {
+        let issuer = MockIssuer::start().await;
+        let code = issuer.issue_code("verifier", "nonce", true);
+
+        let result = OidcClient::new()
+            .exchange_code(&issuer.provider(), REDIRECT_URI, &code, "guess", "nonce")
+            .await;
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn discovery_rejects_issuer_mismatch() {
+        let issuer = MockIssuer::start().await;
+        let mut provider = issuer.provider();
+        provider.issuer = format!("{}/", provider.issuer);
+
+        let result = OidcClient::new()
+            .authorization_url(&provider, REDIRECT_URI, "state", "nonce", "verifier")
+            .await;
+
+        assert!(result.is_err());
+    }
+}
@@ -1,35 +1,73 @@
-use std::sync::LazyLock;
+use std::{
+    sync::{
+        Arc, LazyLock,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
 
 use argon2::{
     self, Argon2, Params, PasswordHash, PasswordVerifier,
-    password_hash::{Error as PasswordHashError, PasswordHasher, SaltString, rand_core::OsRng},
+    password_hash::{
+        Error as PasswordHashError, PasswordHasher, SaltString,
+        rand_core::{OsRng, RngCore},
+    },
+};
+
+use tokio::sync::Semaphore;
+
+use crate::{
+    config::config::env_parse,
+    error::{ErrorMessage, HttpError},
 };
 
-use crate::error::ErrorMessage;
+/// Upper bound on what gets hashed at all. Password rules are enforced
+/// separately by `PasswordPolicy`.
+const MAX_PASSWORD_BYTES: usize = 1024;
 
-const MAX_PASSWORD_LENGTH: usize = 64;
-const MIN_PASSWORD_LENGTH: usize = 8;
+/// Seconds a client is asked to wait when the hashing queue is full.
+const BUSY_RETRY_AFTER_SECONDS: u64 = 1;
 
+/// Argon2id cost for new hashes, from `ARGON2_MEMORY_KIB`,
+/// `ARGON2_ITERATIONS` and `ARGON2_PARALLELISM`. The defaults are OWASP's
+/// recommended minimum.
 static ARGON2: LazyLock<Argon2<'static>> = LazyLock::new(|| {
-    let params = Params::new(4096, 3, 1, Some(32))
-        .expect("There is some error in setting up params for argon2");
+    let params = Params::new(
+        env_parse("ARGON2_MEMORY_KIB", 19 * 1024),
+        env_parse("ARGON2_ITERATIONS", 2),
+        env_parse("ARGON2_PARALLELISM", 1),
+        Some(32),
+    )
+    .unwrap_or_else(|e| panic!("Invalid Argon2 parameters: {}", e));
 
     Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params)
 });
 
+/// A hash no password is known to match, checked for sign-ins with an
+/// unknown email so they take as long as real ones.
+static DUMMY_HASH: LazyLock<String> = LazyLock::new(|| {
+    let mut password = [0u8; 32];
+    OsRng.fill_bytes(&mut password);
+    hash_password(password).expect("Failed to hash the dummy password")
+});
+
+/// Reads the Argon2 parameters so a bad configuration fails at startup
+/// rather than on the first sign-in.
+pub fn init() {
+    LazyLock::force(&ARGON2);
+    LazyLock::force(&DUMMY_HASH);
+}
+
 pub fn hash_password(password: impl AsRef<[u8]>) -> Result<String, ErrorMessage> {
     let password = password.as_ref();
     if password.is_empty() {
         return Err(ErrorMessage::EmptyPassword);
     }
 
-    if password.len() > MAX_PASSWORD_LENGTH {
+    if password.len() > MAX_PASSWORD_BYTES {
         return Err(ErrorMessage::ExceededMaxPasswordLength(password.len()));
     }
-
-    if password.len() < MIN_PASSWORD_LENGTH {
-        return Err(ErrorMessage::PasswordTooShort(password.len()));
-    }
     let salt = SaltString::generate(&mut OsRng);
     let hash = ARGON2
         .hash_password(password, &salt)
@@ -44,13 +82,9 @@ pub fn compare(password: impl AsRef<[u8]>, hashed_password: &str) -> Result<bool
         return Err(ErrorMessage::EmptyPassword);
     }
 
-    if password.len() > MAX_PASSWORD_LENGTH {
+    if password.len() > MAX_PASSWORD_BYTES {
         return Err(ErrorMessage::ExceededMaxPasswordLength(password.len()));
     }
-
-    if password.len() < MIN_PASSWORD_LENGTH {
-        return Err(ErrorMessage::PasswordTooShort(password.len()));
-    }
     let parsed_hash =
         PasswordHash::new(hashed_password).map_err(|_| ErrorMessage::InvalidHashFormat)?;
 
@@ -60,3 +94,244 @@ pub fn compare(password: impl AsRef<[u8]>, hashed_password: &str) -> Result<bool
         Err(_) => Err(ErrorMessage::HashingError),
     }
 }
+
+/// Whether a hash was made with a different algorithm or cost than new
+/// hashes get, and should be replaced the next time the password is known.
+pub fn needs_rehash(hashed_password: &str) -> bool {
+    let Ok(parsed_hash) = PasswordHash::new(hashed_password) else {
+        return false;
+    };
+    let Ok(params) = Params::try_from(&parsed_hash) else {
+        return true;
+    };
+
+    parsed_hash.algorithm != argon2::Algorithm::Argon2id.ident()
+        || parsed_hash.version != Some(argon2::Version::V0x13.into())
+        || params.m_cost() != ARGON2.params().m_cost()
+        || params.t_cost() != ARGON2.params().t_cost()
+        || params.p_cost() != ARGON2.params().p_cost()
+}
+
+/// Upper bounds, in seconds, of the queue wait histogram's buckets.
+pub const QUEUE_WAIT_BUCKETS: [f64; 10] =
+    [0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5];
+
+#[derive(Debug, Default)]
+struct PoolCounters {
+    /// Jobs whose wait fell in each bucket and no lower one.
+    queue_wait_buckets: [AtomicU64; QUEUE_WAIT_BUCKETS.len()],
+    queue_wait_count: AtomicU64,
+    queue_wait_sum_micros: AtomicU64,
+    rejected: AtomicU64,
+}
+
+/// A snapshot of the hashing pool's metrics, in the shape of a Prometheus
+/// histogram: `queue_wait_buckets[i]` counts jobs that waited at most
+/// `QUEUE_WAIT_BUCKETS[i]` seconds for a worker.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PoolMetrics {
+    pub queue_wait_buckets: [u64; QUEUE_WAIT_BUCKETS.len()],
+    pub queue_wait_count: u64,
+    pub queue_wait_sum_seconds: f64,
+    pub rejected: u64,
+    pub pending: usize,
+}
+
+/// Runs password hashing on Tokio's blocking pool so a burst of sign-ins
+/// cannot stall the async workers. At most `PASSWORD_HASHING_CONCURRENCY`
+/// hashes run at once and up to `PASSWORD_HASHING_QUEUE_SIZE` more wait for
+/// a slot; anything beyond that is turned away with 503.
+#[derive(Debug, Clone)]
+pub struct PasswordHasherPool {
+    permits: Arc<Semaphore>,
+    pending: Arc<AtomicUsize>,
+    max_pending: usize,
+    counters: Arc<PoolCounters>,
+}
+
+impl PasswordHasherPool {
+    pub fn init() -> Self {
+        let default_concurrency = thread::available_parallelism().map_or(1, |n| n.get());
+        let concurrency = env_parse("PASSWORD_HASHING_CONCURRENCY", default_concurrency);
+        let queue_size = env_parse("PASSWORD_HASHING_QUEUE_SIZE", 32);
+        if concurrency == 0 {
+            panic!("PASSWORD_HASHING_CONCURRENCY must be at least 1");
+        }
+
+        Self::new(concurrency, queue_size)
+    }
+
+    pub fn new(concurrency: usize, queue_size: usize) -> Self {
+        PasswordHasherPool {
+            permits: Arc::new(Semaphore::new(concurrency)),
+            pending: Arc::new(AtomicUsize::new(0)),
+            max_pending: concurrency + queue_size,
+            counters: Arc::new(PoolCounters::default()),
+        }
+    }
+
+    /// How long jobs have waited for a worker, and how many were turned
+    /// away, since startup.
+    pub fn metrics(&self) -> PoolMetrics {
+        let counters = &self.counters;
+        let mut queue_wait_buckets = [0; QUEUE_WAIT_BUCKETS.len()];
+        let mut cumulative = 0;
+        for (bucket, count) in queue_wait_buckets
+            .iter_mut()
+            .zip(&counters.queue_wait_buckets)
+        {
+            cumulative += count.load(Ordering::Relaxed);
+            *bucket = cumulative;
+        }
+
+        PoolMetrics {
+            queue_wait_buckets,
+            queue_wait_count: counters.queue_wait_count.load(Ordering::Relaxed),
+            queue_wait_sum_seconds: counters.queue_wait_sum_micros.load(Ordering::Relaxed) as f64
+                / 1_000_000.0,
+            rejected: counters.rejected.load(Ordering::Relaxed),
+            pending: self.pending.load(Ordering::Relaxed),
+        }
+    }
+
+    fn record_queue_wait(&self, wait: Duration) {
+        let counters = &self.counters;
+        let seconds = wait.as_secs_f64();
+        if let Some(bucket) = QUEUE_WAIT_BUCKETS.iter().position(|le| seconds <= *le) {
+            counters.queue_wait_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+        counters.queue_wait_count.fetch_add(1, Ordering::Relaxed);
+        counters
+            .queue_wait_sum_micros
+            .fetch_add(wait.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub async fn hash(&self, password: String) -> Result<Result<String, ErrorMessage>, HttpError> {
+        self.run(move || hash_password(password)).await
+    }
+
+    pub async fn compare(
+        &self,
+        password: String,
+        hashed_password: String,
+    ) -> Result<Result<bool, ErrorMessage>, HttpError> {
+        self.run(move || compare(password, &hashed_password)).await
+    }
+
+    /// Checks the password against a dummy hash and discards the result, to
+    /// spend the same time as `compare` when there is no real hash.
+    pub async fn compare_dummy(&self, password: String) -> Result<(), HttpError> {
+        self.run(move || compare(password, &DUMMY_HASH))
+            .await
+            .map(|_| ())
+    }
+
+    async fn run<T: Send + 'static>(
+        &self,
+        job: impl FnOnce() -> T + Send + 'static,
+    ) -> Result<T, HttpError> {
+        let pending = self.pending.fetch_add(1, Ordering::AcqRel) + 1;
+        let _pending = PendingGuard(&self.pending);
+        if pending > self.max_pending {
+            self.counters.rejected.fetch_add(1, Ordering::Relaxed);
+            tracing::warn!(pending, "password hashing queue full, rejecting request");
+            return Err(HttpError::service_unavailable(
+                ErrorMessage::ServerBusy.to_string(),
+                BUSY_RETRY_AFTER_SECONDS,
+            ));
+        }
+
+        let queued_at = Instant::now();
+        let permit = self
+            .permits
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(|e| HttpError::server_error(e.to_string()))?;
+        let queue_wait = queued_at.elapsed();
+        self.record_queue_wait(queue_wait);
+        tracing::debug!(
+            queue_wait_ms = queue_wait.as_secs_f64() * 1000.0,
+            pending,
+            "password hashing job started"
+        );
+
+        tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            job()
+        })
+        .await
+        .map_err(|e| HttpError::server_error(e.to_string()))
+    }
+}
+
+/// Leaves the queue count when a job finishes or its request is dropped.
+struct PendingGuard<'a>(&'a AtomicUsize);
+
+impl Drop for PendingGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn pool_metrics_count_waits_and_rejections() {
+        let pool = PasswordHasherPool::new(1, 0);
+
+        let slow = pool.run(|| thread::sleep(Duration::from_millis(200)));
+        let rejected = async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            pool.run(|| ()).await
+        };
+        let (slow, rejected) = tokio::join!(slow, rejected);
+        assert!(slow.is_ok());
+        assert_eq!(
+            rejected.unwrap_err().status,
+            axum::http::StatusCode::SERVICE_UNAVAILABLE
+        );
+
+        let metrics = pool.metrics();
+        assert_eq!(metrics.queue_wait_count, 1);
+        assert_eq!(metrics.rejected, 1);
+        assert_eq!(metrics.pending, 0);
+        assert_eq!(metrics.queue_wait_buckets[QUEUE_WAIT_BUCKETS.len() - 1], 1);
+    }
+
+    fn hash_with(algorithm: argon2::Algorithm, m_cost: u32, t_cost: u32) -> String {
+        let params = Params::new(m_cost, t_cost, 1, Some(32)).unwrap();
+        Argon2::new(algorithm, argon2::Version::V0x13, params)
+            .hash_password(b"password", &SaltString::generate(&mut OsRng))
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn needs_rehash_only_for_other_algorithms_and_costs() {
+        let current = hash_password("password").unwrap();
+        assert!(!needs_rehash(&current));
+
+        let params = ARGON2.params();
+        assert!(needs_rehash(&hash_with(
+            argon2::Algorithm::Argon2i,
+            params.m_cost(),
+            params.t_cost()
+        )));
+        assert!(needs_rehash(&hash_with(
+            argon2::Algorithm::Argon2id,
+            params.m_cost() / 2,
+            params.t_cost()
+        )));
+        assert!(needs_rehash(&hash_with(
+            argon2::Algorithm::Argon2id,
+            params.m_cost(),
+            params.t_cost() + 1
+        )));
+
+        // Hashes that cannot be parsed are left for `compare` to reject.
+        assert!(!needs_rehash("not a hash"));
+    }
+}
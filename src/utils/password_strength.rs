@@ -0,0 +1,152 @@
+use std::collections::HashSet;
+
+pub const MAX_SCORE: u8 = 4;
+
+/// Dictionary words and user inputs shorter than this are not matched.
+const MIN_WORD_LENGTH: usize = 4;
+
+/// Scores a password from 0 to 4 like zxcvbn: 0 falls to about a thousand
+/// guesses, 4 needs more than ten billion. This is a rough estimate that
+/// charges little for dictionary words, user inputs, repeats and sequences.
+pub fn score(password: &str, user_inputs: &[&str], dictionary: &HashSet<String>) -> u8 {
+    let log10_guesses =
+        estimate_bits(password, user_inputs, dictionary) * std::f64::consts::LOG10_2;
+    match log10_guesses {
+        g if g < 3.0 => 0,
+        g if g < 6.0 => 1,
+        g if g < 8.0 => 2,
+        g if g < 10.0 => 3,
+        _ => MAX_SCORE,
+    }
+}
+
+/// Undoes common character substitutions so `p4ssw0rd` matches `password`.
+fn unleet(c: char) -> char {
+    match c {
+        '4' | '@' => 'a',
+        '3' => 'e',
+        '1' | '!' => 'i',
+        '0' => 'o',
+        '5' | '$' => 's',
+        '7' => 't',
+        c => c,
+    }
+}
+
+/// The cheapest way to build the password from dictionary words, user
+/// inputs, years and single characters, in bits.
+fn estimate_bits(password: &str, user_inputs: &[&str], dictionary: &HashSet<String>) -> f64 {
+    let chars: Vec<char> = password.chars().collect();
+    let normalized: Vec<char> = chars
+        .iter()
+        .map(|c| unleet(c.to_lowercase().next().unwrap_or(*c)))
+        .collect();
+
+    let input_words: Vec<String> = user_inputs
+        .iter()
+        .flat_map(|input| input.split(|c: char| !c.is_alphanumeric()))
+        .map(str::to_lowercase)
+        .collect();
+    let words: Vec<Vec<char>> = input_words
+        .iter()
+        .chain(dictionary.iter())
+        .map(|word| word.chars().map(unleet).collect::<Vec<char>>())
+        .filter(|word| word.len() >= MIN_WORD_LENGTH)
+        .collect();
+
+    // A matched word costs about as much as picking it from the list, plus a
+    // bit for capitalisation, instead of the cost of its characters. Recent
+    // years are one of a small set of guesses.
+    let word_bits = ((dictionary.len() + input_words.len()) as f64 + 1.0).log2() + 1.0;
+    let year_bits = 140f64.log2();
+
+    let charset: f64 = [
+        (chars.iter().any(|c| c.is_ascii_lowercase()), 26.0),
+        (chars.iter().any(|c| c.is_ascii_uppercase()), 26.0),
+        (chars.iter().any(|c| c.is_ascii_digit()), 10.0),
+        (
+            chars.iter().any(|c| c.is_ascii_punctuation() || *c == ' '),
+            33.0,
+        ),
+        (chars.iter().any(|c| !c.is_ascii()), 100.0),
+    ]
+    .iter()
+    .filter(|(present, _)| *present)
+    .map(|(_, size)| size)
+    .sum();
+    let char_bits = charset.max(2.0).log2();
+
+    // `best[end]` is the cheapest way to build the first `end` characters.
+    let mut best = vec![0.0; chars.len() + 1];
+    for end in 1..=chars.len() {
+        // Repeated and consecutive characters (`aaaa`, `1234`) add almost
+        // nothing.
+        let predictable = end > 1 && (chars[end - 1] as u32).abs_diff(chars[end - 2] as u32) <= 1;
+        let mut bits = best[end - 1] + if predictable { 1.0 } else { char_bits };
+
+        for word in &words {
+            if word.len() <= end && normalized[end - word.len()..end] == word[..] {
+                bits = f64::min(bits, best[end - word.len()] + word_bits);
+            }
+        }
+
+        if end >= 4 {
+            let year: String = chars[end - 4..end].iter().collect();
+            if year
+                .parse::<u32>()
+                .is_ok_and(|year| (1900..=2039).contains(&year))
+            {
+                bits = f64::min(bits, best[end - 4] + year_bits);
+            }
+        }
+
+        best[end] = bits;
+    }
+
+    best[chars.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dictionary() -> HashSet<String> {
+        [
+            "password", "monkey", "dragon", "correct", "horse", "battery", "staple",
+        ]
+        .into_iter()
+        .map(str::to_string)
+        .collect()
+    }
+
+    #[test]
+    fn dictionary_words_and_sequences_score_low() {
+        let dictionary = dictionary();
+
+        assert_eq!(score("password", &[], &dictionary), 0);
+        assert_eq!(score("P4ssw0rd", &[], &dictionary), 0);
+        assert!(score("12345678", &[], &dictionary) <= 1);
+        assert!(score("aaaaaaaa", &[], &dictionary) <= 1);
+        assert!(score("monkey1990", &[], &dictionary) <= 1);
+    }
+
+    #[test]
+    fn user_inputs_count_as_words() {
+        let dictionary = dictionary();
+        let inputs = ["Janet Morrison", "janet@example.com"];
+
+        assert_eq!(score("MorrisonJanet!", &[], &dictionary), MAX_SCORE);
+        assert!(score("MorrisonJanet!", &inputs, &dictionary) <= 1);
+    }
+
+    #[test]
+    fn random_passwords_score_high() {
+        let dictionary = dictionary();
+
+        assert_eq!(score("tQ8#vL2x!mZr", &[], &dictionary), MAX_SCORE);
+        assert_eq!(
+            score("plum-gadget-oxide-tundra", &[], &dictionary),
+            MAX_SCORE
+        );
+    }
+}
@@ -0,0 +1,127 @@
+use std::{str::FromStr, time::Duration};
+
+use chrono::{DateTime, Utc};
+
+/// A token bucket: up to `capacity` requests in a burst, then one more every
+/// `refill_every`.
+#[derive(Debug, Clone, Copy)]
+pub struct BucketPolicy {
+    pub capacity: u32,
+    pub refill_every: Duration,
+}
+
+impl BucketPolicy {
+    pub const fn new(capacity: u32, refill_every: Duration) -> Self {
+        BucketPolicy {
+            capacity,
+            refill_every,
+        }
+    }
+
+    /// Takes a token from a bucket that held `tokens` at `updated_at`. Returns
+    /// the new level and, if the bucket was empty, the seconds until the next
+    /// token.
+    pub fn take(
+        &self,
+        tokens: f64,
+        updated_at: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> (f64, Option<u64>) {
+        let refill_seconds = self.refill_every.as_secs_f64();
+        let elapsed = (now - updated_at)
+            .to_std()
+            .unwrap_or_default()
+            .as_secs_f64();
+        let tokens = (tokens + elapsed / refill_seconds).min(f64::from(self.capacity));
+
+        if tokens >= 1.0 {
+            (tokens - 1.0, None)
+        } else {
+            let wait = ((1.0 - tokens) * refill_seconds).ceil().max(1.0);
+            (tokens, Some(wait as u64))
+        }
+    }
+
+    /// When a bucket left with `tokens` is full again and can be forgotten.
+    pub fn full_at(&self, tokens: f64, now: DateTime<Utc>) -> DateTime<Utc> {
+        let missing = (f64::from(self.capacity) - tokens).max(0.0);
+        let seconds = missing * self.refill_every.as_secs_f64();
+        now + chrono::Duration::milliseconds((seconds * 1000.0).ceil() as i64)
+    }
+}
+
+/// Parses `<capacity>/<seconds>`, as in `10/360` for a burst of ten requests
+/// and one more every six minutes.
+impl FromStr for BucketPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("expected <capacity>/<seconds>, got {}", s);
+        let (capacity, seconds) = s.split_once('/').ok_or_else(invalid)?;
+        let capacity: u32 = capacity.trim().parse().map_err(|_| invalid())?;
+        let seconds: u64 = seconds.trim().parse().map_err(|_| invalid())?;
+        if capacity == 0 || seconds == 0 {
+            return Err(invalid());
+        }
+
+        Ok(BucketPolicy::new(capacity, Duration::from_secs(seconds)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_allows_a_burst_then_refills() {
+        let policy = BucketPolicy::new(3, Duration::from_secs(10));
+        let start = Utc::now();
+
+        let mut tokens = f64::from(policy.capacity);
+        for _ in 0..3 {
+            let (left, retry_after) = policy.take(tokens, start, start);
+            assert_eq!(retry_after, None);
+            tokens = left;
+        }
+
+        let (left, retry_after) = policy.take(tokens, start, start);
+        assert_eq!(retry_after, Some(10));
+        assert_eq!(left, 0.0);
+
+        let later = start + chrono::Duration::seconds(4);
+        assert_eq!(policy.take(tokens, start, later).1, Some(6));
+
+        let refilled = start + chrono::Duration::seconds(10);
+        assert_eq!(policy.take(tokens, start, refilled), (0.0, None));
+    }
+
+    #[test]
+    fn bucket_never_holds_more_than_its_capacity() {
+        let policy = BucketPolicy::new(2, Duration::from_secs(1));
+        let start = Utc::now();
+        let much_later = start + chrono::Duration::hours(1);
+
+        assert_eq!(policy.take(0.0, start, much_later), (1.0, None));
+    }
+
+    #[test]
+    fn bucket_is_full_after_the_missing_tokens_refill() {
+        let policy = BucketPolicy::new(5, Duration::from_secs(2));
+        let now = Utc::now();
+
+        assert_eq!(policy.full_at(2.0, now), now + chrono::Duration::seconds(6));
+        assert_eq!(policy.full_at(5.0, now), now);
+    }
+
+    #[test]
+    fn bucket_policy_parses_capacity_and_seconds() {
+        let policy: BucketPolicy = "10/360".parse().unwrap();
+        assert_eq!(policy.capacity, 10);
+        assert_eq!(policy.refill_every, Duration::from_secs(360));
+
+        assert!("10".parse::<BucketPolicy>().is_err());
+        assert!("0/60".parse::<BucketPolicy>().is_err());
+        assert!("10/0".parse::<BucketPolicy>().is_err());
+        assert!("ten/60".parse::<BucketPolicy>().is_err());
+    }
+}
@@ -1,53 +1,227 @@
-use std::sync::LazyLock;
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use hmac::{Hmac, Mac};
+use jsonwebtoken::{Header, Validation};
+use rand::{Rng, RngCore, rngs::OsRng};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
 
-use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
-use serde::{Deserialize, Serialize};
+use crate::{
+    config::jwt_config::JwtConfig,
+    error::{ErrorMessage, HttpError},
+};
 
-use crate::error::{ErrorMessage, HttpError};
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenClaims {
+    pub sub: String,
+    pub sid: String,
+    pub jti: String,
+    pub iss: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aud: Option<String>,
+    /// Seconds since the epoch to the microsecond, so a token issued just
+    /// before a password change is told apart from one issued just after.
+    pub iat: f64,
+    pub exp: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<WorkspaceClaims>,
+}
+
+/// Snapshot of a member's access to one workspace, carried by workspace
+/// tokens. `version` is the workspace's `permissions_version` at issue time.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkspaceClaims {
+    pub id: Uuid,
+    pub role_id: Uuid,
+    pub permissions: Vec<String>,
+    pub version: i64,
+}
+
+/// Purpose of the short-lived token returned by `login` when a second factor
+/// is still required.
+pub const TWO_FACTOR_CHALLENGE: &str = "two_factor";
 
+/// Claims of single-purpose tokens that prove one step of a multi-step flow.
+/// They lack `sid` and `jti`, so they are never accepted as access tokens.
 #[derive(Debug, Serialize, Deserialize)]
-pub struct TokenClaims {
+pub struct ChallengeClaims {
     pub sub: String,
+    pub purpose: String,
+    pub iss: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aud: Option<String>,
     pub iat: usize,
     pub exp: usize,
 }
 
-const DEFAULT_ALGORITHM: Algorithm = Algorithm::HS256;
-static DEFAULT_TOKEN_VALIDATION: LazyLock<Validation> =
-    LazyLock::new(|| Validation::new(DEFAULT_ALGORITHM));
-static HEADER: LazyLock<Header> = LazyLock::new(|| Header::new(DEFAULT_ALGORITHM));
+fn encode<T: Serialize>(
+    claims: &T,
+    jwt_config: &JwtConfig,
+) -> Result<String, jsonwebtoken::errors::Error> {
+    let mut header = Header::new(jwt_config.algorithm);
+    header.kid = jwt_config.kid.clone();
+
+    jsonwebtoken::encode(&header, claims, jwt_config.encoding_key())
+}
+
+/// Verifies the signature with the key named by the token's `kid` and checks
+/// `exp`, `iss` and, when configured, `aud`.
+fn decode<T: DeserializeOwned>(token: &str, jwt_config: &JwtConfig) -> Result<T, HttpError> {
+    let invalid_token = || HttpError::unauthorized(ErrorMessage::InvalidToken.to_string());
+
+    let header = jsonwebtoken::decode_header(token).map_err(|_| invalid_token())?;
+    let key = jwt_config
+        .verification_key(header.kid.as_deref(), header.alg)
+        .ok_or_else(invalid_token)?;
+
+    let mut validation = Validation::new(key.algorithm);
+    validation.set_issuer(&[&jwt_config.issuer]);
+    match &jwt_config.audience {
+        Some(audience) => validation.set_audience(&[audience]),
+        None => validation.validate_aud = false,
+    }
+
+    jsonwebtoken::decode::<T>(token, &key.key, &validation)
+        .map(|data| data.claims)
+        .map_err(|_| invalid_token())
+}
 
 pub fn create_token(
     user_id: &str,
-    secret: &[u8],
+    session_id: &str,
+    jwt_config: &JwtConfig,
     expires_in_seconds: i64,
+) -> Result<String, jsonwebtoken::errors::Error> {
+    let now = chrono::Utc::now();
+    let expires_at = (now + chrono::Duration::seconds(expires_in_seconds)).timestamp() as usize;
+
+    create_access_token(user_id, session_id, None, jwt_config, expires_at)
+}
+
+/// Access token for one workspace. It authenticates like a user token and
+/// also lets the workspace middleware authorize without loading the role.
+pub fn create_workspace_token(
+    user_id: &str,
+    session_id: &str,
+    workspace: WorkspaceClaims,
+    jwt_config: &JwtConfig,
+    expires_at: usize,
+) -> Result<String, jsonwebtoken::errors::Error> {
+    create_access_token(user_id, session_id, Some(workspace), jwt_config, expires_at)
+}
+
+fn create_access_token(
+    user_id: &str,
+    session_id: &str,
+    workspace: Option<WorkspaceClaims>,
+    jwt_config: &JwtConfig,
+    expires_at: usize,
 ) -> Result<String, jsonwebtoken::errors::Error> {
     if user_id.is_empty() {
         return Err(jsonwebtoken::errors::ErrorKind::InvalidSubject.into());
     }
 
+    let claims = TokenClaims {
+        sub: user_id.to_string(),
+        sid: session_id.to_string(),
+        jti: Uuid::new_v4().to_string(),
+        iss: jwt_config.issuer.clone(),
+        aud: jwt_config.audience.clone(),
+        iat: epoch_seconds(chrono::Utc::now()),
+        exp: expires_at,
+        workspace,
+    };
+
+    encode(&claims, jwt_config)
+}
+
+/// A timestamp as fractional seconds since the epoch, like `iat`.
+pub fn epoch_seconds(time: chrono::DateTime<chrono::Utc>) -> f64 {
+    time.timestamp_micros() as f64 / 1_000_000.0
+}
+
+pub fn decode_token(token: &str, jwt_config: &JwtConfig) -> Result<TokenClaims, HttpError> {
+    decode(token, jwt_config)
+}
+
+pub fn create_challenge_token(
+    user_id: &str,
+    purpose: &str,
+    jwt_config: &JwtConfig,
+    expires_in_seconds: i64,
+) -> Result<String, jsonwebtoken::errors::Error> {
     let now = chrono::Utc::now();
 
-    let claims = TokenClaims {
+    let claims = ChallengeClaims {
         sub: user_id.to_string(),
+        purpose: purpose.to_string(),
+        iss: jwt_config.issuer.clone(),
+        aud: jwt_config.audience.clone(),
         iat: now.timestamp() as usize,
         exp: (now + chrono::Duration::seconds(expires_in_seconds)).timestamp() as usize,
     };
 
-    jsonwebtoken::encode(&HEADER, &claims, &EncodingKey::from_secret(secret)).map_err(|e| e.into())
+    encode(&claims, jwt_config)
 }
 
-pub fn decode_token(token: &str, secret: &[u8]) -> Result<String, HttpError> {
-    let decode = jsonwebtoken::decode::<TokenClaims>(
-        token,
-        &DecodingKey::from_secret(secret),
-        &DEFAULT_TOKEN_VALIDATION,
-    );
+pub fn decode_challenge_token(
+    token: &str,
+    purpose: &str,
+    jwt_config: &JwtConfig,
+) -> Result<String, HttpError> {
+    let claims: ChallengeClaims = decode(token, jwt_config)?;
 
-    match decode {
-        Ok(token) => Ok(token.claims.sub),
-        Err(_) => Err(HttpError::unauthorized(
+    if claims.purpose != purpose {
+        return Err(HttpError::unauthorized(
             ErrorMessage::InvalidToken.to_string(),
-        )),
+        ));
     }
+
+    Ok(claims.sub)
+}
+
+/// Generates a random, URL-safe opaque token (256 bits of entropy).
+pub fn generate_opaque_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Start of every personal access token, so leaked tokens are easy to spot.
+pub const PERSONAL_ACCESS_TOKEN_PREFIX: &str = "wkpat_";
+
+pub fn generate_personal_access_token() -> String {
+    format!(
+        "{}{}",
+        PERSONAL_ACCESS_TOKEN_PREFIX,
+        generate_opaque_token()
+    )
+}
+
+/// Start of every service account API key.
+pub const SERVICE_ACCOUNT_KEY_PREFIX: &str = "wksa_";
+
+pub fn generate_service_account_key() -> String {
+    format!("{}{}", SERVICE_ACCOUNT_KEY_PREFIX, generate_opaque_token())
+}
+
+/// Generates a random numeric code such as a 6-digit sign-in code.
+pub fn generate_numeric_code(digits: u32) -> String {
+    let code = OsRng.gen_range(0..10u32.pow(digits));
+    format!("{:0width$}", code, width = digits as usize)
+}
+
+/// Hex encoded SHA-256 of an opaque token. Only this value is persisted.
+pub fn hash_opaque_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Hex encoded HMAC-SHA256 of `value` keyed with `HMAC_SECRET`. Short codes
+/// are stored this way, since a plain hash of six digits is trivial to
+/// reverse.
+pub fn hmac_sha256(secret: &str, value: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(value.as_bytes());
+    format!("{:x}", mac.finalize().into_bytes())
 }
@@ -0,0 +1,115 @@
+use rand::{Rng, distributions::Alphanumeric, rngs::OsRng};
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use crate::utils::token;
+
+const TOTP_DIGITS: usize = 6;
+const TOTP_STEP: u64 = 30;
+const RECOVERY_CODE_COUNT: usize = 10;
+
+/// Generates a new base32 encoded TOTP secret.
+pub fn generate_secret() -> String {
+    match Secret::generate_secret().to_encoded() {
+        Secret::Encoded(secret) => secret,
+        Secret::Raw(_) => unreachable!("to_encoded always returns an encoded secret"),
+    }
+}
+
+fn build_totp(secret: &str, issuer: &str, account_name: &str) -> Option<TOTP> {
+    let secret = Secret::Encoded(secret.to_string()).to_bytes().ok()?;
+
+    TOTP::new(
+        Algorithm::SHA1,
+        TOTP_DIGITS,
+        1,
+        TOTP_STEP,
+        secret,
+        Some(issuer.replace(':', "")),
+        account_name.replace(':', ""),
+    )
+    .ok()
+}
+
+/// `otpauth://` URI that authenticator apps import (usually as a QR code).
+pub fn otpauth_url(secret: &str, issuer: &str, account_name: &str) -> Option<String> {
+    build_totp(secret, issuer, account_name).map(|totp| totp.get_url())
+}
+
+/// Checks `code` against the previous, current and next time step and returns
+/// the matching step. Steps at or before `last_used_step` are rejected so a
+/// code cannot be replayed.
+pub fn verify_code(secret: &str, code: &str, last_used_step: Option<i64>) -> Option<i64> {
+    let totp = build_totp(secret, "", "")?;
+    let code = code.trim();
+    let current_step = (chrono::Utc::now().timestamp() as u64) / TOTP_STEP;
+
+    [current_step - 1, current_step, current_step + 1]
+        .into_iter()
+        .map(|step| step as i64)
+        .filter(|step| last_used_step.is_none_or(|last| *step > last))
+        .find(|step| totp.generate(*step as u64 * TOTP_STEP) == code)
+}
+
+/// The code an authenticator app shows for `secret` right now.
+#[cfg(test)]
+pub fn current_code(secret: &str) -> String {
+    build_totp(secret, "", "")
+        .and_then(|totp| totp.generate_current().ok())
+        .expect("valid TOTP secret")
+}
+
+/// Generates a fresh set of one-time recovery codes in `xxxxx-xxxxx` form.
+pub fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let code: String = OsRng
+                .sample_iter(&Alphanumeric)
+                .take(10)
+                .map(|c| char::from(c).to_ascii_lowercase())
+                .collect();
+            format!("{}-{}", &code[..5], &code[5..])
+        })
+        .collect()
+}
+
+/// Recovery codes are stored hashed; dashes, whitespace and case are ignored.
+pub fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    token::hash_opaque_token(&normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn code_cannot_be_used_twice() {
+        let secret = generate_secret();
+        let code = current_code(&secret);
+
+        let step = verify_code(&secret, &code, None).expect("fresh code is accepted");
+        assert_eq!(verify_code(&secret, &code, Some(step)), None);
+        assert_eq!(verify_code(&secret, &code, Some(step - 1)), Some(step));
+    }
+
+    #[test]
+    fn wrong_code_is_rejected() {
+        let secret = generate_secret();
+        let code = current_code(&secret);
+        let wrong = format!("{:06}", (code.parse::<u32>().unwrap() + 1) % 1_000_000);
+
+        assert_eq!(verify_code(&secret, &wrong, None), None);
+    }
+
+    #[test]
+    fn recovery_codes_ignore_formatting() {
+        assert_eq!(
+            hash_recovery_code("abcde-12345"),
+            hash_recovery_code(" ABCDE 12345 ")
+        );
+    }
+}
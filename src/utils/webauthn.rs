@@ -0,0 +1,353 @@
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use ciborium::Value;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::error::{ErrorMessage, HttpError};
+
+pub const REGISTRATION_CEREMONY: &str = "registration";
+pub const AUTHENTICATION_CEREMONY: &str = "authentication";
+
+/// COSE algorithm identifiers accepted for new passkeys, in order of preference.
+pub const ES256: i32 = -7;
+pub const EDDSA: i32 = -8;
+pub const RS256: i32 = -257;
+pub const SUPPORTED_ALGORITHMS: [i32; 3] = [ES256, EDDSA, RS256];
+
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_ATTESTED_CREDENTIAL_DATA: u8 = 0x40;
+
+const MAX_CREDENTIAL_ID_LENGTH: usize = 1023;
+const MIN_RSA_KEY_BYTES: usize = 256;
+
+/// The relying party a ceremony is checked against.
+pub struct RelyingParty<'a> {
+    pub id: &'a str,
+    pub origin: &'a str,
+}
+
+/// A credential that passed the registration ceremony and can be stored.
+pub struct VerifiedRegistration {
+    pub credential_id: String,
+    pub public_key: Vec<u8>,
+    pub algorithm: i32,
+    pub sign_count: u32,
+    pub aaguid: Uuid,
+}
+
+#[derive(Deserialize)]
+struct CollectedClientData {
+    #[serde(rename = "type")]
+    ceremony_type: String,
+    challenge: String,
+    origin: String,
+}
+
+struct AuthenticatorData {
+    rp_id_hash: [u8; 32],
+    flags: u8,
+    sign_count: u32,
+    attested_credential: Option<AttestedCredential>,
+}
+
+struct AttestedCredential {
+    aaguid: [u8; 16],
+    credential_id: Vec<u8>,
+    public_key: Vec<u8>,
+}
+
+fn verification_failed() -> HttpError {
+    HttpError::bad_request(ErrorMessage::PasskeyVerificationFailed.to_string())
+}
+
+/// Decodes base64url, tolerating the padding some clients still send.
+pub fn decode_base64url(value: &str) -> Result<Vec<u8>, HttpError> {
+    URL_SAFE_NO_PAD
+        .decode(value.trim_end_matches('='))
+        .map_err(|_| verification_failed())
+}
+
+pub fn encode_base64url(value: &[u8]) -> String {
+    URL_SAFE_NO_PAD.encode(value)
+}
+
+/// The WebAuthn user handle for an account is the raw bytes of its id.
+pub fn user_handle(user_id: Uuid) -> String {
+    encode_base64url(user_id.as_bytes())
+}
+
+fn verify_client_data(
+    client_data_json: &[u8],
+    expected_type: &str,
+    expected_challenge: &str,
+    relying_party: &RelyingParty,
+) -> Result<(), HttpError> {
+    let client_data: CollectedClientData =
+        serde_json::from_slice(client_data_json).map_err(|_| verification_failed())?;
+
+    if client_data.ceremony_type != expected_type
+        || decode_base64url(&client_data.challenge)? != decode_base64url(expected_challenge)?
+        || client_data.origin != relying_party.origin
+    {
+        return Err(verification_failed());
+    }
+
+    Ok(())
+}
+
+fn parse_authenticator_data(data: &[u8]) -> Result<AuthenticatorData, HttpError> {
+    if data.len() < 37 {
+        return Err(verification_failed());
+    }
+
+    let mut rp_id_hash = [0u8; 32];
+    rp_id_hash.copy_from_slice(&data[..32]);
+    let flags = data[32];
+    let sign_count = u32::from_be_bytes([data[33], data[34], data[35], data[36]]);
+
+    let attested_credential = if flags & FLAG_ATTESTED_CREDENTIAL_DATA != 0 {
+        let rest = &data[37..];
+        if rest.len() < 18 {
+            return Err(verification_failed());
+        }
+
+        let mut aaguid = [0u8; 16];
+        aaguid.copy_from_slice(&rest[..16]);
+        let id_length = u16::from_be_bytes([rest[16], rest[17]]) as usize;
+        let rest = &rest[18..];
+        if id_length > MAX_CREDENTIAL_ID_LENGTH || rest.len() < id_length {
+            return Err(verification_failed());
+        }
+
+        let credential_id = rest[..id_length].to_vec();
+        let key_bytes = &rest[id_length..];
+
+        // The COSE key is followed by optional extension data, so decode one
+        // CBOR item and keep exactly the bytes it occupied.
+        let mut reader = key_bytes;
+        let _: Value = ciborium::from_reader(&mut reader).map_err(|_| verification_failed())?;
+        let public_key = key_bytes[..key_bytes.len() - reader.len()].to_vec();
+
+        Some(AttestedCredential {
+            aaguid,
+            credential_id,
+            public_key,
+        })
+    } else {
+        None
+    };
+
+    Ok(AuthenticatorData {
+        rp_id_hash,
+        flags,
+        sign_count,
+        attested_credential,
+    })
+}
+
+fn check_authenticator_data(
+    authenticator_data: &AuthenticatorData,
+    relying_party: &RelyingParty,
+) -> Result<(), HttpError> {
+    let expected_hash: [u8; 32] = Sha256::digest(relying_party.id.as_bytes()).into();
+    let required_flags = FLAG_USER_PRESENT | FLAG_USER_VERIFIED;
+
+    if authenticator_data.rp_id_hash != expected_hash
+        || authenticator_data.flags & required_flags != required_flags
+    {
+        return Err(verification_failed());
+    }
+
+    Ok(())
+}
+
+fn map_get(map: &[(Value, Value)], label: i64) -> Option<&Value> {
+    map.iter()
+        .find(|(key, _)| {
+            key.as_integer()
+                .is_some_and(|k| i128::from(k) == label as i128)
+        })
+        .map(|(_, value)| value)
+}
+
+fn map_get_bytes(map: &[(Value, Value)], label: i64) -> Result<&[u8], HttpError> {
+    map_get(map, label)
+        .and_then(Value::as_bytes)
+        .map(Vec::as_slice)
+        .ok_or_else(verification_failed)
+}
+
+fn map_get_int(map: &[(Value, Value)], label: i64) -> Result<i128, HttpError> {
+    map_get(map, label)
+        .and_then(Value::as_integer)
+        .map(i128::from)
+        .ok_or_else(verification_failed)
+}
+
+enum PublicKey {
+    Es256(p256::ecdsa::VerifyingKey),
+    EdDsa(ed25519_dalek::VerifyingKey),
+    Rs256(rsa::RsaPublicKey),
+}
+
+impl PublicKey {
+    /// Parses a COSE_Key as stored in `webauthn_credentials.public_key`.
+    fn from_cose(cose_key: &[u8]) -> Result<(Self, i32), HttpError> {
+        let value: Value = ciborium::from_reader(cose_key).map_err(|_| verification_failed())?;
+        let map = value.as_map().ok_or_else(verification_failed)?;
+
+        let key_type = map_get_int(map, 1)?;
+        let algorithm = i32::try_from(map_get_int(map, 3)?).map_err(|_| verification_failed())?;
+
+        let key = match (key_type, algorithm) {
+            // EC2 key on P-256
+            (2, ES256) => {
+                let x = map_get_bytes(map, -2)?;
+                let y = map_get_bytes(map, -3)?;
+                if map_get_int(map, -1)? != 1 || x.len() != 32 || y.len() != 32 {
+                    return Err(verification_failed());
+                }
+                let point = p256::EncodedPoint::from_affine_coordinates(
+                    p256::FieldBytes::from_slice(x),
+                    p256::FieldBytes::from_slice(y),
+                    false,
+                );
+                p256::ecdsa::VerifyingKey::from_encoded_point(&point)
+                    .map(PublicKey::Es256)
+                    .map_err(|_| verification_failed())?
+            }
+            // OKP key on Ed25519
+            (1, EDDSA) => {
+                let x: [u8; 32] = map_get_bytes(map, -2)?
+                    .try_into()
+                    .map_err(|_| verification_failed())?;
+                if map_get_int(map, -1)? != 6 {
+                    return Err(verification_failed());
+                }
+                ed25519_dalek::VerifyingKey::from_bytes(&x)
+                    .map(PublicKey::EdDsa)
+                    .map_err(|_| verification_failed())?
+            }
+            (3, RS256) => {
+                let n = rsa::BigUint::from_bytes_be(map_get_bytes(map, -1)?);
+                let e = rsa::BigUint::from_bytes_be(map_get_bytes(map, -2)?);
+                let key = rsa::RsaPublicKey::new(n, e).map_err(|_| verification_failed())?;
+                if rsa::traits::PublicKeyParts::size(&key) < MIN_RSA_KEY_BYTES {
+                    return Err(verification_failed());
+                }
+                PublicKey::Rs256(key)
+            }
+            _ => return Err(verification_failed()),
+        };
+
+        Ok((key, algorithm))
+    }
+
+    fn verify(&self, message: &[u8], signature: &[u8]) -> Result<(), HttpError> {
+        use p256::ecdsa::signature::Verifier;
+
+        let verified = match self {
+            PublicKey::Es256(key) => p256::ecdsa::Signature::from_der(signature)
+                .is_ok_and(|signature| key.verify(message, &signature).is_ok()),
+            PublicKey::EdDsa(key) => ed25519_dalek::Signature::from_slice(signature)
+                .is_ok_and(|signature| key.verify_strict(message, &signature).is_ok()),
+            PublicKey::Rs256(key) => {
+                let key = rsa::pkcs1v15::VerifyingKey::<Sha256>::new(key.clone());
+                rsa::pkcs1v15::Signature::try_from(signature)
+                    .is_ok_and(|signature| key.verify(message, &signature).is_ok())
+            }
+        };
+
+        if verified {
+            Ok(())
+        } else {
+            Err(verification_failed())
+        }
+    }
+}
+
+/// Verifies a `navigator.credentials.create()` response. Attestation
+/// statements are not checked since the options ask for `none`.
+pub fn verify_registration(
+    relying_party: &RelyingParty,
+    challenge: &str,
+    raw_id: &str,
+    client_data_json: &str,
+    attestation_object: &str,
+) -> Result<VerifiedRegistration, HttpError> {
+    let client_data_json = decode_base64url(client_data_json)?;
+    verify_client_data(
+        &client_data_json,
+        "webauthn.create",
+        challenge,
+        relying_party,
+    )?;
+
+    let attestation: Value =
+        ciborium::from_reader(decode_base64url(attestation_object)?.as_slice())
+            .map_err(|_| verification_failed())?;
+    let auth_data = attestation
+        .as_map()
+        .and_then(|map| {
+            map.iter()
+                .find(|(key, _)| key.as_text() == Some("authData"))
+                .and_then(|(_, value)| value.as_bytes())
+        })
+        .ok_or_else(verification_failed)?;
+
+    let authenticator_data = parse_authenticator_data(auth_data)?;
+    check_authenticator_data(&authenticator_data, relying_party)?;
+
+    let credential = authenticator_data
+        .attested_credential
+        .ok_or_else(verification_failed)?;
+    if credential.credential_id != decode_base64url(raw_id)? {
+        return Err(verification_failed());
+    }
+
+    let (_, algorithm) = PublicKey::from_cose(&credential.public_key)?;
+
+    Ok(VerifiedRegistration {
+        credential_id: encode_base64url(&credential.credential_id),
+        public_key: credential.public_key,
+        algorithm,
+        sign_count: authenticator_data.sign_count,
+        aaguid: Uuid::from_bytes(credential.aaguid),
+    })
+}
+
+/// Verifies a `navigator.credentials.get()` response against a stored
+/// passkey and returns the authenticator's new signature counter.
+pub fn verify_authentication(
+    relying_party: &RelyingParty,
+    challenge: &str,
+    client_data_json: &str,
+    authenticator_data: &str,
+    signature: &str,
+    public_key: &[u8],
+    stored_sign_count: i64,
+) -> Result<u32, HttpError> {
+    let client_data_json = decode_base64url(client_data_json)?;
+    verify_client_data(&client_data_json, "webauthn.get", challenge, relying_party)?;
+
+    let authenticator_data_bytes = decode_base64url(authenticator_data)?;
+    let authenticator_data = parse_authenticator_data(&authenticator_data_bytes)?;
+    check_authenticator_data(&authenticator_data, relying_party)?;
+
+    let mut signed_data = authenticator_data_bytes;
+    signed_data.extend_from_slice(&Sha256::digest(&client_data_json));
+
+    let (key, _) = PublicKey::from_cose(public_key)?;
+    key.verify(&signed_data, &decode_base64url(signature)?)?;
+
+    // Authenticators that keep a counter must always increase it; a counter
+    // that goes backwards suggests the credential was cloned.
+    let sign_count = authenticator_data.sign_count;
+    if (sign_count != 0 || stored_sign_count != 0) && i64::from(sign_count) <= stored_sign_count {
+        return Err(verification_failed());
+    }
+
+    Ok(sign_count)
+}